@@ -0,0 +1,24 @@
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::prelude::PermissionsExt;
+use std::path::Path;
+
+/// Write a file by writing a temporary file in the same directory and
+/// renaming it, so that the file is never left truncated
+pub(crate) fn write(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let path = path.as_ref();
+    let dir = match path.parent() {
+        Some(d) if !d.as_os_str().is_empty() => d,
+        _ => Path::new("."),
+    };
+    let mut tmp = tempfile::Builder::new()
+        .prefix(".icemelter-")
+        .tempfile_in(dir)?;
+    tmp.write_all(contents.as_ref())?;
+    // Temporary files are only readable by the owner
+    tmp.as_file()
+        .set_permissions(fs::Permissions::from_mode(0o644))?;
+    tmp.as_file().sync_all()?;
+    tmp.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
@@ -0,0 +1,162 @@
+//! Getting code from files attached to issues, e.g., `repro.rs` or
+//! `repro.zip`
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+
+use crate::flatten;
+use crate::http;
+use crate::temp;
+
+/// Larger attachments probably aren't reproductions
+const MAX_SIZE: usize = 1024 * 1024;
+
+/// Nor do archives that are larger than this once unpacked
+const MAX_UNPACKED_SIZE: u64 = 16 * 1024 * 1024;
+
+const ARCHIVES: &[&str] = &[".zip", ".tar.gz", ".tgz", ".tar"];
+
+/// Crate roots, in the order they're preferred within an archive
+const ROOTS: &[&str] = &["src/main.rs", "src/lib.rs", "main.rs", "lib.rs"];
+
+fn is_archive(name: &str) -> bool {
+    ARCHIVES.iter().any(|ext| name.ends_with(ext))
+}
+
+/// Links to attached Rust files and archives in Markdown, in order
+pub(crate) fn links(markdown: &str) -> Vec<String> {
+    let rx = Regex::new(
+        r#"https://github\.com/(?:user-attachments/files|[\w.-]+/[\w.-]+/files)/\d+/[^\s)\]>"'`]+"#,
+    )
+    .unwrap();
+    let mut links = Vec::new();
+    for m in rx.find_iter(markdown) {
+        let link = m.as_str().to_string();
+        let name = link.to_lowercase();
+        // GitHub only accepts `.rs` files renamed to `.rs.txt`
+        let wanted = name.ends_with(".rs") || name.ends_with(".rs.txt") || is_archive(&name);
+        if wanted && !links.contains(&link) {
+            links.push(link);
+        }
+    }
+    links
+}
+
+/// The crate root in an unpacked archive, ignoring symlinks (which may point
+/// anywhere)
+fn root(dir: &Path) -> Result<PathBuf> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(d) = dirs.pop() {
+        for entry in fs::read_dir(&d).with_context(|| format!("Failed to read {}", d.display()))? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            let path = entry.path();
+            if file_type.is_dir() {
+                if !path.ends_with("target") {
+                    dirs.push(path);
+                }
+            } else if file_type.is_file() && path.extension().is_some_and(|e| e == "rs") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    // Fewest components first, archives often have a top-level directory
+    files.sort_by_key(|f| f.components().count());
+    if files.len() == 1 {
+        return Ok(files.remove(0));
+    }
+    for r in ROOTS {
+        if let Some(f) = files.iter().find(|f| f.ends_with(r)) {
+            return Ok(f.clone());
+        }
+    }
+    match files.into_iter().next() {
+        Some(f) => Ok(f),
+        None => bail!("No Rust files in the archive"),
+    }
+}
+
+/// The size of the files in an archive, up to just over
+/// [`MAX_UNPACKED_SIZE`], without unpacking it
+fn unpacked_size(name: &str, archive: &Path) -> Result<u64> {
+    let mut cmd = if name.ends_with(".zip") {
+        let mut cmd = Command::new("unzip");
+        cmd.arg("-p").arg(archive);
+        cmd
+    } else if name.ends_with(".tar") {
+        return Ok(fs::metadata(archive)?.len());
+    } else {
+        let mut cmd = Command::new("gzip");
+        cmd.arg("-dc").arg(archive);
+        cmd
+    };
+    let mut child = cmd
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run {:?}", cmd.get_program()))?;
+    let stdout = child.stdout.take().context("Failed to read archive")?;
+    let size = io::copy(
+        &mut io::Read::take(stdout, MAX_UNPACKED_SIZE + 1),
+        &mut io::sink(),
+    );
+    let _ = child.kill();
+    let _ = child.wait();
+    size.context("Failed to read archive")
+}
+
+fn unpack(name: &str, bytes: &[u8]) -> Result<String> {
+    let dir = temp::dir("icemelter-attachment")?;
+    let archive = dir.path().join("archive");
+    fs::write(&archive, bytes).context("Failed to write archive")?;
+    if unpacked_size(name, &archive)? > MAX_UNPACKED_SIZE {
+        bail!("{name} is too large once unpacked");
+    }
+    let unpacked = dir.path().join("unpacked");
+    fs::create_dir(&unpacked)?;
+    let mut cmd = if name.ends_with(".zip") {
+        let mut cmd = Command::new("unzip");
+        cmd.arg("-q").arg(&archive).arg("-d").arg(&unpacked);
+        cmd
+    } else {
+        let mut cmd = Command::new("tar");
+        cmd.arg(if name.ends_with(".tar") {
+            "-xf"
+        } else {
+            "-xzf"
+        })
+        .arg(&archive)
+        .arg("-C")
+        .arg(&unpacked);
+        cmd
+    };
+    let status = cmd
+        .status()
+        .with_context(|| format!("Failed to run {:?}", cmd.get_program()))?;
+    if !status.success() {
+        bail!("Failed to unpack {name}");
+    }
+    let root = root(&unpacked)?;
+    let code =
+        fs::read_to_string(&root).with_context(|| format!("Failed to read {}", root.display()))?;
+    let (code, _) = flatten::flatten_within(&code, root.parent().unwrap_or(&unpacked), &unpacked)?;
+    Ok(code)
+}
+
+/// The code in an attachment, unpacking archives
+pub(crate) fn code(link: &str) -> Result<String> {
+    let bytes = http::get_limited(link, MAX_SIZE)?;
+    let name = link.to_lowercase();
+    if is_archive(&name) {
+        unpack(&name, &bytes)
+    } else {
+        String::from_utf8(bytes).with_context(|| format!("{link} isn't UTF-8"))
+    }
+}
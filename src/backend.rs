@@ -0,0 +1,35 @@
+/// A rustc codegen backend
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Backend {
+    Cranelift,
+    Gcc,
+    Llvm,
+}
+
+impl Backend {
+    /// Flag to select the backend, if it isn't the default
+    pub(crate) fn flag(self) -> Option<&'static str> {
+        match self {
+            Backend::Cranelift => Some("-Zcodegen-backend=cranelift"),
+            Backend::Gcc => Some("-Zcodegen-backend=gcc"),
+            Backend::Llvm => None,
+        }
+    }
+
+    /// Rustup component providing the backend, if any
+    pub(crate) fn component(self) -> Option<&'static str> {
+        match self {
+            Backend::Cranelift => Some("rustc-codegen-cranelift-preview"),
+            Backend::Gcc | Backend::Llvm => None,
+        }
+    }
+
+    /// Regex for errors from the backend that don't look like rustc ICEs
+    pub(crate) fn banner(self) -> &'static str {
+        match self {
+            Backend::Cranelift => r"(Verifier errors|panicked at .*cranelift)",
+            Backend::Gcc => r"libgccjit\.so: error:",
+            Backend::Llvm => r"LLVM ERROR:",
+        }
+    }
+}
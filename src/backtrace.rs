@@ -0,0 +1,499 @@
+//! Parsing rustc's stderr (with `RUST_BACKTRACE`) after an ICE, shared by
+//! signatures, label suggestions, and phase classification
+
+use std::collections::HashSet;
+use std::fmt;
+
+use regex::Regex;
+
+use crate::issue;
+
+/// How many compiler frames go into [`Backtrace::hash`]
+const HASH_FRAMES: usize = 8;
+
+/// Crates whose frames are just error reporting, not where the bug is
+const REPORTING_CRATES: &[&str] = &["rustc_driver", "rustc_driver_impl", "rustc_errors"];
+
+/// How alike two ICEs are, each part from 0 (different) to 1 (the same), see
+/// [`Backtrace::similarity`]
+///
+/// Parts are `None` if either ICE lacks them, e.g., without `RUST_BACKTRACE`.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Similarity {
+    pub(crate) message: Option<f64>,
+    pub(crate) queries: Option<f64>,
+    pub(crate) frames: Option<f64>,
+}
+
+/// Words in messages, with numbers generalized as in
+/// [`issue::message_regex`]
+fn message_words(message: &str) -> HashSet<String> {
+    let number_regex = Regex::new(r"\d+").unwrap();
+    message
+        .split_whitespace()
+        .map(|w| number_regex.replace_all(w, "N").into_owned())
+        .collect()
+}
+
+fn jaccard<T: Eq + std::hash::Hash>(a: &HashSet<T>, b: &HashSet<T>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 1.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+impl Similarity {
+    /// Weighted mean of the known parts, the query stack is often short and
+    /// shared by unrelated bugs
+    pub(crate) fn score(&self) -> Option<f64> {
+        let parts = [(self.message, 2.0), (self.frames, 2.0), (self.queries, 1.0)];
+        let weight: f64 = parts
+            .iter()
+            .filter(|(p, _)| p.is_some())
+            .map(|(_, w)| w)
+            .sum();
+        if weight == 0.0 {
+            return None;
+        }
+        let total: f64 = parts.iter().filter_map(|(p, w)| p.map(|p| p * w)).sum();
+        Some(total / weight)
+    }
+
+    pub(crate) fn verdict(&self) -> &'static str {
+        match self.score() {
+            Some(s) if s >= 0.8 => "likely the same bug",
+            Some(s) if s >= 0.5 => "possibly the same bug",
+            Some(_) => "probably different bugs",
+            None => "not enough information to compare",
+        }
+    }
+}
+
+impl fmt::Display for Similarity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let part = |p: Option<f64>| match p {
+            Some(p) => format!("{p:.2}"),
+            None => String::from("-"),
+        };
+        writeln!(f, "Message:     {}", part(self.message))?;
+        writeln!(f, "Query stack: {}", part(self.queries))?;
+        writeln!(f, "Backtrace:   {}", part(self.frames))?;
+        write!(
+            f,
+            "Similarity:  {} ({})",
+            part(self.score()),
+            self.verdict()
+        )
+    }
+}
+
+/// An ICE, as reported by rustc
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Backtrace {
+    /// The panic or ICE message, or LLVM's error
+    pub(crate) message: Option<String>,
+    /// Compiler crates where rustc panicked or reported the ICE
+    pub(crate) location_crates: Vec<String>,
+    /// Query stack, innermost first
+    pub(crate) queries: Vec<String>,
+    /// Functions in the backtrace, innermost first, without addresses or
+    /// symbol hashes
+    pub(crate) frames: Vec<String>,
+}
+
+/// What identifies an ICE without a backtrace, see
+/// [`IceCheck::require_fingerprint`](crate::check::IceCheck::require_fingerprint)
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Fingerprint {
+    /// The panic or ICE message
+    message: Option<String>,
+    /// Where rustc panicked or reported the ICE, e.g.,
+    /// `compiler/rustc_middle/src/ty/mod.rs:123`
+    location: Option<String>,
+    /// Names of the queries in the query stack, innermost first
+    queries: Vec<String>,
+}
+
+/// A message without what changes between candidates: quoted code (e.g.,
+/// item paths, which include the candidate's crate name), `DefId`s, crate
+/// disambiguators, and numbers
+fn normalize_message(message: &str) -> String {
+    let quoted = Regex::new(r"`[^`]*`").unwrap();
+    let def_id = Regex::new(r"DefId\([^)]*\)").unwrap();
+    let disambiguator = Regex::new(r"\[[0-9a-f]{4}\]").unwrap();
+    let number = Regex::new(r"\d+").unwrap();
+    let message = quoted.replace_all(message, "`_`");
+    let message = def_id.replace_all(&message, "DefId(_)");
+    let message = disambiguator.replace_all(&message, "[_]");
+    number.replace_all(&message, "N").into_owned()
+}
+
+impl Fingerprint {
+    pub(crate) fn parse(stderr: &str) -> Self {
+        let location_regex = Regex::new(
+            r"(?:internal compiler error: |panicked at (?:'.*', )?)(?:/rustc/[0-9a-f]+/)?(compiler/[^\s:']+:\d+)",
+        )
+        .unwrap();
+        let query_regex = Regex::new(r"(?m)^#\d+ \[(\w+)\]").unwrap();
+        Fingerprint {
+            message: issue::ice_message(stderr),
+            location: location_regex.captures(stderr).map(|c| c[1].to_string()),
+            queries: query_regex
+                .captures_iter(stderr)
+                .map(|c| c[1].to_string())
+                .collect(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.message.is_none() && self.location.is_none() && self.queries.is_empty()
+    }
+
+    /// How `other` differs, ignoring parts that this fingerprint lacks
+    ///
+    /// Messages (see [`normalize_message`]) are only compared without a
+    /// location, they often quote code that changes during reduction.
+    pub(crate) fn mismatch(&self, other: &Fingerprint) -> Option<&'static str> {
+        if let Some(location) = &self.location {
+            if other.location.as_ref() != Some(location) {
+                return Some("location");
+            }
+        } else if let Some(message) = &self.message {
+            let same = other
+                .message
+                .as_ref()
+                .is_some_and(|m| normalize_message(m) == normalize_message(message));
+            if !same {
+                return Some("message");
+            }
+        }
+        if !self.queries.is_empty() && self.queries != other.queries {
+            return Some("query stack");
+        }
+        None
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(m) = &self.message {
+            parts.push(format!("message `{m}`"));
+        }
+        if let Some(l) = &self.location {
+            parts.push(format!("at {l}"));
+        }
+        if !self.queries.is_empty() {
+            parts.push(format!("in {}", self.queries.join(" < ")));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// The backtrace section of rustc's stderr
+pub(crate) fn extract(stderr: &str) -> Option<String> {
+    let mut lines = stderr
+        .lines()
+        .skip_while(|l| !l.starts_with("stack backtrace:"))
+        .peekable();
+    lines.peek()?;
+    let backtrace = lines
+        .take_while(|l| {
+            !l.starts_with("note:") && !l.starts_with("error:") && !l.starts_with("query stack")
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    Some(backtrace)
+}
+
+/// Lines of stderr to show in reports, see [`for_report`]
+const REPORT_LINES: usize = 50;
+
+/// rustc's stderr without the backtrace (which is shown separately), with the
+/// temporary file replaced by `file_name`, and truncated
+pub(crate) fn for_report(stderr: &str, file_name: &str) -> String {
+    let temp_file_regex = Regex::new(r"\S*icemelter-[[:alnum:]]{6}\.rs").unwrap();
+    let mut in_backtrace = false;
+    let mut lines = Vec::new();
+    for line in stderr.lines() {
+        if line.starts_with("stack backtrace:") {
+            in_backtrace = true;
+            continue;
+        }
+        if in_backtrace {
+            if line.starts_with("note:")
+                || line.starts_with("error:")
+                || line.starts_with("query stack")
+            {
+                in_backtrace = false;
+            } else {
+                continue;
+            }
+        }
+        lines.push(temp_file_regex.replace_all(line, file_name).into_owned());
+    }
+    if lines.len() > REPORT_LINES {
+        let more = lines.len() - REPORT_LINES;
+        lines.truncate(REPORT_LINES);
+        lines.push(format!("[... {more} more lines]"));
+    }
+    lines.join("\n")
+}
+
+/// The crate a (normalized) frame is in, e.g., `rustc_hir_typeck`
+pub(crate) fn frame_crate(frame: &str) -> Option<&str> {
+    frame.trim_start_matches('<').split("::").next()
+}
+
+/// FNV-1a, as 16 hex digits, stable across platforms and Rust versions
+pub(crate) fn fnv1a(bytes: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in bytes {
+        hash ^= u64::from(*b);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{hash:016x}")
+}
+
+impl Backtrace {
+    pub(crate) fn parse(stderr: &str) -> Self {
+        let location_regex =
+            Regex::new(r"(?m)(?:panicked at '?|internal compiler error: )compiler/(rustc_\w+)/")
+                .unwrap();
+        let query_regex = Regex::new(r"(?m)^#\d+ \[(\w+)\]").unwrap();
+        let frame_regex = Regex::new(r"(?m)^\s*\d+:\s+(?:0x[0-9a-f]+ - )?(.+?)\s*$").unwrap();
+        let symbol_hash_regex = Regex::new(r"::h[0-9a-f]{16}$").unwrap();
+        let frames = match extract(stderr) {
+            Some(bt) => frame_regex
+                .captures_iter(&bt)
+                .map(|c| symbol_hash_regex.replace(&c[1], "").into_owned())
+                .collect(),
+            None => Vec::new(),
+        };
+        let llvm_error = stderr
+            .lines()
+            .find(|l| l.starts_with("LLVM ERROR:"))
+            .map(String::from);
+        Backtrace {
+            message: issue::ice_message(stderr).or(llvm_error),
+            location_crates: location_regex
+                .captures_iter(stderr)
+                .map(|c| c[1].to_string())
+                .collect(),
+            queries: query_regex
+                .captures_iter(stderr)
+                .map(|c| c[1].to_string())
+                .collect(),
+            frames,
+        }
+    }
+
+    pub(crate) fn is_llvm_error(&self) -> bool {
+        self.message
+            .as_deref()
+            .map(|m| m.starts_with("LLVM ERROR:"))
+            .unwrap_or(false)
+    }
+
+    /// Frames in compiler crates, other than error reporting
+    pub(crate) fn compiler_frames(&self) -> impl Iterator<Item = &str> {
+        self.frames.iter().map(String::as_str).filter(|f| {
+            frame_crate(f)
+                .map(|c| c.starts_with("rustc_") && !REPORTING_CRATES.contains(&c))
+                .unwrap_or(false)
+                && !f.contains("::util::bug::")
+        })
+    }
+
+    /// Compare messages (up to numbers), query stacks, and the innermost
+    /// compiler frames (those in [`Backtrace::hash`])
+    pub(crate) fn similarity(&self, other: &Backtrace) -> Similarity {
+        let message = match (&self.message, &other.message) {
+            (Some(a), Some(b)) if issue::message_regex(a) == issue::message_regex(b) => Some(1.0),
+            (Some(a), Some(b)) => Some(jaccard(&message_words(a), &message_words(b))),
+            (None, None) => None,
+            _ => Some(0.0),
+        };
+        let queries = (!self.queries.is_empty() && !other.queries.is_empty()).then(|| {
+            let a = self.queries.iter().collect::<HashSet<_>>();
+            let b = other.queries.iter().collect::<HashSet<_>>();
+            jaccard(&a, &b)
+        });
+        let a = self
+            .compiler_frames()
+            .take(HASH_FRAMES)
+            .collect::<HashSet<_>>();
+        let b = other
+            .compiler_frames()
+            .take(HASH_FRAMES)
+            .collect::<HashSet<_>>();
+        let frames = (!a.is_empty() && !b.is_empty()).then(|| jaccard(&a, &b));
+        Similarity {
+            message,
+            queries,
+            frames,
+        }
+    }
+
+    /// A hash of the innermost compiler frames, stable across runs and
+    /// Icemelter versions
+    pub(crate) fn hash(&self) -> Option<String> {
+        let frames = self.compiler_frames().take(HASH_FRAMES).collect::<Vec<_>>();
+        if frames.is_empty() {
+            return None;
+        }
+        Some(fnv1a(frames.join("\n").as_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// rustc 1.73 and later
+    const NEW_FORMAT: &str = "\
+thread 'rustc' panicked at /rustc/5680fa18feaa87f3ff04063800aec256c3d4b4be/compiler/rustc_hir_typeck/src/method/probe.rs:1035:25:
+assertion failed: !self.candidates.is_empty()
+note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
+
+error: the compiler unexpectedly panicked. this is a bug.
+
+note: we would appreciate a bug report: https://github.com/rust-lang/rust/issues/new?labels=C-bug%2C+I-ICE%2C+T-compiler&template=ice.md
+
+note: rustc 1.75.0-nightly (2f1bd0729 2023-10-27) running on x86_64-unknown-linux-gnu
+
+query stack during panic:
+#0 [typeck] type-checking `main`
+#1 [used_trait_imports] finding used_trait_imports `main`
+#2 [analysis] running analysis passes on this crate
+end of query stack
+";
+
+    /// rustc 1.72 and earlier
+    const OLD_FORMAT: &str = "\
+thread 'rustc' panicked at 'assertion failed: !self.candidates.is_empty()', compiler/rustc_hir_typeck/src/method/probe.rs:1035:25
+note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
+
+error: internal compiler error: unexpected panic
+
+note: the compiler unexpectedly panicked. this is a bug.
+
+query stack during panic:
+#0 [typeck] type-checking `main`
+#1 [used_trait_imports] finding used_trait_imports `main`
+#2 [analysis] running analysis passes on this crate
+end of query stack
+";
+
+    const BUG: &str = "\
+error: internal compiler error: compiler/rustc_middle/src/ty/sty.rs:2258:18: cannot convert `ReErased` to a region vid
+
+thread 'rustc' panicked at compiler/rustc_middle/src/ty/sty.rs:2258:18:
+Box<dyn Any>
+
+query stack during panic:
+#0 [mir_borrowck] borrow-checking `icemelter_AbC12x::foo`
+#1 [analysis] running analysis passes on this crate
+end of query stack
+";
+
+    #[test]
+    fn parse_new_format() {
+        let fp = Fingerprint::parse(NEW_FORMAT);
+        assert_eq!(
+            fp.message.as_deref(),
+            Some("assertion failed: !self.candidates.is_empty()")
+        );
+        assert_eq!(
+            fp.location.as_deref(),
+            Some("compiler/rustc_hir_typeck/src/method/probe.rs:1035")
+        );
+        assert_eq!(fp.queries, ["typeck", "used_trait_imports", "analysis"]);
+    }
+
+    #[test]
+    fn parse_old_format() {
+        let fp = Fingerprint::parse(OLD_FORMAT);
+        assert_eq!(
+            fp.message.as_deref(),
+            Some("assertion failed: !self.candidates.is_empty()")
+        );
+        assert_eq!(
+            fp.location.as_deref(),
+            Some("compiler/rustc_hir_typeck/src/method/probe.rs:1035")
+        );
+        assert_eq!(fp.queries, ["typeck", "used_trait_imports", "analysis"]);
+    }
+
+    #[test]
+    fn parse_bug() {
+        let fp = Fingerprint::parse(BUG);
+        assert_eq!(
+            fp.message.as_deref(),
+            Some("cannot convert `ReErased` to a region vid")
+        );
+        assert_eq!(
+            fp.location.as_deref(),
+            Some("compiler/rustc_middle/src/ty/sty.rs:2258")
+        );
+        assert_eq!(fp.queries, ["mir_borrowck", "analysis"]);
+    }
+
+    #[test]
+    fn parse_not_an_ice() {
+        assert!(Fingerprint::parse("error[E0308]: mismatched types\n").is_empty());
+    }
+
+    #[test]
+    fn mismatch_across_formats() {
+        let new = Fingerprint::parse(NEW_FORMAT);
+        let old = Fingerprint::parse(OLD_FORMAT);
+        assert_eq!(new.mismatch(&old), None);
+        assert_eq!(old.mismatch(&new), None);
+    }
+
+    #[test]
+    fn mismatch_ignores_quoted_code() {
+        let fp = Fingerprint::parse(BUG);
+        let renamed = BUG.replace("icemelter_AbC12x::foo", "icemelter_Zz9yXw::bar");
+        assert_eq!(fp.mismatch(&Fingerprint::parse(&renamed)), None);
+    }
+
+    #[test]
+    fn mismatch_location() {
+        let fp = Fingerprint::parse(BUG);
+        let moved = BUG.replace("sty.rs:2258", "sty.rs:2260");
+        assert_eq!(fp.mismatch(&Fingerprint::parse(&moved)), Some("location"));
+        assert_eq!(
+            fp.mismatch(&Fingerprint::parse(NEW_FORMAT)),
+            Some("location")
+        );
+    }
+
+    #[test]
+    fn mismatch_query_stack() {
+        let fp = Fingerprint::parse(NEW_FORMAT);
+        let other = NEW_FORMAT.replace("#0 [typeck]", "#0 [type_of]");
+        assert_eq!(
+            fp.mismatch(&Fingerprint::parse(&other)),
+            Some("query stack")
+        );
+    }
+
+    #[test]
+    fn mismatch_message_without_location() {
+        let delayed = "error: internal compiler error: broken MIR in DefId(0:4 ~ icemelter_Ab12Cd[5e6f]::main) (_3 = &_4): bad assignment (`&u8` = `&i32`)\n";
+        let fp = Fingerprint::parse(delayed);
+        assert_eq!(fp.location, None);
+        let other = delayed
+            .replace(
+                "0:4 ~ icemelter_Ab12Cd[5e6f]",
+                "0:7 ~ icemelter_Xy34Ef[0a1b]",
+            )
+            .replace("`&u8`", "`&u16`");
+        assert_eq!(fp.mismatch(&Fingerprint::parse(&other)), None);
+        let different = "error: internal compiler error: no type for node HirId(DefId(0:4 ~ icemelter_Ab12Cd[5e6f]::main).1)\n";
+        assert_eq!(fp.mismatch(&Fingerprint::parse(different)), Some("message"));
+    }
+}
@@ -0,0 +1,289 @@
+//! Melting several inputs, e.g., `icemelter '#1' '#2' repro.rs`
+//!
+//! Each input is melted by running icemelter again with the same arguments
+//! and [`INPUT_VAR`] set to the input. Each run records itself
+//! in the file named by [`SUMMARY_VAR`], and the records make up the summary.
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use tracing::{info, warn};
+
+use crate::backtrace::Backtrace;
+use crate::history::{self, Record};
+use crate::issue;
+use crate::serve::Dashboard;
+
+/// Whether an input is a glob, e.g., `crashes/*.rs`
+fn is_glob(source: &str) -> bool {
+    source.contains(['*', '?'])
+}
+
+/// Files matching a glob, whose wildcards must be in the file name
+fn glob(pattern: &str) -> Result<Vec<String>> {
+    let path = Path::new(pattern);
+    let dir = match path.parent() {
+        Some(d) if !d.as_os_str().is_empty() => d,
+        _ => Path::new("."),
+    };
+    if is_glob(&dir.to_string_lossy()) {
+        bail!("Only the file name can have wildcards in {pattern}");
+    }
+    let name = path
+        .file_name()
+        .with_context(|| format!("Bad glob {pattern}"))?
+        .to_string_lossy();
+    let rx = regex::escape(&name)
+        .replace(r"\*", ".*")
+        .replace(r"\?", ".");
+    let rx = Regex::new(&format!("^{rx}$")).context("Internal error: bad glob regex")?;
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.is_file()
+            && path
+                .file_name()
+                .is_some_and(|n| rx.is_match(&n.to_string_lossy()))
+        {
+            files.push(path.display().to_string());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Rust files in a directory that isn't a Cargo project
+fn rust_files(dir: &Path) -> Result<Vec<String>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|e| e == "rs") {
+            files.push(path.display().to_string());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Replace directories (that aren't Cargo projects) and globs with the Rust
+/// files in them
+pub(crate) fn expand(sources: &[String]) -> Result<Vec<String>> {
+    let mut expanded = Vec::with_capacity(sources.len());
+    for source in sources {
+        let path = Path::new(source);
+        let files = if path.is_dir() && !path.join("Cargo.toml").is_file() {
+            rust_files(path)?
+        } else if is_glob(source) && !path.exists() {
+            glob(source)?
+        } else {
+            expanded.push(source.clone());
+            continue;
+        };
+        if files.is_empty() {
+            bail!("No Rust files in {source}");
+        }
+        expanded.extend(files);
+    }
+    Ok(expanded)
+}
+
+/// Which input to melt, only set for runs within a batch
+pub(crate) const INPUT_VAR: &str = "ICEMELTER_BATCH_INPUT";
+
+/// Where runs within a batch record themselves
+pub(crate) const SUMMARY_VAR: &str = "ICEMELTER_BATCH_SUMMARY";
+
+/// The input to melt, if this is a run within a batch
+pub(crate) fn input() -> Option<String> {
+    std::env::var(INPUT_VAR).ok()
+}
+
+/// Record a run within a batch, if this is one
+pub(crate) fn record(record: &Record) {
+    if let Some(path) = std::env::var_os(SUMMARY_VAR) {
+        if let Err(e) = history::append(Path::new(&path), record) {
+            warn!("Failed to record run in batch summary: {:#}", e);
+        }
+    }
+}
+
+/// Where runs within a batch write the reduced file, unless it's an issue
+pub(crate) const OUTPUT_VAR: &str = "ICEMELTER_BATCH_OUTPUT";
+
+fn stem(source: &str) -> String {
+    Path::new(source)
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| String::from("input"))
+}
+
+/// Where the reduced files go for inputs that aren't issues, e.g.,
+/// `repro.melted.rs` for `repro.rs`, numbered if several inputs have the same
+/// name (e.g., `repro.1.melted.rs` and `repro.2.melted.rs` for `a/repro.rs`
+/// and `b/repro.rs`)
+fn outputs(sources: &[String]) -> Vec<PathBuf> {
+    let stems: Vec<String> = sources.iter().map(|s| stem(s)).collect();
+    stems
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            if stems.iter().filter(|t| *t == s).count() > 1 {
+                PathBuf::from(format!("{s}.{}.melted.rs", i + 1))
+            } else {
+                PathBuf::from(format!("{s}.melted.rs"))
+            }
+        })
+        .collect()
+}
+
+/// Where the reduced file goes for an input in a batch
+pub(crate) fn output(source: &str) -> PathBuf {
+    std::env::var_os(OUTPUT_VAR)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(format!("{}.melted.rs", stem(source))))
+}
+
+/// An earlier input in this batch that was melted and has the same ICE as
+/// `stderr`, by backtrace hash or (without backtraces) message, like
+/// [`crate::intake::group`]
+pub(crate) fn melted_duplicate(stderr: &str) -> Option<Record> {
+    let path = std::env::var_os(SUMMARY_VAR)?;
+    let bt = Backtrace::parse(stderr);
+    let hash = bt.hash();
+    let message = bt.message.as_deref().map(issue::message_regex);
+    history::read(Path::new(&path))
+        .ok()?
+        .into_iter()
+        .filter(|r| r.duplicate_of.is_none())
+        .find(|r| match (&r.backtrace_hash, &hash) {
+            (Some(a), Some(b)) => a == b,
+            _ => message.is_some() && r.signature.as_deref().map(issue::message_regex) == message,
+        })
+}
+
+fn describe(record: Option<&Record>, code: Option<i32>) -> String {
+    let Some(r) = record else {
+        return match code {
+            Some(c) => format!("didn't reproduce or failed (exit code {c})"),
+            None => String::from("didn't reproduce or failed"),
+        };
+    };
+    if let Some(first) = &r.duplicate_of {
+        return match &r.output {
+            Some(o) => format!("same ICE as {first}, see {}", o.display()),
+            None => format!("same ICE as {first}"),
+        };
+    }
+    let mut parts = vec![String::from("reproduced")];
+    if r.reduced_size < r.original_size {
+        parts.push(format!(
+            "reduced from {} to {} bytes",
+            r.original_size, r.reduced_size
+        ));
+    } else {
+        parts.push(String::from("not reduced"));
+    }
+    if let Some(pr) = &r.bisect_pr {
+        parts.push(format!("bisected to {pr}"));
+    }
+    if let Some(report) = &r.report {
+        parts.push(format!("report in {}", report.display()));
+    }
+    parts.join(", ")
+}
+
+/// Arguments for the runs within a batch, without `--serve`, since the batch
+/// serves the dashboard
+fn child_args(mut args: impl Iterator<Item = OsString>) -> Vec<OsString> {
+    let mut child = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            child.push(arg);
+            child.extend(args);
+            break;
+        }
+        if arg == "--serve" {
+            args.next();
+        } else if !arg.to_string_lossy().starts_with("--serve=") {
+            child.push(arg);
+        }
+    }
+    child
+}
+
+/// Melt each input in turn, then summarize the results
+pub(crate) fn run(sources: &[String], dashboard: &Dashboard) -> Result<()> {
+    if sources.iter().any(|s| s == "-") {
+        bail!("Can't read several inputs from stdin");
+    }
+    for s in sources {
+        let path = Path::new(s);
+        let file = path.is_dir()
+            || (path.is_file()
+                && (path.extension().is_some_and(|e| e == "rs")
+                    || path.file_name().is_some_and(|f| f == "Cargo.toml")));
+        let url = s.starts_with("https://") || s.starts_with("http://");
+        if !file && !url && issue::parse_reference(s).is_none() {
+            bail!(
+                "{s} isn't a Rust file, project, or issue; put the rustc command line after `--`"
+            );
+        }
+    }
+    let exe = std::env::current_exe().context("Couldn't find the icemelter executable")?;
+    let summary = tempfile::NamedTempFile::new().context("Failed to create a temporary file")?;
+    let outputs = outputs(sources);
+    let args = child_args(std::env::args_os().skip(1));
+    for source in sources {
+        dashboard.queue(source);
+    }
+    let mut results = Vec::with_capacity(sources.len());
+    for (i, (source, output)) in sources.iter().zip(&outputs).enumerate() {
+        info!("[{}/{}] Melting {}...", i + 1, sources.len(), source);
+        dashboard.start(source);
+        let before = history::read(summary.path())?.len();
+        let status = Command::new(&exe)
+            .args(&args)
+            .env(INPUT_VAR, source)
+            .env(OUTPUT_VAR, output)
+            .env(SUMMARY_VAR, summary.path())
+            .status()
+            .context("Failed to run icemelter")?;
+        let records = history::read(summary.path())?;
+        let record = records.get(before).cloned();
+        if !status.success() && record.is_none() {
+            warn!("Failed to melt {}", source);
+        }
+        match &record {
+            Some(r) => dashboard.finish(r.output.clone(), r.report.clone()),
+            None => dashboard.finish(None, None),
+        }
+        results.push((source, record, status.code()));
+    }
+    info!("Summary:");
+    for (source, record, code) in &results {
+        info!("  {}: {}", source, describe(record.as_ref(), *code));
+    }
+    // Inputs with the same backtrace are probably duplicates
+    let mut first_with: Vec<(&String, &String)> = Vec::new();
+    for (source, record, _) in &results {
+        let Some(hash) = record
+            .as_ref()
+            .filter(|r| r.duplicate_of.is_none())
+            .and_then(|r| r.backtrace_hash.as_ref())
+        else {
+            continue;
+        };
+        match first_with.iter().find(|(h, _)| *h == hash) {
+            Some((_, first)) => info!("{} has the same backtrace as {}", source, first),
+            None => first_with.push((hash, source)),
+        }
+    }
+    let reproduced = results.iter().filter(|(_, r, _)| r.is_some()).count();
+    info!("{} of {} reproduced", reproduced, results.len());
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use std::fs;
+use std::os::unix::prelude::PermissionsExt;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use tracing::{debug, info, warn};
+
+use crate::check;
+use crate::temp;
+
+/// Drop `--sysroot` from rustc arguments, the stage 1 compiler knows its own
+pub(crate) fn without_sysroot(args: &[String]) -> Vec<String> {
+    let mut out = Vec::with_capacity(args.len());
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--sysroot" {
+            args.next();
+        } else if !arg.starts_with("--sysroot=") {
+            out.push(arg.clone());
+        }
+    }
+    out
+}
+
+fn git(rust: &Path) -> Command {
+    let mut cmd = Command::new("git");
+    cmd.arg("-C").arg(rust);
+    cmd
+}
+
+/// Run `git bisect` in a rust-lang/rust checkout, building a stage 1 compiler
+/// at each step, to find the exact commit that introduced the ICE.
+pub(crate) fn bisect_local(
+    rust: &Path,
+    good: &str,
+    bad: &str,
+    args: &[String],
+    env: &[(String, String)],
+    file: &[u8],
+    stderr_regex: &str,
+) -> Result<String> {
+    let rust = rust
+        .canonicalize()
+        .with_context(|| format!("Couldn't find rust checkout at {}", rust.display()))?;
+    let rs_tmp = temp::file("icemelter-", ".rs")?;
+    let rs_path = rs_tmp.path();
+    fs::write(rs_path, file)?;
+    debug!("Wrote source to {}", rs_path.display());
+    let script_tmp = temp::file("git-bisect-", ".sh")?;
+    let script_path = script_tmp.path();
+    fs::write(
+        script_path,
+        format!(
+            r#"#!/usr/bin/env bash
+cd '{}' || exit 255
+if ! ./x.py build --stage 1 library; then
+  exit 125
+fi
+if build/host/stage1/bin/rustc {} 2>&1 | egrep '{}'; then
+  exit 1
+fi
+exit 0
+"#,
+            rust.display(),
+            check::shell_words(&without_sysroot(args), &rs_path.display().to_string()),
+            stderr_regex
+        ),
+    )?;
+    let mut perms = fs::metadata(script_path)?.permissions();
+    perms.set_mode(0o700);
+    fs::set_permissions(script_path, perms)?;
+    debug!("Wrote script to {}", script_path.display());
+
+    let start = git(&rust)
+        .args(["bisect", "start", bad, good])
+        .status()
+        .context("Failed to run git bisect start")?;
+    if !start.success() {
+        anyhow::bail!("git bisect start failed");
+    }
+    info!("Running git bisect in {}", rust.display());
+    let out = git(&rust)
+        .args(["bisect", "run"])
+        .arg(script_path)
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .output()
+        .context("Failed to run git bisect run")?;
+    crate::atomic::write("git-bisect.stdout.txt", &out.stdout)?;
+    crate::atomic::write("git-bisect.stderr.txt", &out.stderr)?;
+    info!("Wrote to git-bisect.std{{out,err}}.txt");
+    let reset = git(&rust).args(["bisect", "reset"]).status();
+    if !reset.map(|s| s.success()).unwrap_or(false) {
+        warn!("git bisect reset failed");
+    }
+    if !out.status.success() {
+        warn!("git bisect failed");
+    }
+
+    // The culprit is printed as `<sha> is the first bad commit`, followed by
+    // the commit message
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let report = stdout
+        .lines()
+        .skip_while(|l| !l.ends_with("is the first bad commit"))
+        .take_while(|l| !l.starts_with("bisect found first bad commit"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok(report)
+}
@@ -0,0 +1,62 @@
+//! GitHub responses, revalidated with their ETags, see `--refresh`
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+use crate::atomic;
+
+/// Ignore cached responses, set by `--refresh`
+static REFRESH: AtomicBool = AtomicBool::new(false);
+
+pub(crate) fn refresh() {
+    REFRESH.store(true, Ordering::Relaxed);
+}
+
+#[derive(Deserialize, Serialize)]
+pub(crate) struct Entry {
+    pub(crate) etag: String,
+    pub(crate) body: String,
+}
+
+/// `$XDG_CACHE_HOME/icemelter`, or `~/.cache/icemelter`
+pub(crate) fn dir() -> Option<PathBuf> {
+    let cache = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(d) if !d.is_empty() => PathBuf::from(d),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+    };
+    Some(cache.join("icemelter"))
+}
+
+fn path(url: &str) -> Option<PathBuf> {
+    let hash = Sha256::digest(url.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>();
+    Some(dir()?.join("github").join(format!("{hash}.json")))
+}
+
+/// The cached response for `url`, unless `--refresh`
+pub(crate) fn get(url: &str) -> Option<Entry> {
+    if REFRESH.load(Ordering::Relaxed) {
+        return None;
+    }
+    serde_json::from_slice(&fs::read(path(url)?).ok()?).ok()
+}
+
+pub(crate) fn put(url: &str, entry: &Entry) {
+    let Some(path) = path(url) else {
+        return;
+    };
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| atomic::write(&path, serde_json::to_vec(entry)?));
+    if let Err(e) = result {
+        debug!("Failed to cache {}: {}", url, e);
+    }
+}
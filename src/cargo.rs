@@ -0,0 +1,199 @@
+//! Finding the file to reduce in a Cargo project, e.g., its build script or a
+//! file that includes code generated by it
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+use regex::{Captures, Regex};
+use tracing::debug;
+
+use crate::temp;
+
+/// Where the ICE in a Cargo build is
+pub(crate) struct IceSite {
+    /// The file to reduce, relative to the project root
+    pub(crate) file: PathBuf,
+    /// Code generated by the build script that `file` includes, by its path
+    /// in `OUT_DIR`
+    pub(crate) generated: Vec<(String, String)>,
+}
+
+/// `include!(concat!(env!("OUT_DIR"), "/path"))`
+fn out_dir_include() -> Regex {
+    Regex::new(
+        r#"(?m)(?P<start>^[ \t]*)?include!\s*\(\s*concat!\s*\(\s*env!\s*\(\s*"OUT_DIR"\s*\)\s*,\s*"/?(?P<path>[^"]+)"\s*\)\s*\)(?P<semi>\s*;)?"#,
+    )
+    .unwrap()
+}
+
+/// The Rust files in a directory, except build outputs
+fn rust_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            if !path.ends_with("target") && !path.ends_with(".git") {
+                rust_files(&path, files)?;
+            }
+        } else if path.extension().is_some_and(|e| e == "rs") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// The file in the project at `root` that includes `name` from `OUT_DIR`
+fn includer(root: &Path, name: &str) -> Result<Option<PathBuf>> {
+    let rx = out_dir_include();
+    let mut files = Vec::new();
+    rust_files(root, &mut files)?;
+    files.sort();
+    for file in files {
+        let code = fs::read_to_string(&file).unwrap_or_default();
+        if rx.captures_iter(&code).any(|c| &c["path"] == name) {
+            return Ok(file.strip_prefix(root).ok().map(Path::to_path_buf));
+        }
+    }
+    Ok(None)
+}
+
+/// The path of a generated file in `OUT_DIR`, from its path in the target
+/// directory (e.g., `debug/build/foo-0123/out/gen.rs`)
+fn out_dir_path(path: &Path) -> Option<String> {
+    let mut components = path.components();
+    components.find(|c| *c == Component::Normal("out".as_ref()))?;
+    Some(components.as_path().to_string_lossy().replace('\\', "/"))
+}
+
+/// The build script of the package at `root`
+fn build_script(root: &Path) -> PathBuf {
+    let manifest = fs::read_to_string(root.join("Cargo.toml")).unwrap_or_default();
+    let build = Regex::new(r#"(?m)^\s*build\s*=\s*"(?P<path>[^"]+)""#).unwrap();
+    match build.captures(&manifest) {
+        Some(c) => PathBuf::from(&c["path"]),
+        None => PathBuf::from("build.rs"),
+    }
+}
+
+/// The name of the package at `root`
+fn package_name(root: &Path) -> Option<String> {
+    let manifest = fs::read_to_string(root.join("Cargo.toml")).ok()?;
+    let name = Regex::new(r#"(?m)^\s*name\s*=\s*"(?P<name>[^"]+)""#).unwrap();
+    name.captures(&manifest).map(|c| c["name"].to_string())
+}
+
+/// The lockfile of a workspace, generated (in a copy) if it doesn't have one
+pub(crate) fn lockfile(workspace: &Path, env: &[(String, String)]) -> Result<Vec<u8>> {
+    let path = workspace.join("Cargo.lock");
+    if path.is_file() {
+        return fs::read(&path).with_context(|| format!("Failed to read {}", path.display()));
+    }
+    let copy = temp::copy_workspace(workspace).context("Failed to copy the workspace")?;
+    let status = Command::new("cargo")
+        .arg("generate-lockfile")
+        .current_dir(copy.path())
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .context("Failed to run cargo")?;
+    if !status.success() {
+        bail!("Failed to generate Cargo.lock for {}", workspace.display());
+    }
+    let path = copy.path().join("Cargo.lock");
+    fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+/// Build the project at `root` (in `workspace`) once, with the variables in
+/// `env` and the lockfile `lock`, and find the file the ICE is in: a file of
+/// the project that rustc points to, the file that includes generated code
+/// that rustc points to, or the build script if that's what failed to compile
+pub(crate) fn locate_ice(
+    root: &Path,
+    workspace: &Path,
+    env: &[(String, String)],
+    lock: &[u8],
+    interesting: &Regex,
+) -> Result<Option<IceSite>> {
+    let copy = temp::copy_workspace(workspace).context("Failed to copy the workspace")?;
+    fs::write(copy.path().join("Cargo.lock"), lock).context("Failed to write Cargo.lock")?;
+    let target = temp::dir("icemelter-target-").context("Failed to create directory")?;
+    let project = copy
+        .path()
+        .join(root.strip_prefix(workspace).unwrap_or(Path::new("")));
+    let out = Command::new("cargo")
+        .args(["build", "--locked"])
+        .current_dir(&project)
+        .env("CARGO_TARGET_DIR", target.path())
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .stdin(Stdio::null())
+        .output()
+        .context("Failed to run cargo")?;
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    if !interesting.is_match(&stderr) {
+        debug!("No ICE when building {}", root.display());
+        return Ok(None);
+    }
+    let location = Regex::new(r"(?m)^\s*--> (?P<path>[^:\n]+):\d+:\d+").unwrap();
+    for c in location.captures_iter(&stderr) {
+        // Relative to the workspace, where Cargo runs rustc
+        let path = copy.path().join(&c["path"]);
+        if let Ok(file) = path.strip_prefix(&project) {
+            if file.extension().is_some_and(|e| e == "rs") && path.is_file() {
+                return Ok(Some(IceSite {
+                    file: file.to_path_buf(),
+                    generated: Vec::new(),
+                }));
+            }
+        }
+        let Some(name) = path.strip_prefix(target.path()).ok().and_then(out_dir_path) else {
+            continue;
+        };
+        let code = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read generated file {}", path.display()))?;
+        if let Some(file) = includer(&project, &name)? {
+            return Ok(Some(IceSite {
+                file,
+                generated: vec![(name, code)],
+            }));
+        }
+        debug!("Couldn't find the file that includes {}", name);
+    }
+    let unit =
+        Regex::new(r"could not compile `(?P<package>[^`]+)`[^(\n]*\((?P<unit>[^)]+)\)").unwrap();
+    let build_failed = unit.captures_iter(&stderr).any(|c| {
+        &c["unit"] == "build script" && package_name(root).is_some_and(|n| n == c["package"])
+    });
+    let script = build_script(root);
+    if build_failed && root.join(&script).is_file() {
+        return Ok(Some(IceSite {
+            file: script,
+            generated: Vec::new(),
+        }));
+    }
+    Ok(None)
+}
+
+/// Inline `include!`s of generated code, so that it gets reduced along with
+/// the file that includes it
+pub(crate) fn inline_generated(code: &str, generated: &[(String, String)]) -> String {
+    out_dir_include()
+        .replace_all(code, |c: &Captures| {
+            let Some((_, included)) = generated.iter().find(|(name, _)| *name == c["path"]) else {
+                return c[0].to_string();
+            };
+            let start = c.name("start").map(|m| m.as_str()).unwrap_or_default();
+            match (c.name("start"), c.name("semi")) {
+                // Items, drop the semicolon
+                (Some(_), Some(_)) => format!("{start}{included}"),
+                (_, semi) => format!(
+                    "{start}{included}{}",
+                    semi.map(|m| m.as_str()).unwrap_or_default()
+                ),
+            }
+        })
+        .into_owned()
+}
@@ -0,0 +1,753 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use tempfile::TempDir;
+use tracing::debug;
+use treereduce::Check;
+use wait_timeout::ChildExt;
+
+use crate::backtrace::Fingerprint;
+use crate::temp;
+use crate::trace::{self, Verdict};
+use crate::tui;
+
+/// With [`IceCheck::prefer_faster`], candidates are uninteresting if their
+/// check takes longer than this multiple of the input's...
+const SLOWER_FACTOR: f64 = 1.5;
+
+/// ...plus this, to allow for noise
+const SLOWER_NOISE: Duration = Duration::from_millis(50);
+
+/// With [`IceCheck::scale_timeout`], candidates get this multiple of the
+/// time the input took to check, scaled by their size...
+const TIMEOUT_SLACK: f64 = 3.0;
+
+/// ...but at least this long
+const TIMEOUT_FLOOR: Duration = Duration::from_millis(500);
+
+/// Number of containers started, to name them, see [`IceCheck::container`]
+static CONTAINERS: AtomicUsize = AtomicUsize::new(0);
+
+static ERROR_CODE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?m)^error\[E(?P<code>\d\d\d\d)\]: ").unwrap());
+
+/// The error codes (e.g., `0277`) in rustc's output, in order
+pub(crate) fn error_codes(stderr: &str) -> Vec<String> {
+    ERROR_CODE
+        .captures_iter(stderr)
+        .map(|c| c["code"].to_string())
+        .collect()
+}
+
+/// Whether an argument is a placeholder for the candidate file (`@@`, or
+/// `@@.ext` for a file with that extension)
+pub(crate) fn is_marker(s: &str) -> bool {
+    s.starts_with("@@")
+}
+
+/// Replace the placeholders in a command line with `file`, or append `file`
+/// if there are none (except in a Cargo project, see [`temp::in_project`])
+pub(crate) fn with_file(args: &[String], file: &str) -> Vec<String> {
+    let mut out = args
+        .iter()
+        .map(|a| if is_marker(a) { file } else { a })
+        .map(String::from)
+        .collect::<Vec<_>>();
+    if !args.iter().any(|a| is_marker(a)) && temp::project().is_none() {
+        out.push(String::from(file));
+    }
+    out
+}
+
+/// Like [`with_file`], but single-quote the arguments for a shell script
+/// (leaving `file` alone)
+pub(crate) fn shell_words(args: &[String], file: &str) -> String {
+    let quoted = args
+        .iter()
+        .map(|a| {
+            if is_marker(a) {
+                a.clone()
+            } else {
+                format!("'{a}'")
+            }
+        })
+        .collect::<Vec<_>>();
+    with_file(&quoted, file).join(" ")
+}
+
+/// Split a command line into words like a shell would, handling quotes and
+/// backslashes (but not variables and the like)
+pub(crate) fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = None::<String>;
+    let mut quote = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, '\\') | (Some('"'), '\\') => {
+                if let Some(next) = chars.next() {
+                    word.get_or_insert_with(String::new).push(next);
+                }
+            }
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (Some(q), c) if c == q => quote = None,
+            (_, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+/// An auxiliary crate, compiled as a library (or proc macro) before the main
+/// file
+#[derive(Clone, Debug)]
+pub(crate) struct Aux {
+    /// Crate name
+    pub(crate) name: String,
+    pub(crate) code: Vec<u8>,
+    pub(crate) proc_macro: bool,
+}
+
+impl Aux {
+    pub(crate) fn read(path: &Path, proc_macro: bool) -> io::Result<Self> {
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().replace('-', "_"))
+            .unwrap_or_default();
+        Ok(Aux {
+            name,
+            code: fs::read(path)?,
+            proc_macro,
+        })
+    }
+
+    fn crate_type(&self) -> &'static str {
+        if self.proc_macro {
+            "proc-macro"
+        } else {
+            "lib"
+        }
+    }
+}
+
+/// Runs a command (usually rustc) on a candidate and looks for an ICE in its
+/// stderr.
+///
+/// Similar to [`treereduce::CmdCheck`], but also sets environment variables
+/// for the command.
+#[derive(Clone, Debug)]
+pub(crate) struct IceCheck {
+    cmd: String,
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+    interesting_stderr: Option<Regex>,
+    uninteresting_stderr: Option<Regex>,
+    inherit: bool,
+    timeout: Option<Duration>,
+    aux: Vec<Aux>,
+    /// When reducing an auxiliary crate, its index and the main file
+    reducing_aux: Option<(usize, Vec<u8>)>,
+    /// How long the input took to check, see [`IceCheck::prefer_faster`]
+    check_time: Option<Duration>,
+    /// How long the input took to check, and its size, see
+    /// [`IceCheck::scale_timeout`]
+    baseline: Option<(Duration, usize)>,
+    /// Number of checks started, shared between clones
+    checks: Arc<AtomicUsize>,
+    /// Sorted error codes that candidates must emit, see
+    /// [`IceCheck::preserve_diagnostics`]
+    diagnostics: Option<Vec<String>>,
+    /// See [`IceCheck::require_fingerprint`]
+    fingerprint: Option<Fingerprint>,
+    /// See [`IceCheck::unless_reproduces`]
+    differential: Option<Box<IceCheck>>,
+    /// Whether to record candidates with `--trace`
+    traced: bool,
+}
+
+pub(crate) struct IceCheckState {
+    /// `None` if an auxiliary crate failed to build, or the check was
+    /// skipped from the `--tui`
+    child: Option<Child>,
+    stdout: Option<JoinHandle<Vec<u8>>>,
+    stderr: Option<JoinHandle<Vec<u8>>>,
+    _temp_file: Option<temp::Candidate>,
+    _aux_dir: Option<TempDir>,
+    started: Instant,
+    timeout: Option<Duration>,
+    /// The candidate, for `--trace` and `--tui`
+    candidate: Option<Vec<u8>>,
+    /// The check with [`IceCheck::unless_reproduces`], which runs alongside
+    differential: Option<Box<IceCheckState>>,
+    /// The verdict, once the check is done but `differential` isn't, see
+    /// [`IceCheck::try_wait`]
+    finished: Option<bool>,
+    /// The name of the Docker container the check runs in, if any
+    container: Option<String>,
+}
+
+impl IceCheckState {
+    /// A check that was never run, which is uninteresting
+    fn not_run(started: Instant, timeout: Option<Duration>, candidate: Option<Vec<u8>>) -> Self {
+        IceCheckState {
+            child: None,
+            stdout: None,
+            stderr: None,
+            _temp_file: None,
+            _aux_dir: None,
+            started,
+            timeout,
+            candidate,
+            differential: None,
+            finished: None,
+            container: None,
+        }
+    }
+
+    /// Killing `docker run` leaves the container running, kill it too
+    fn kill_container(&self) {
+        if let Some(name) = &self.container {
+            let _ = Command::new("docker")
+                .args(["kill", name])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+        }
+    }
+}
+
+/// Read a pipe on a separate thread, so that the child doesn't block on a full
+/// pipe while we wait for it.
+fn read_in_background<R: Read + Send + 'static>(
+    pipe: Option<R>,
+    echo: bool,
+) -> Option<JoinHandle<Vec<u8>>> {
+    pipe.map(|mut p| {
+        thread::spawn(move || {
+            let mut buf = Vec::new();
+            let _ = p.read_to_end(&mut buf);
+            if echo {
+                let _ = io::stderr().write_all(&buf);
+            }
+            buf
+        })
+    })
+}
+
+fn verdict(interesting: bool) -> Verdict {
+    if interesting {
+        Verdict::Interesting
+    } else {
+        Verdict::Uninteresting
+    }
+}
+
+fn join(handle: Option<JoinHandle<Vec<u8>>>) -> Vec<u8> {
+    handle.and_then(|h| h.join().ok()).unwrap_or_default()
+}
+
+impl IceCheck {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        cmd: String,
+        args: Vec<String>,
+        env: Vec<(String, String)>,
+        interesting_stderr: Option<Regex>,
+        uninteresting_stderr: Option<Regex>,
+        inherit: bool,
+        timeout: Option<Duration>,
+    ) -> Self {
+        IceCheck {
+            cmd,
+            args,
+            env,
+            interesting_stderr,
+            uninteresting_stderr,
+            inherit,
+            timeout,
+            aux: Vec::new(),
+            reducing_aux: None,
+            check_time: None,
+            baseline: None,
+            checks: Arc::default(),
+            diagnostics: None,
+            fingerprint: None,
+            differential: None,
+            traced: true,
+        }
+    }
+
+    /// Number of checks started by this check and its clones
+    pub(crate) fn checks(&self) -> usize {
+        self.checks.load(Ordering::SeqCst)
+    }
+
+    /// Instead of a fixed timeout, give each candidate time in proportion to
+    /// its size, relative to how long the input of `size` bytes took to check
+    /// (but no more than the fixed timeout)
+    pub(crate) fn scale_timeout(mut self, baseline: Duration, size: usize) -> Self {
+        self.baseline = Some((baseline, std::cmp::max(1, size)));
+        self
+    }
+
+    fn timeout_for(&self, candidate: &[u8]) -> Option<Duration> {
+        match self.baseline {
+            // The baseline is for the main file
+            Some((duration, size)) if self.reducing_aux.is_none() => {
+                let scale = TIMEOUT_SLACK * candidate.len() as f64 / size as f64;
+                let scaled = std::cmp::max(TIMEOUT_FLOOR, duration.mul_f64(scale));
+                // The fixed timeout is an upper bound
+                Some(self.timeout.map_or(scaled, |t| std::cmp::min(t, scaled)))
+            }
+            _ => self.timeout,
+        }
+    }
+
+    /// Treat candidates as uninteresting unless they emit exactly these error
+    /// codes, as many times each
+    pub(crate) fn preserve_diagnostics(mut self, mut codes: Vec<String>) -> Self {
+        codes.sort();
+        self.diagnostics = Some(codes);
+        self
+    }
+
+    fn same_diagnostics(&self, stderr: &[u8]) -> bool {
+        let Some(expected) = &self.diagnostics else {
+            return true;
+        };
+        let mut codes = error_codes(&String::from_utf8_lossy(stderr));
+        codes.sort();
+        if &codes != expected {
+            debug!("Error codes changed: {:?}", codes);
+            return false;
+        }
+        true
+    }
+
+    /// Treat candidates as uninteresting unless their ICE has the same
+    /// location (or message, without one) and query stack
+    pub(crate) fn require_fingerprint(mut self, fingerprint: Fingerprint) -> Self {
+        self.fingerprint = Some(fingerprint);
+        self
+    }
+
+    fn same_fingerprint(&self, stderr: &[u8]) -> bool {
+        let Some(expected) = &self.fingerprint else {
+            return true;
+        };
+        let actual = Fingerprint::parse(&String::from_utf8_lossy(stderr));
+        if let Some(part) = expected.mismatch(&actual) {
+            debug!("ICE {} changed: {}", part, actual);
+            return false;
+        }
+        true
+    }
+
+    /// Treat candidates that take much longer to check than the input (which
+    /// took `check_time`) as uninteresting, so that the result doesn't compile
+    /// more slowly than the input
+    pub(crate) fn prefer_faster(mut self, check_time: Duration) -> Self {
+        self.check_time = Some(check_time);
+        self
+    }
+
+    /// Treat candidates as uninteresting if `other` (e.g., with a baseline
+    /// toolchain) finds them interesting too
+    pub(crate) fn unless_reproduces(mut self, mut other: IceCheck) -> Self {
+        other.traced = false;
+        self.differential = Some(Box::new(other));
+        self
+    }
+
+    /// Build these auxiliary crates (in order) before the main file
+    pub(crate) fn with_aux(mut self, aux: Vec<Aux>) -> Self {
+        self.differential = self.differential.map(|d| Box::new(d.with_aux(aux.clone())));
+        self.aux = aux;
+        self
+    }
+
+    /// Make the candidate the `index`th auxiliary crate instead of the main
+    /// file
+    pub(crate) fn reducing_aux(mut self, index: usize, main: Vec<u8>) -> Self {
+        self.differential = self
+            .differential
+            .map(|d| Box::new(d.reducing_aux(index, main.clone())));
+        self.reducing_aux = Some((index, main));
+        self
+    }
+
+    fn wait_timeout(
+        &self,
+        child: &mut Child,
+        timeout: Option<Duration>,
+    ) -> io::Result<Option<ExitStatus>> {
+        match timeout {
+            Some(to) => {
+                let status = child.wait_timeout(to)?;
+                if status.is_none() {
+                    child.kill()?;
+                    child.wait()?;
+                }
+                Ok(status)
+            }
+            None => child.wait().map(Some),
+        }
+    }
+
+    /// Build the auxiliary crates into a temporary directory, returning the
+    /// directory and arguments for using them, or `None` if one failed to
+    /// build
+    fn build_aux(&self, candidate: &[u8]) -> io::Result<Option<(TempDir, Vec<OsString>)>> {
+        let dir = temp::dir("icemelter-aux-")?;
+        // Arguments without the file, and without those that are about the
+        // main crate (e.g., a test harness doesn't make sense for a library)
+        let mut base = Vec::with_capacity(self.args.len());
+        let mut args = self.args.iter();
+        while let Some(arg) = args.next() {
+            if ["--crate-type", "--crate-name", "-o"].contains(&arg.as_str()) {
+                args.next();
+            } else if !is_marker(arg)
+                && arg != "--test"
+                && !arg.starts_with("--crate-type=")
+                && !arg.starts_with("--crate-name=")
+            {
+                base.push(OsString::from(arg));
+            }
+        }
+        let mut externs = vec![OsString::from("-L"), dir.path().as_os_str().to_os_string()];
+        for (i, aux) in self.aux.iter().enumerate() {
+            let code = match &self.reducing_aux {
+                Some((index, _)) if *index == i => candidate,
+                _ => &aux.code,
+            };
+            let path = dir.path().join(format!("{}.rs", aux.name));
+            fs::write(&path, code)?;
+            let mut child = Command::new(&self.cmd)
+                .args(&base)
+                .args(&externs)
+                .args(if aux.proc_macro {
+                    &["--extern", "proc_macro"][..]
+                } else {
+                    &[]
+                })
+                .args(["--crate-type", aux.crate_type(), "--crate-name", &aux.name])
+                .arg("--out-dir")
+                .arg(dir.path())
+                .arg(&path)
+                .envs(self.env.iter().map(|(k, v)| (k, v)))
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()?;
+            if !self
+                .wait_timeout(&mut child, self.timeout)?
+                .map(|s| s.success())
+                .unwrap_or(false)
+            {
+                debug!("Auxiliary crate {} failed to build", aux.name);
+                return Ok(None);
+            }
+            // Proc macros are dynamic libraries, let rustc find the file
+            externs.extend([OsString::from("--extern"), OsString::from(&aux.name)]);
+        }
+        Ok(Some((dir, externs)))
+    }
+
+    /// Replace `@@` (and `@@.ext`) with the path to a temporary file
+    /// containing the candidate
+    fn args_with_file(
+        &self,
+        candidate: &[u8],
+    ) -> io::Result<(Option<temp::Candidate>, Vec<OsString>)> {
+        let mut temp_file: Option<temp::Candidate> = None;
+        let mut args = Vec::with_capacity(self.args.len());
+        for arg in &self.args {
+            if !is_marker(arg) {
+                args.push(OsString::from(arg));
+                continue;
+            }
+            if temp_file.is_none() {
+                temp_file = Some(temp::candidate(&arg["@@".len()..], candidate)?);
+            }
+            if let Some(f) = &temp_file {
+                args.push(f.path().as_os_str().to_os_string());
+            }
+        }
+        // Cargo finds the file itself
+        if temp_file.is_none() && temp::project().is_some() {
+            temp_file = Some(temp::candidate(".rs", candidate)?);
+        }
+        Ok((temp_file, args))
+    }
+
+    /// The file that candidates replace, for `--trace`
+    fn file(&self) -> &str {
+        match &self.reducing_aux {
+            Some((index, _)) => &self.aux[*index].name,
+            None => trace::MAIN,
+        }
+    }
+
+    /// Whether an interesting check was fast enough, see
+    /// [`IceCheck::prefer_faster`]
+    fn fast_enough(&self, started: Instant) -> bool {
+        let Some(check_time) = self.check_time else {
+            return true;
+        };
+        let elapsed = started.elapsed();
+        if elapsed > check_time.mul_f64(SLOWER_FACTOR) + SLOWER_NOISE {
+            debug!("Check took {}ms, too slow", elapsed.as_millis());
+            return false;
+        }
+        true
+    }
+
+    /// Whether the check was interesting, with the same diagnostics and ICE,
+    /// and fast enough
+    fn verdict(&self, started: Instant, stderr: &[u8]) -> bool {
+        self.is_interesting(stderr)
+            && self.same_diagnostics(stderr)
+            && self.same_fingerprint(stderr)
+            && self.fast_enough(started)
+    }
+
+    /// Cancel the check with [`IceCheck::unless_reproduces`], if it's running
+    fn cancel_differential(&self, state: &mut IceCheckState) -> io::Result<()> {
+        match (&self.differential, state.differential.take()) {
+            (Some(other), Some(other_state)) => other.cancel(*other_state),
+            _ => Ok(()),
+        }
+    }
+
+    /// A name for the container, if the check is `docker run`
+    fn container(&self) -> Option<String> {
+        (self.cmd == "docker" && self.args.first().is_some_and(|a| a == "run")).then(|| {
+            let n = CONTAINERS.fetch_add(1, Ordering::SeqCst);
+            format!("icemelter-{}-{n}", std::process::id())
+        })
+    }
+
+    /// Whether an interesting candidate is interesting with
+    /// [`IceCheck::unless_reproduces`] too
+    fn reproduces_elsewhere(&self, state: &mut IceCheckState) -> io::Result<bool> {
+        let (Some(other), Some(other_state)) = (&self.differential, state.differential.take())
+        else {
+            return Ok(false);
+        };
+        let reproduces = other.wait(*other_state)?;
+        if reproduces {
+            debug!("Also reproduces with {}", other.args.join(" "));
+        }
+        Ok(reproduces)
+    }
+
+    fn record(&self, started: Instant, candidate: Option<Vec<u8>>, verdict: Verdict) {
+        if !self.traced {
+            return;
+        }
+        if let Some(candidate) = candidate {
+            let command = std::iter::once(self.cmd.clone())
+                .chain(self.args.iter().cloned())
+                .collect();
+            trace::record(self.file(), command, &candidate, verdict, started.elapsed());
+            tui::record(self.file(), &candidate, verdict, started.elapsed());
+        }
+    }
+
+    fn is_interesting(&self, stderr: &[u8]) -> bool {
+        let err_str = String::from_utf8_lossy(stderr);
+        let stderr_match = self
+            .interesting_stderr
+            .as_ref()
+            .map(|rx| rx.is_match(&err_str))
+            .unwrap_or(false);
+        let stderr_unmatch = self
+            .uninteresting_stderr
+            .as_ref()
+            .map(|rx| rx.is_match(&err_str))
+            .unwrap_or(false);
+        let is_interesting = stderr_match && !stderr_unmatch;
+        debug!(
+            stderr_match,
+            stderr_unmatch, is_interesting, "Interesting? {}", is_interesting
+        );
+        is_interesting
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn wait_with_output(
+        &self,
+        mut state: IceCheckState,
+    ) -> io::Result<(bool, Option<ExitStatus>, Vec<u8>, Vec<u8>)> {
+        let status = match &mut state.child {
+            None => {
+                self.record(state.started, state.candidate, Verdict::Uninteresting);
+                return Ok((false, None, Vec::new(), Vec::new()));
+            }
+            Some(child) => match self.wait_timeout(child, state.timeout)? {
+                Some(s) => s,
+                None => {
+                    state.kill_container();
+                    self.cancel_differential(&mut state)?;
+                    self.record(state.started, state.candidate, Verdict::Timeout);
+                    return Ok((false, None, join(state.stdout), join(state.stderr)));
+                }
+            },
+        };
+        let stdout = join(state.stdout.take());
+        let stderr = join(state.stderr.take());
+        let interesting = if self.verdict(state.started, &stderr) {
+            !self.reproduces_elsewhere(&mut state)?
+        } else {
+            self.cancel_differential(&mut state)?;
+            false
+        };
+        self.record(state.started, state.candidate, verdict(interesting));
+        Ok((interesting, Some(status), stdout, stderr))
+    }
+}
+
+impl Check for IceCheck {
+    type State = IceCheckState;
+
+    fn start(&self, stdin: &[u8]) -> io::Result<Self::State> {
+        if !tui::before_check() {
+            return Ok(IceCheckState::not_run(Instant::now(), None, None));
+        }
+        self.checks.fetch_add(1, Ordering::SeqCst);
+        let started = Instant::now();
+        let timeout = self.timeout_for(stdin);
+        let candidate =
+            (self.traced && (trace::enabled() || tui::enabled())).then(|| stdin.to_vec());
+        let (aux_dir, aux_args) = if self.aux.is_empty() {
+            (None, Vec::new())
+        } else {
+            match self.build_aux(stdin)? {
+                Some((dir, args)) => (Some(dir), args),
+                None => return Ok(IceCheckState::not_run(started, timeout, candidate)),
+            }
+        };
+        let main = match &self.reducing_aux {
+            Some((_, main)) => main.as_slice(),
+            None => stdin,
+        };
+        let (temp_file, mut args) = self.args_with_file(main)?;
+        let container = self.container();
+        if let Some(name) = &container {
+            args.splice(1..1, [OsString::from("--name"), OsString::from(name)]);
+        }
+        let mut cmd = Command::new(&self.cmd);
+        if let Some(dir) = temp_file.as_ref().and_then(temp::Candidate::project) {
+            cmd.current_dir(dir);
+        }
+        // Before the environment from the command line, which may override it
+        if let Some(dir) = temp_file.as_ref().and_then(temp::Candidate::target) {
+            cmd.env("CARGO_TARGET_DIR", dir);
+        }
+        let mut child = cmd
+            .args(aux_args)
+            .args(args)
+            .envs(self.env.iter().map(|(k, v)| (k, v)))
+            .stdin(if temp_file.is_some() {
+                Stdio::null()
+            } else {
+                Stdio::piped()
+            })
+            .stdout(if self.inherit {
+                Stdio::inherit()
+            } else {
+                Stdio::null()
+            })
+            .stderr(Stdio::piped())
+            .spawn()?;
+        if let Some(mut sin) = child.stdin.take() {
+            sin.write_all(main)?;
+        }
+        let stdout = read_in_background(child.stdout.take(), false);
+        let stderr = read_in_background(child.stderr.take(), self.inherit);
+        let differential = match &self.differential {
+            Some(other) => Some(Box::new(other.start(stdin)?)),
+            None => None,
+        };
+        Ok(IceCheckState {
+            child: Some(child),
+            stdout,
+            stderr,
+            _temp_file: temp_file,
+            _aux_dir: aux_dir,
+            started,
+            timeout,
+            candidate,
+            differential,
+            finished: None,
+            container,
+        })
+    }
+
+    fn cancel(&self, mut state: Self::State) -> io::Result<()> {
+        self.cancel_differential(&mut state)?;
+        if let Some(child) = &mut state.child {
+            child.kill()?;
+            child.wait()?;
+        }
+        state.kill_container();
+        Ok(())
+    }
+
+    fn try_wait(&self, state: &mut Self::State) -> io::Result<Option<bool>> {
+        let child = match &mut state.child {
+            None => {
+                self.record(
+                    state.started,
+                    state.candidate.take(),
+                    Verdict::Uninteresting,
+                );
+                return Ok(Some(false));
+            }
+            Some(c) => c,
+        };
+        let interesting = match state.finished {
+            Some(interesting) => interesting,
+            None => match child.try_wait()? {
+                None => return Ok(None),
+                Some(_) => {
+                    let stderr = join(state.stderr.take());
+                    let interesting = self.verdict(state.started, &stderr);
+                    state.finished = Some(interesting);
+                    interesting
+                }
+            },
+        };
+        let interesting = match (&self.differential, &mut state.differential) {
+            (Some(other), Some(other_state)) if interesting => {
+                match other.try_wait(other_state)? {
+                    None => return Ok(None),
+                    Some(reproduces) => !reproduces,
+                }
+            }
+            _ => {
+                self.cancel_differential(state)?;
+                interesting
+            }
+        };
+        self.record(state.started, state.candidate.take(), verdict(interesting));
+        Ok(Some(interesting))
+    }
+
+    fn wait(&self, state: Self::State) -> io::Result<bool> {
+        Ok(self.wait_with_output(state)?.0)
+    }
+}
@@ -0,0 +1,102 @@
+//! Adding the reduced file to rust-lang/rust's `tests/crashes`, see
+//! `--crashes-pr`
+
+#[cfg(feature = "fetch")]
+use std::thread;
+#[cfg(feature = "fetch")]
+use std::time::Duration;
+
+#[cfg(feature = "fetch")]
+use anyhow::{Context, Result};
+#[cfg(feature = "fetch")]
+use tracing::{debug, info};
+
+use crate::bisect_local::without_sysroot;
+use crate::check;
+#[cfg(feature = "fetch")]
+use crate::check::Aux;
+#[cfg(feature = "fetch")]
+use crate::{github, post};
+
+/// A `tests/crashes` test, `code` should already have any `aux-build`
+/// directives
+///
+/// `args` are rustc's arguments, without the program.
+pub(crate) fn test_file(issue: usize, args: &[String], code: &[u8]) -> Vec<u8> {
+    let mut flags = Vec::new();
+    let mut edition = None;
+    let args = without_sysroot(args);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--edition" {
+            edition = args.next().cloned();
+        } else if let Some(e) = arg.strip_prefix("--edition=") {
+            edition = Some(e.to_string());
+        } else if !arg.starts_with('+') && !check::is_marker(arg) {
+            flags.push(arg.as_str());
+        }
+    }
+    let mut test = format!("//@ known-bug: #{issue}\n");
+    if !flags.is_empty() {
+        test.push_str(&format!("//@ compile-flags: {}\n", flags.join(" ")));
+    }
+    if let Some(e) = edition {
+        test.push_str(&format!("//@ edition:{e}\n"));
+    }
+    let mut test = test.into_bytes();
+    test.extend(code);
+    test
+}
+
+/// Open a draft PR adding the test (and auxiliary crates) to `tests/crashes`,
+/// from a branch of `fork` (created if not given), after confirmation (unless
+/// `yes`)
+#[cfg(feature = "fetch")]
+pub(crate) fn open_pr(
+    issue: usize,
+    test: &[u8],
+    aux: &[Aux],
+    fork: Option<&str>,
+    yes: bool,
+) -> Result<()> {
+    let path = format!("tests/crashes/{issue}.rs");
+    let question = format!("Open a draft PR adding {path} to rust-lang/rust?");
+    if !yes && !post::confirm(&String::from_utf8_lossy(test), &question)? {
+        info!("Not opening a PR");
+        return Ok(());
+    }
+    let config = github::Config::load()?;
+    let fork = match fork {
+        Some(f) => f.to_string(),
+        None => github::create_fork(&config).context("Failed to fork rust-lang/rust")?,
+    };
+    let sha = github::get_branch_sha(&config, "rust-lang/rust", "master")
+        .context("Failed to find rust-lang/rust's master branch")?;
+    let branch = format!("crash-{issue}");
+    // New forks take a moment to become usable
+    let mut tries = 0;
+    while let Err(e) = github::create_branch(&config, &fork, &branch, &sha) {
+        tries += 1;
+        if tries == 5 {
+            return Err(e).with_context(|| format!("Failed to create branch {branch} in {fork}"));
+        }
+        debug!("Failed to create branch, retrying: {}", e);
+        thread::sleep(Duration::from_secs(2));
+    }
+    let message = format!("Add crash test for #{issue}");
+    github::create_file(&config, &fork, &branch, &path, test, &message)
+        .with_context(|| format!("Failed to add {path}"))?;
+    for a in aux {
+        let aux_path = format!("tests/crashes/auxiliary/{}.rs", a.name);
+        github::create_file(&config, &fork, &branch, &aux_path, &a.code, &message)
+            .with_context(|| format!("Failed to add {aux_path}"))?;
+    }
+    let owner = fork.split('/').next().unwrap_or(&fork);
+    let body = format!(
+        "Adds a test for #{issue}, reduced with [Icemelter](https://github.com/langston-barrett/icemelter)."
+    );
+    let url = github::create_draft_pull(&config, &message, &format!("{owner}:{branch}"), &body)
+        .context("Failed to open a PR")?;
+    info!("Opened draft PR: {}", url);
+    Ok(())
+}
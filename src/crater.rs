@@ -0,0 +1,226 @@
+//! Setting up a reduction from a crater log, see `--crater`
+//!
+//! Crater logs (e.g., `https://crater-reports.s3.amazonaws.com/EXPERIMENT/TOOLCHAIN/reg/CRATE-VERSION/log.txt`)
+//! show the Cargo command and the crates it compiled. The crate that was
+//! being compiled when rustc crashed is downloaded and reduced as a Cargo
+//! project.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use tracing::{debug, info, warn};
+
+use crate::http::CLIENT;
+use crate::temp;
+
+#[derive(Debug)]
+enum Crate {
+    Registry {
+        name: String,
+        version: String,
+    },
+    /// `owner/repo`
+    GitHub(String),
+}
+
+impl Crate {
+    /// From a path segment like `reg/foo-0.1.0` or `gh/owner.repo`
+    fn from_segments(kind: &str, id: &str) -> Option<Self> {
+        match kind {
+            "reg" => {
+                let rx = Regex::new(r"^(.+)-(\d+\.\d+\.\d+\S*)$").unwrap();
+                let c = rx.captures(id)?;
+                Some(Crate::Registry {
+                    name: c[1].to_string(),
+                    version: c[2].to_string(),
+                })
+            }
+            "gh" => id
+                .split_once('.')
+                .map(|(o, r)| Crate::GitHub(format!("{o}/{r}"))),
+            _ => None,
+        }
+    }
+
+    fn dir_name(&self) -> String {
+        match self {
+            Crate::Registry { name, version } => format!("{name}-{version}"),
+            Crate::GitHub(repo) => repo.replace('/', "."),
+        }
+    }
+}
+
+fn is_url(source: &str) -> bool {
+    source.starts_with(crate::CRATER_REPORTS)
+}
+
+fn read_log(source: &str) -> Result<String> {
+    if !is_url(source) {
+        return fs::read_to_string(source).with_context(|| format!("Failed to read {source}"));
+    }
+    let url = if source.ends_with("log.txt") {
+        source.to_string()
+    } else if source.ends_with('/') {
+        format!("{source}log.txt")
+    } else {
+        bail!("Pass the log of one crate (log.txt), not the whole report");
+    };
+    CLIENT
+        .get(&url)
+        .send()
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.text())
+        .with_context(|| format!("Failed to download {url}"))
+}
+
+/// The crate that crater tested, from the URL or the log
+fn tested_crate(source: &str, log: &str) -> Option<Crate> {
+    let url = Regex::new(r"/(reg|gh)/([^/]+)/(?:log\.txt)?$").unwrap();
+    if let Some(c) = url.captures(source) {
+        return Crate::from_segments(&c[1], &c[2]);
+    }
+    let fetching = Regex::new(r"fetching crate (\S+) (\d+\.\d+\.\d+\S*?)\.*$").unwrap();
+    let cloning = Regex::new(r"https://github\.com/([\w.-]+/[\w.-]+?)(?:\.git)?(?:\s|$)").unwrap();
+    log.lines().find_map(|l| {
+        if let Some(c) = fetching.captures(l) {
+            return Some(Crate::Registry {
+                name: c[1].to_string(),
+                version: c[2].to_string(),
+            });
+        }
+        cloning.captures(l).map(|c| Crate::GitHub(c[1].to_string()))
+    })
+}
+
+/// The dependency that rustc crashed on, if it wasn't the tested crate
+fn crashed_dependency(log: &str) -> Option<Crate> {
+    let compiling =
+        Regex::new(r"\b(?:Compiling|Checking|Documenting) (\S+) v(\S+)( \([^)]*\))?").unwrap();
+    let failed = Regex::new(r"could not compile `([^`]+)`").unwrap();
+    let failed = failed.captures(log).map(|c| c[1].to_string());
+    let mut crashed = None;
+    for line in log.lines() {
+        if failed.is_none() && line.contains("internal compiler error") {
+            break;
+        }
+        if let Some(c) = compiling.captures(line) {
+            if failed.as_ref().is_some_and(|f| *f != c[1]) {
+                continue;
+            }
+            // Local paths are the tested crate (or its workspace)
+            crashed = c.get(3).is_none().then(|| Crate::Registry {
+                name: c[1].to_string(),
+                version: c[2].to_string(),
+            });
+        }
+    }
+    crashed
+}
+
+/// The Cargo command crater ran, e.g., `cargo +nightly-2024-01-02 check`
+fn command(log: &str) -> Vec<String> {
+    let rx = Regex::new(r#""cargo" "\+([^"]+)" "(\w+)""#).unwrap();
+    let Some(c) = rx.captures(log) else {
+        return vec![String::from("cargo"), String::from("build")];
+    };
+    let mut cmd = vec![String::from("cargo")];
+    let toolchain = &c[1];
+    if Regex::new(r"^(nightly|beta|stable)(-\d{4}-\d{2}-\d{2})?$|^\d+\.\d+")
+        .unwrap()
+        .is_match(toolchain)
+    {
+        cmd.push(format!("+{toolchain}"));
+    } else {
+        warn!(
+            "Crater used the toolchain {}, pass it in the command if the ICE doesn't reproduce",
+            toolchain
+        );
+    }
+    // Crater only builds tests with `cargo test`, which would run them
+    let subcommand = match &c[2] {
+        "test" => "build",
+        s => s,
+    };
+    cmd.push(subcommand.to_string());
+    if &c[2] == "test" {
+        cmd.push(String::from("--tests"));
+    }
+    cmd
+}
+
+fn download(krate: &Crate, dir: &Path) -> Result<()> {
+    match krate {
+        Crate::Registry { name, version } => {
+            let url = format!("https://static.crates.io/crates/{name}/{name}-{version}.crate");
+            info!("Downloading {} {}...", name, version);
+            let bytes = CLIENT
+                .get(&url)
+                .send()
+                .and_then(|r| r.error_for_status())
+                .and_then(|r| r.bytes())
+                .with_context(|| format!("Failed to download {url}"))?;
+            let tmp = temp::dir("icemelter-crate")?;
+            let archive = tmp.path().join("crate.tar.gz");
+            fs::write(&archive, &bytes).context("Failed to write crate")?;
+            // Crates unpack to NAME-VERSION, see [`Crate::dir_name`]
+            let parent = match dir.parent() {
+                Some(p) if !p.as_os_str().is_empty() => p,
+                _ => Path::new("."),
+            };
+            let status = Command::new("tar")
+                .arg("-xzf")
+                .arg(&archive)
+                .arg("-C")
+                .arg(parent)
+                .status()
+                .context("Failed to run tar")?;
+            if !status.success() {
+                bail!("Failed to unpack {name} {version}");
+            }
+            if !dir.join("Cargo.toml").is_file() {
+                bail!("{name} {version} didn't unpack to {}", dir.display());
+            }
+        }
+        Crate::GitHub(repo) => {
+            info!("Cloning {}...", repo);
+            let status = Command::new("git")
+                .args(["clone", "--depth", "1"])
+                .arg(format!("https://github.com/{repo}"))
+                .arg(dir)
+                .status()
+                .context("Failed to run git")?;
+            if !status.success() {
+                bail!("Failed to clone {repo}");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Download the crate that crashed in a crater log (a URL or file), returning
+/// the Cargo project and the command that crater ran
+pub(crate) fn setup(source: &str) -> Result<(PathBuf, Vec<String>)> {
+    let log = read_log(source)?;
+    let krate = match crashed_dependency(&log) {
+        Some(dep) => {
+            info!("The ICE is in the dependency {}", dep.dir_name());
+            dep
+        }
+        None => tested_crate(source, &log).context("Couldn't find the crate in the crater log")?,
+    };
+    debug!("Crate: {:?}", krate);
+    warn!(
+        "Reducing {} builds it, running its build script and procedural macros",
+        krate.dir_name()
+    );
+    let dir = PathBuf::from(krate.dir_name());
+    if dir.join("Cargo.toml").is_file() {
+        info!("Using the existing copy in {}", dir.display());
+    } else {
+        download(&krate, &dir)?;
+    }
+    Ok((dir, command(&log)))
+}
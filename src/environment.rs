@@ -0,0 +1,72 @@
+//! The environment of a run, for reports; environment-specific ICEs (e.g.,
+//! with linkers or targets) are hard to triage without it
+
+use std::fmt;
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub(crate) struct Environment {
+    /// E.g., `linux 6.1.0`
+    pub(crate) os: String,
+    pub(crate) arch: String,
+    /// Host triple, from `rustc -vV`
+    pub(crate) host: Option<String>,
+    /// From `rustc -vV`
+    pub(crate) llvm: Option<String>,
+    /// Toolchains used in the run, e.g., the one under test and `--baseline`
+    pub(crate) toolchains: Vec<String>,
+    pub(crate) locale: Option<String>,
+}
+
+fn output(cmd: &str, args: &[&str]) -> Option<String> {
+    let out = Command::new(cmd).args(args).output().ok()?;
+    let out = String::from_utf8_lossy(&out.stdout).trim().to_string();
+    (!out.is_empty()).then_some(out)
+}
+
+/// The value of a `key: value` line in `rustc -vV` output
+fn version_field(version: &str, key: &str) -> Option<String> {
+    version
+        .lines()
+        .find_map(|l| l.strip_prefix(key)?.strip_prefix(':'))
+        .map(|v| v.trim().to_string())
+}
+
+/// rustup's default toolchain for the current directory
+pub(crate) fn active_toolchain() -> Option<String> {
+    let active = output("rustup", &["show", "active-toolchain"])?;
+    active.split_whitespace().next().map(String::from)
+}
+
+/// `rustc_version` is the output of `rustc -vV`
+pub(crate) fn capture(rustc_version: &str, toolchains: Vec<String>) -> Environment {
+    let os_version = output("uname", &["-r"]);
+    Environment {
+        os: match os_version {
+            Some(v) => format!("{} {}", std::env::consts::OS, v),
+            None => std::env::consts::OS.to_string(),
+        },
+        arch: std::env::consts::ARCH.to_string(),
+        host: version_field(rustc_version, "host"),
+        llvm: version_field(rustc_version, "LLVM version"),
+        toolchains,
+        locale: ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|v| std::env::var(v).ok())
+            .find(|v| !v.is_empty()),
+    }
+}
+
+/// Without the host and LLVM version, which reports show with the rustc
+/// version
+impl fmt::Display for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "OS: {} ({})", self.os, self.arch)?;
+        if !self.toolchains.is_empty() {
+            writeln!(f, "Toolchains: {}", self.toolchains.join(", "))?;
+        }
+        write!(f, "Locale: {}", self.locale.as_deref().unwrap_or("unknown"))
+    }
+}
@@ -0,0 +1,108 @@
+//! Simplifying `--extern` dependencies of the reduced file, by dropping them
+//! or replacing them with empty stub crates
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result};
+use tracing::debug;
+
+/// A `--extern NAME[=PATH]` (or `--extern=NAME[=PATH]`) flag
+#[derive(Clone, Debug)]
+pub(crate) struct Extern {
+    /// Index of the flag in the command line
+    index: usize,
+    /// Whether the value is a separate argument
+    separate: bool,
+    pub(crate) name: String,
+    pub(crate) path: Option<String>,
+}
+
+impl Extern {
+    fn len(&self) -> usize {
+        if self.separate {
+            2
+        } else {
+            1
+        }
+    }
+}
+
+/// The `--extern` flags in a rustc command line
+pub(crate) fn find(check: &[String]) -> Vec<Extern> {
+    let mut externs = Vec::new();
+    let mut i = 0;
+    while i < check.len() {
+        let (value, separate) = if check[i] == "--extern" {
+            match check.get(i + 1) {
+                Some(v) => (v.as_str(), true),
+                None => break,
+            }
+        } else if let Some(v) = check[i].strip_prefix("--extern=") {
+            (v, false)
+        } else {
+            i += 1;
+            continue;
+        };
+        let (name, path) = match value.split_once('=') {
+            Some((n, p)) => (n.to_string(), Some(p.to_string())),
+            None => (value.to_string(), None),
+        };
+        let e = Extern {
+            index: i,
+            separate,
+            name,
+            path,
+        };
+        i += e.len();
+        externs.push(e);
+    }
+    externs
+}
+
+/// The command line without the flag
+pub(crate) fn without(check: &[String], e: &Extern) -> Vec<String> {
+    let mut cmd = check.to_vec();
+    cmd.drain(e.index..e.index + e.len());
+    cmd
+}
+
+/// The command line with the flag pointing at `path`
+pub(crate) fn with_path(check: &[String], e: &Extern, path: &Path) -> Vec<String> {
+    let mut cmd = without(check, e);
+    cmd.splice(
+        e.index..e.index,
+        [
+            String::from("--extern"),
+            format!("{}={}", e.name, path.display()),
+        ],
+    );
+    cmd
+}
+
+/// Compile an empty library crate named `name` into `dir` with `rustc`, a
+/// command line that should have the same toolchain, target, and edition as
+/// the check
+pub(crate) fn build_stub(rustc: &[String], name: &str, dir: &Path) -> Result<PathBuf> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    let src = dir.join(format!("{name}.rs"));
+    fs::write(&src, "").with_context(|| format!("Failed to write {}", src.display()))?;
+    let out = dir.join(format!("lib{name}.rlib"));
+    let status = Command::new(&rustc[0])
+        .args(&rustc[1..])
+        .args(["--crate-type", "lib", "--crate-name", name, "-o"])
+        .arg(&out)
+        .arg(&src)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .context("Failed to run rustc")?;
+    let _ = fs::remove_file(&src);
+    if !status.success() {
+        anyhow::bail!("Failed to build stub crate {name}");
+    }
+    debug!("Built stub crate {}", out.display());
+    Ok(out)
+}
@@ -0,0 +1,258 @@
+//! Inline `include!`, `include_str!`, `include_bytes!`, and out-of-line
+//! modules, so that the input is a single file that can be reduced.
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Context, Result};
+use regex::{Captures, Regex};
+use tracing::{debug, warn};
+
+/// Bail out on (probably) cyclic includes
+const MAX_DEPTH: usize = 16;
+
+struct Regexes {
+    include: Regex,
+    include_str: Regex,
+    include_bytes: Regex,
+    path_mod: Regex,
+    mod_decl: Regex,
+}
+
+impl Regexes {
+    fn new() -> Result<Self> {
+        let rx = |r: &str| Regex::new(r).context("Internal error: bad flattening regex");
+        Ok(Regexes {
+            include: rx(
+                r#"(?m)(?P<start>^[ \t]*)?include!\s*\(\s*"(?P<path>[^"]+)"\s*\)(?P<semi>\s*;)?"#,
+            )?,
+            include_str: rx(r#"include_str!\s*\(\s*"(?P<path>[^"]+)"\s*\)"#)?,
+            include_bytes: rx(r#"include_bytes!\s*\(\s*"(?P<path>[^"]+)"\s*\)"#)?,
+            path_mod: rx(
+                r#"(?m)^(?P<indent>[ \t]*)#\[path\s*=\s*"(?P<path>[^"]+)"\]\s*(?P<vis>pub(?:\([^)]*\))?\s+)?mod\s+(?P<name>\w+)\s*;"#,
+            )?,
+            mod_decl: rx(
+                r"(?m)^(?P<indent>[ \t]*)(?P<vis>pub(?:\([^)]*\))?\s+)?mod\s+(?P<name>\w+)\s*;",
+            )?,
+        })
+    }
+}
+
+fn read(path: &Path) -> Option<String> {
+    match fs::read(path) {
+        Ok(bytes) => Some(String::from_utf8_lossy(&bytes).into_owned()),
+        Err(e) => {
+            warn!("Couldn't read {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// A raw string literal that can hold `s`
+fn raw_string(s: &str) -> String {
+    let mut hashes = String::from("#");
+    while s.contains(&format!("\"{hashes}")) {
+        hashes.push('#');
+    }
+    format!("r{hashes}\"{s}\"{hashes}")
+}
+
+fn byte_string(bytes: &[u8]) -> String {
+    let escaped = bytes
+        .iter()
+        .flat_map(|b| std::ascii::escape_default(*b))
+        .map(char::from)
+        .collect::<String>();
+    format!("b\"{escaped}\"")
+}
+
+#[derive(Default)]
+struct State {
+    /// Number of files inlined
+    resolved: usize,
+    /// Contents of `include_str!` and `include_bytes!`, left as placeholders
+    /// until the end so that they aren't flattened themselves
+    literals: Vec<String>,
+    /// Only inline files in this (canonical) directory, see [`flatten_within`]
+    within: Option<PathBuf>,
+}
+
+impl State {
+    fn literal(&mut self, lit: String) -> String {
+        self.resolved += 1;
+        self.literals.push(lit);
+        placeholder(self.literals.len() - 1)
+    }
+
+    fn allows(&self, path: &Path) -> bool {
+        let Some(within) = &self.within else {
+            return true;
+        };
+        if path.canonicalize().is_ok_and(|p| p.starts_with(within)) {
+            return true;
+        }
+        warn!("Not inlining {}, it's outside of the input", path.display());
+        false
+    }
+
+    fn read(&self, path: &Path) -> Option<String> {
+        self.allows(path).then(|| read(path)).flatten()
+    }
+}
+
+fn placeholder(i: usize) -> String {
+    format!("__icemelter_include_{i}__")
+}
+
+fn module(c: &Captures, inner: &str) -> String {
+    format!(
+        "{}{}mod {} {{\n{}\n{}}}",
+        &c["indent"],
+        c.name("vis").map(|m| m.as_str()).unwrap_or_default(),
+        &c["name"],
+        inner,
+        &c["indent"],
+    )
+}
+
+/// `dir` is where `include!`s are resolved, `mod_dir` is where modules are
+fn flatten_in(
+    rx: &Regexes,
+    code: &str,
+    dir: &Path,
+    mod_dir: &Path,
+    depth: usize,
+    state: &mut State,
+) -> String {
+    if depth > MAX_DEPTH {
+        warn!("Too many nested includes, not inlining further");
+        return code.to_string();
+    }
+    let code = rx.include_str.replace_all(code, |c: &Captures| {
+        match state.read(&dir.join(&c["path"])) {
+            Some(s) => state.literal(raw_string(&s)),
+            None => c[0].to_string(),
+        }
+    });
+    let code = rx.include_bytes.replace_all(&code, |c: &Captures| {
+        let path = dir.join(&c["path"]);
+        match state.allows(&path).then(|| fs::read(&path)) {
+            Some(Ok(b)) => state.literal(byte_string(&b)),
+            _ => c[0].to_string(),
+        }
+    });
+    let code = rx.include.replace_all(&code, |c: &Captures| {
+        let path = dir.join(&c["path"]);
+        let Some(included) = state.read(&path) else {
+            return c[0].to_string();
+        };
+        state.resolved += 1;
+        let inc_dir = path.parent().unwrap_or(dir);
+        let included = flatten_in(rx, &included, inc_dir, inc_dir, depth + 1, state);
+        let start = c.name("start").map(|m| m.as_str()).unwrap_or_default();
+        match (c.name("start"), c.name("semi")) {
+            // Items, drop the semicolon
+            (Some(_), Some(_)) => format!("{start}{included}"),
+            (_, semi) => format!(
+                "{start}{included}{}",
+                semi.map(|m| m.as_str()).unwrap_or_default()
+            ),
+        }
+    });
+    let code = rx.path_mod.replace_all(&code, |c: &Captures| {
+        let path = dir.join(&c["path"]);
+        let Some(inner) = state.read(&path) else {
+            return c[0].to_string();
+        };
+        state.resolved += 1;
+        let inner_dir = path.parent().unwrap_or(dir);
+        module(
+            c,
+            &flatten_in(rx, &inner, inner_dir, inner_dir, depth + 1, state),
+        )
+    });
+    let code = rx.mod_decl.replace_all(&code, |c: &Captures| {
+        let name = &c["name"];
+        let candidates = [
+            mod_dir.join(format!("{name}.rs")),
+            mod_dir.join(name).join("mod.rs"),
+        ];
+        let Some(path) = candidates.iter().find(|p| p.is_file()) else {
+            debug!("Couldn't find file for module {}", name);
+            return c[0].to_string();
+        };
+        let Some(inner) = state.read(path) else {
+            return c[0].to_string();
+        };
+        state.resolved += 1;
+        let inner_dir = mod_dir.join(name);
+        module(
+            c,
+            &flatten_in(
+                rx,
+                &inner,
+                path.parent().unwrap_or(dir),
+                &inner_dir,
+                depth + 1,
+                state,
+            ),
+        )
+    });
+    code.into_owned()
+}
+
+/// Inline files referenced by `code`, which is in the directory `dir`
+///
+/// Returns the flattened code, and the number of files that were inlined.
+pub(crate) fn flatten(code: &str, dir: &Path) -> Result<(String, usize)> {
+    flatten_in_dir(code, dir, State::default())
+}
+
+/// Like [`flatten`], but only inline files in `root` (after following
+/// symlinks), for untrusted inputs
+#[cfg(feature = "fetch")]
+pub(crate) fn flatten_within(code: &str, dir: &Path, root: &Path) -> Result<(String, usize)> {
+    let root = root
+        .canonicalize()
+        .with_context(|| format!("Failed to canonicalize {}", root.display()))?;
+    let state = State {
+        within: Some(root),
+        ..State::default()
+    };
+    flatten_in_dir(code, dir, state)
+}
+
+fn flatten_in_dir(code: &str, dir: &Path, mut state: State) -> Result<(String, usize)> {
+    let rx = Regexes::new()?;
+    let mut flat = flatten_in(&rx, code, dir, dir, 0, &mut state);
+    for (i, lit) in state.literals.iter().enumerate() {
+        flat = flat.replacen(&placeholder(i), lit, 1);
+    }
+    Ok((flat, state.resolved))
+}
+
+/// Relative paths in the `include!`s, `include_str!`s, and `include_bytes!`s
+/// left in `code` after flattening, i.e., of files that are missing (e.g., in
+/// an issue), for stubs
+pub(crate) fn missing_includes(code: &str) -> Result<Vec<PathBuf>> {
+    let rx = Regexes::new()?;
+    let mut paths = Vec::new();
+    for c in [&rx.include, &rx.include_str, &rx.include_bytes]
+        .into_iter()
+        .flat_map(|r| r.captures_iter(code))
+    {
+        let path = PathBuf::from(&c["path"]);
+        if paths.contains(&path) {
+            continue;
+        }
+        if !path.components().all(|p| matches!(p, Component::Normal(_))) {
+            warn!(
+                "Can't stub {}, it's outside of the file's directory",
+                path.display()
+            );
+            continue;
+        }
+        paths.push(path);
+    }
+    Ok(paths)
+}
@@ -1,4 +1,6 @@
+use std::env;
 use std::fmt;
+use std::io::{self, IsTerminal};
 use tracing::{Event, Level, Subscriber};
 use tracing_subscriber::fmt::{
     format::{self, FormatEvent, FormatFields},
@@ -8,7 +10,35 @@ use tracing_subscriber::fmt::{
 use nu_ansi_term::{Color, Style};
 use tracing_subscriber::registry::LookupSpan;
 
-pub struct TerseFormatter;
+/// When to use color, see `--color`
+#[derive(Clone, Copy, Debug, Default, clap::ValueEnum)]
+pub(crate) enum ColorChoice {
+    /// If stdout is a terminal and NO_COLOR isn't set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub(crate) fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()) && io::stdout().is_terminal()
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// Status glyphs, left out without color
+const GLYPHS: &[&str] = &["✅ ", "❌ "];
+
+pub struct TerseFormatter {
+    /// Use color and status glyphs
+    pub(crate) color: bool,
+}
 
 fn style_for(level: &Level) -> Style {
     match *level {
@@ -39,8 +69,18 @@ where
             if field.name() == "message" {
                 // TODO(lb): Pad level to 5 places
                 // TODO(lb): Don't print all the danged fields
-                write!(&mut writer, "[{}] ", style.paint(format!("{}", level)))?;
-                ctx.field_format().format_fields(writer.by_ref(), event)?;
+                if self.color {
+                    write!(&mut writer, "[{}] ", style.paint(format!("{}", level)))?;
+                    ctx.field_format().format_fields(writer.by_ref(), event)?;
+                } else {
+                    let mut fields = String::new();
+                    ctx.field_format()
+                        .format_fields(format::Writer::new(&mut fields), event)?;
+                    for glyph in GLYPHS {
+                        fields = fields.replace(glyph, "");
+                    }
+                    write!(&mut writer, "[{}] {}", level, fields)?;
+                }
             }
         }
         writeln!(writer)
@@ -1,17 +1,139 @@
-use once_cell::sync::Lazy;
-use reqwest::blocking::Client;
-use serde::Deserialize;
+use anyhow::Context;
+use once_cell::sync::OnceCell;
+use reqwest::blocking::{RequestBuilder, Response};
+use reqwest::header::{ETAG, IF_NONE_MATCH};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::env::{var, VarError};
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, warn};
 
-static CLIENT: Lazy<Client> = Lazy::new(|| {
-    Client::builder()
-        .user_agent("langston-barrett/icemelter")
-        .build()
-        .unwrap()
-});
+use crate::cache;
+use crate::http::CLIENT;
+
+/// GitHub's API, for github.com
+const DEFAULT_API_URL: &str = "https://api.github.com";
+
+/// See [`set_api_url`]
+static API_URL: OnceCell<String> = OnceCell::new();
+
+/// Use another API (e.g., `https://github.example.com/api/v3` for GitHub
+/// Enterprise), must be called before any requests
+pub(crate) fn set_api_url(url: &str) {
+    let _ = API_URL.set(url.trim_end_matches('/').to_string());
+}
+
+/// The API's base URL, from [`set_api_url`], `GITHUB_API_URL`, or else
+/// github.com's
+fn api() -> &'static str {
+    API_URL.get_or_init(|| match var("GITHUB_API_URL") {
+        Ok(url) if !url.is_empty() => url.trim_end_matches('/').to_string(),
+        _ => String::from(DEFAULT_API_URL),
+    })
+}
+
+/// The host that the GitHub CLI knows the API by, e.g., `github.com`
+fn host() -> &'static str {
+    let url = api();
+    if url == DEFAULT_API_URL {
+        return "github.com";
+    }
+    let host = url.split_once("://").map_or(url, |(_, rest)| rest);
+    host.split('/').next().unwrap_or(host)
+}
 
 pub(crate) struct Config {
-    token: String,
+    /// Without one, only public data can be read, with a lower rate limit
+    token: Option<String>,
+}
+
+/// Retries of a rate-limited request
+const MAX_RETRIES: u32 = 5;
+
+/// Longest wait for a rate limit to reset, rather than failing
+const MAX_WAIT: Duration = Duration::from_secs(5 * 60);
+
+/// How long to wait before retrying, if the response says the request was
+/// rate-limited
+fn rate_limited(resp: &Response, attempt: u32) -> Option<Duration> {
+    let status = resp.status();
+    if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+    let header = |name: &str| resp.headers().get(name)?.to_str().ok()?.parse::<u64>().ok();
+    if let Some(secs) = header("retry-after") {
+        return Some(Duration::from_secs(secs));
+    }
+    if header("x-ratelimit-remaining") == Some(0) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let reset = header("x-ratelimit-reset")?;
+        return Some(Duration::from_secs(reset.saturating_sub(now) + 1));
+    }
+    // Secondary rate limits don't always say when to retry
+    (status == StatusCode::TOO_MANY_REQUESTS).then(|| Duration::from_secs(1 << attempt))
+}
+
+/// E.g., `12:34 UTC`
+fn utc_time(after: Duration) -> String {
+    let secs = (SystemTime::now() + after)
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    format!("{:02}:{:02} UTC", secs / 3600 % 24, secs / 60 % 60)
+}
+
+trait GitHubRequest {
+    fn authorize(self, config: &Config) -> Self;
+
+    /// Send, retrying with backoff while rate-limited
+    fn send_retrying(self) -> reqwest::Result<Response>;
+}
+
+impl GitHubRequest for RequestBuilder {
+    fn authorize(self, config: &Config) -> Self {
+        match &config.token {
+            Some(token) => self.bearer_auth(token),
+            None => self,
+        }
+    }
+
+    fn send_retrying(self) -> reqwest::Result<Response> {
+        let mut req = self;
+        let mut attempt = 0;
+        loop {
+            let Some(next) = req.try_clone() else {
+                return req.send();
+            };
+            let resp = req.send()?;
+            let Some(wait) = rate_limited(&resp, attempt) else {
+                return Ok(resp);
+            };
+            if attempt >= MAX_RETRIES || wait > MAX_WAIT {
+                error!(
+                    "GitHub rate limit exceeded, requests will succeed again in {} minute(s), at {}",
+                    wait.as_secs().div_ceil(60),
+                    utc_time(wait)
+                );
+                return Ok(resp);
+            }
+            warn!(
+                "GitHub rate limit exceeded, retrying in {}s...",
+                wait.as_secs()
+            );
+            thread::sleep(wait);
+            req = next;
+            attempt += 1;
+        }
+    }
 }
 
 impl Config {
@@ -19,23 +141,540 @@ impl Config {
 
     pub(crate) fn from_env() -> Result<Self, VarError> {
         Ok(Self {
-            token: var(Self::ENV_VAR)?,
+            token: Some(var(Self::ENV_VAR)?),
         })
     }
+
+    /// The token from the GitHub CLI, if it's installed and logged in
+    pub(crate) fn from_gh() -> Option<Self> {
+        let out = Command::new("gh")
+            .args(["auth", "token", "--hostname", host()])
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        let token = String::from_utf8(out.stdout).ok()?.trim().to_string();
+        if !out.status.success() || token.is_empty() {
+            return None;
+        }
+        Some(Self { token: Some(token) })
+    }
+
+    /// The token in the GitHub CLI's `hosts.yml`, for when `gh` can't be run
+    /// (newer versions keep it in the system keyring instead)
+    pub(crate) fn from_gh_hosts() -> Option<Self> {
+        let dir = match var("GH_CONFIG_DIR") {
+            Ok(d) if !d.is_empty() => PathBuf::from(d),
+            _ => match var("XDG_CONFIG_HOME") {
+                Ok(d) if !d.is_empty() => PathBuf::from(d).join("gh"),
+                _ => PathBuf::from(var("HOME").ok()?).join(".config").join("gh"),
+            },
+        };
+        let hosts = fs::read_to_string(dir.join("hosts.yml")).ok()?;
+        let mut in_github = false;
+        for line in hosts.lines() {
+            if !line.starts_with(' ') {
+                in_github = line.trim_end().strip_suffix(':') == Some(host());
+            } else if let Some(token) = line.trim().strip_prefix("oauth_token:") {
+                let token = token.trim().trim_matches(|c| c == '"' || c == '\'');
+                if in_github && !token.is_empty() {
+                    return Some(Self {
+                        token: Some(token.to_string()),
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    #[cfg(feature = "keyring")]
+    pub(crate) fn new(token: String) -> Self {
+        Self { token: Some(token) }
+    }
+
+    /// Without a token
+    pub(crate) fn anonymous() -> Self {
+        Self { token: None }
+    }
+
+    #[cfg(feature = "keyring")]
+    fn keyring_entry() -> keyring::Result<keyring::Entry> {
+        keyring::Entry::new("icemelter", "github")
+    }
+
+    /// The token saved by `icemelter auth login`
+    #[cfg(feature = "keyring")]
+    pub(crate) fn from_keyring() -> Option<Self> {
+        let token = Self::keyring_entry().ok()?.get_password().ok()?;
+        Some(Self { token: Some(token) })
+    }
+
+    #[cfg(not(feature = "keyring"))]
+    pub(crate) fn from_keyring() -> Option<Self> {
+        None
+    }
+
+    #[cfg(feature = "keyring")]
+    pub(crate) fn save_to_keyring(&self) -> keyring::Result<()> {
+        Self::keyring_entry()?.set_password(self.token.as_deref().unwrap_or_default())
+    }
+
+    #[cfg(feature = "keyring")]
+    pub(crate) fn delete_from_keyring() -> keyring::Result<()> {
+        Self::keyring_entry()?.delete_credential()
+    }
+
+    /// From `GITHUB_TOKEN`, the system keyring, or else the GitHub CLI
+    pub(crate) fn load() -> anyhow::Result<Self> {
+        Self::from_env().or_else(|_| {
+            Self::from_keyring()
+                .or_else(Self::from_gh)
+                .or_else(Self::from_gh_hosts)
+                .with_context(|| {
+                    format!(
+                        "Missing {} environment variable, and `gh auth token` failed; try `icemelter auth login`",
+                        Self::ENV_VAR
+                    )
+                })
+        })
+    }
+
+    /// Like [`Config::load`], but falls back to unauthenticated requests,
+    /// which work for public issues
+    pub(crate) fn load_or_anonymous() -> Self {
+        Self::load().unwrap_or_else(|e| {
+            warn!(
+                "{:#}. Using unauthenticated requests, which GitHub limits to 60 per hour.",
+                e
+            );
+            Self::anonymous()
+        })
+    }
+}
+
+/// GET `url`, revalidating the cached response (if any) with its ETag
+fn get_cached<T: DeserializeOwned>(config: &Config, url: &str) -> anyhow::Result<T> {
+    let cached = cache::get(url);
+    let mut req = CLIENT.get(url).authorize(config);
+    if let Some(c) = &cached {
+        req = req.header(IF_NONE_MATCH, &c.etag);
+    }
+    let resp = req.send_retrying()?;
+    if let (StatusCode::NOT_MODIFIED, Some(c)) = (resp.status(), &cached) {
+        debug!("Using cached response for {}", url);
+        return Ok(serde_json::from_str(&c.body)?);
+    }
+    let resp = resp.error_for_status()?;
+    let etag = resp
+        .headers()
+        .get(ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from);
+    let body = resp.text()?;
+    let parsed = serde_json::from_str(&body)?;
+    if let Some(etag) = etag {
+        cache::put(url, &cache::Entry { etag, body });
+    }
+    Ok(parsed)
+}
+
+/// An issue in `repo` (`owner/name`), see [`get_cached`]
+pub(crate) fn get_issue(config: &Config, repo: &str, number: usize) -> anyhow::Result<Issue> {
+    get_cached(config, &format!("{}/repos/{repo}/issues/{number}", api()))
+}
+
+/// All comments on an issue, oldest first, see [`get_cached`]
+pub(crate) fn get_comments(
+    config: &Config,
+    repo: &str,
+    number: usize,
+) -> anyhow::Result<Vec<IssueComment>> {
+    const PER_PAGE: usize = 100;
+    let mut comments = Vec::new();
+    for page in 1.. {
+        let url = format!(
+            "{}/repos/{repo}/issues/{number}/comments?per_page={PER_PAGE}&page={page}",
+            api()
+        );
+        let batch: Vec<IssueComment> = get_cached(config, &url)?;
+        let last = batch.len() < PER_PAGE;
+        comments.extend(batch);
+        if last {
+            break;
+        }
+    }
+    Ok(comments)
+}
+
+#[derive(Serialize)]
+struct NewComment<'a> {
+    body: &'a str,
+}
+
+/// Returns the URL of the new comment
+pub(crate) fn post_comment(
+    config: &Config,
+    repo: &str,
+    number: usize,
+    body: &str,
+) -> Result<String, reqwest::Error> {
+    let url = format!("{}/repos/{repo}/issues/{number}/comments", api());
+    let comment: Comment = CLIENT
+        .post(url)
+        .authorize(config)
+        .json(&NewComment { body })
+        .send_retrying()?
+        .error_for_status()?
+        .json()?;
+    Ok(comment.html_url)
+}
+
+pub(crate) fn get_user(config: &Config) -> Result<User, reqwest::Error> {
+    CLIENT
+        .get(format!("{}/user", api()))
+        .authorize(config)
+        .send_retrying()?
+        .error_for_status()?
+        .json()
+}
+
+pub(crate) fn get_rate_limit(config: &Config) -> Result<RateLimit, reqwest::Error> {
+    CLIENT
+        .get(format!("{}/rate_limit", api()))
+        .authorize(config)
+        .send_retrying()?
+        .error_for_status()?
+        .json()
 }
 
-pub(crate) fn get_issue(config: &Config, number: usize) -> Result<Issue, reqwest::Error> {
-    let url = format!("https://api.github.com/repos/rust-lang/rust/issues/{number}");
+/// One page (of up to 100) of open issues labeled I-ICE
+pub(crate) fn get_open_ices(
+    config: &Config,
+    page: usize,
+) -> Result<Vec<IssueSummary>, reqwest::Error> {
+    let url = format!(
+        "{}/repos/rust-lang/rust/issues?labels=I-ICE&state=open&per_page=100&page={page}",
+        api()
+    );
     CLIENT
         .get(url)
-        .bearer_auth(&config.token)
-        .send()?
+        .authorize(config)
+        .send_retrying()?
         .error_for_status()?
         .json()
 }
 
+/// Issues (not pull requests) matching a search query, e.g.,
+/// `repo:rust-lang/rust label:I-ICE state:open`, in GitHub's order
+///
+/// GitHub returns at most the first 1000.
+pub(crate) fn search_issues(config: &Config, query: &str) -> anyhow::Result<Vec<IssueSummary>> {
+    const PER_PAGE: usize = 100;
+    let mut issues = Vec::new();
+    for page in 1.. {
+        let url = reqwest::Url::parse_with_params(
+            &format!("{}/search/issues", api()),
+            &[
+                ("q", query),
+                ("per_page", &PER_PAGE.to_string()),
+                ("page", &page.to_string()),
+            ],
+        )?;
+        let results: SearchResults = CLIENT
+            .get(url)
+            .authorize(config)
+            .send_retrying()?
+            .error_for_status()
+            .with_context(|| format!("Failed to search for {query}"))?
+            .json()?;
+        if results.incomplete_results {
+            warn!("GitHub's search timed out, some issues may be missing");
+        }
+        let last = results.items.len() < PER_PAGE || page * PER_PAGE >= results.total_count;
+        issues.extend(results.items);
+        if last || page * PER_PAGE >= 1000 {
+            break;
+        }
+    }
+    issues.retain(|i| i.pull_request.is_none());
+    Ok(issues)
+}
+
+/// Fork rust-lang/rust (or get the existing fork), returns `owner/repo`
+pub(crate) fn create_fork(config: &Config) -> Result<String, reqwest::Error> {
+    let repo: Repo = CLIENT
+        .post(format!("{}/repos/rust-lang/rust/forks", api()))
+        .authorize(config)
+        .send_retrying()?
+        .error_for_status()?
+        .json()?;
+    Ok(repo.full_name)
+}
+
+/// The commit a branch points to
+pub(crate) fn get_branch_sha(
+    config: &Config,
+    repo: &str,
+    branch: &str,
+) -> Result<String, reqwest::Error> {
+    let url = format!("{}/repos/{repo}/git/ref/heads/{branch}", api());
+    let r: Ref = CLIENT
+        .get(url)
+        .authorize(config)
+        .send_retrying()?
+        .error_for_status()?
+        .json()?;
+    Ok(r.object.sha)
+}
+
+#[derive(Serialize)]
+struct NewRef<'a> {
+    r#ref: &'a str,
+    sha: &'a str,
+}
+
+pub(crate) fn create_branch(
+    config: &Config,
+    repo: &str,
+    branch: &str,
+    sha: &str,
+) -> Result<(), reqwest::Error> {
+    let url = format!("{}/repos/{repo}/git/refs", api());
+    CLIENT
+        .post(url)
+        .authorize(config)
+        .json(&NewRef {
+            r#ref: &format!("refs/heads/{branch}"),
+            sha,
+        })
+        .send_retrying()?
+        .error_for_status()?;
+    Ok(())
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | u32::from(*b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(char::from(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize]));
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[derive(Serialize)]
+struct NewFile<'a> {
+    message: &'a str,
+    content: String,
+    branch: &'a str,
+}
+
+/// Commit a new file to a branch
+pub(crate) fn create_file(
+    config: &Config,
+    repo: &str,
+    branch: &str,
+    path: &str,
+    contents: &[u8],
+    message: &str,
+) -> Result<(), reqwest::Error> {
+    let url = format!("{}/repos/{repo}/contents/{path}", api());
+    CLIENT
+        .put(url)
+        .authorize(config)
+        .json(&NewFile {
+            message,
+            content: base64(contents),
+            branch,
+        })
+        .send_retrying()?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct NewPull<'a> {
+    title: &'a str,
+    head: &'a str,
+    base: &'a str,
+    body: &'a str,
+    draft: bool,
+}
+
+/// Open a draft PR against rust-lang/rust, returns its URL
+pub(crate) fn create_draft_pull(
+    config: &Config,
+    title: &str,
+    head: &str,
+    body: &str,
+) -> Result<String, reqwest::Error> {
+    let pull: Comment = CLIENT
+        .post(format!("{}/repos/rust-lang/rust/pulls", api()))
+        .authorize(config)
+        .json(&NewPull {
+            title,
+            head,
+            base: "master",
+            body,
+            draft: true,
+        })
+        .send_retrying()?
+        .error_for_status()?
+        .json()?;
+    Ok(pull.html_url)
+}
+
+/// The latest release of `repo` (`owner/repo`), the token is optional here
+pub(crate) fn get_latest_release(
+    config: Option<&Config>,
+    repo: &str,
+) -> Result<Release, reqwest::Error> {
+    let url = format!("https://api.github.com/repos/{repo}/releases/latest");
+    let mut req = CLIENT.get(url);
+    if let Some(c) = config {
+        req = req.authorize(c);
+    }
+    req.send_retrying()?.error_for_status()?.json()
+}
+
+/// A gist, e.g., one behind a Rust Playground share link
+pub(crate) fn get_gist(config: &Config, id: &str) -> Result<Gist, reqwest::Error> {
+    let url = format!("{}/gists/{id}", api());
+    CLIENT
+        .get(url)
+        .authorize(config)
+        .send_retrying()?
+        .error_for_status()?
+        .json()
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct Asset {
+    pub(crate) name: String,
+    pub(crate) browser_download_url: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct Release {
+    pub(crate) tag_name: String,
+    pub(crate) assets: Vec<Asset>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct GistFile {
+    pub(crate) content: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct Gist {
+    /// By file name
+    pub(crate) files: BTreeMap<String, GistFile>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct Repo {
+    pub(crate) full_name: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct RefObject {
+    pub(crate) sha: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct Ref {
+    pub(crate) object: RefObject,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct Comment {
+    pub(crate) html_url: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct IssueComment {
+    pub(crate) body: Option<String>,
+    pub(crate) user: Option<User>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct User {
+    pub(crate) login: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct Rate {
+    pub(crate) limit: usize,
+    pub(crate) remaining: usize,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct RateLimit {
+    pub(crate) rate: Rate,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct Label {
+    pub(crate) name: String,
+}
+
 #[derive(Deserialize, Debug)]
 pub(crate) struct Issue {
     pub(crate) number: usize,
-    pub(crate) body: String,
+    /// `null` if the issue has an empty body
+    #[serde(default)]
+    pub(crate) body: Option<String>,
+    pub(crate) state: String,
+    pub(crate) labels: Vec<Label>,
+    #[serde(default)]
+    pub(crate) locked: bool,
+    pub(crate) user: Option<User>,
+}
+
+/// An issue in a list, which may be a pull request
+#[derive(Deserialize, Debug)]
+pub(crate) struct IssueSummary {
+    pub(crate) number: usize,
+    pub(crate) title: String,
+    pub(crate) body: Option<String>,
+    #[serde(default)]
+    pub(crate) pull_request: Option<serde_json::Value>,
+    /// Only in search results, e.g., `https://api.github.com/repos/OWNER/NAME`
+    #[serde(default)]
+    repository_url: Option<String>,
+}
+
+impl IssueSummary {
+    /// The issue's repository (`owner/name`), for search results
+    pub(crate) fn repo(&self) -> Option<&str> {
+        self.repository_url
+            .as_deref()?
+            .strip_prefix(api())?
+            .strip_prefix("/repos/")
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct SearchResults {
+    total_count: usize,
+    incomplete_results: bool,
+    items: Vec<IssueSummary>,
+}
+
+impl Issue {
+    pub(crate) fn is_closed(&self) -> bool {
+        self.state == "closed"
+    }
+
+    pub(crate) fn has_label(&self, name: &str) -> bool {
+        self.labels.iter().any(|l| l.name == name)
+    }
 }
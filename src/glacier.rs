@@ -0,0 +1,56 @@
+//! Reducing glacier fixtures in place, see `--glacier`
+//!
+//! rust-lang/glacier keeps each known ICE as `fixtures/NNNNN.rs`, next to the
+//! stderr it's expected to produce (`fixtures/NNNNN.stderr`).
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+
+use crate::{atomic, issue};
+
+pub(crate) struct Fixture {
+    pub(crate) path: PathBuf,
+    stderr: String,
+}
+
+impl Fixture {
+    pub(crate) fn read(source: &str) -> Result<Self> {
+        let path = PathBuf::from(source);
+        if !(path.is_file() && path.extension().is_some_and(|e| e == "rs")) {
+            bail!("{source} isn't a glacier fixture (e.g., fixtures/12345.rs)");
+        }
+        let stderr_path = path.with_extension("stderr");
+        let stderr = fs::read_to_string(&stderr_path).with_context(|| {
+            format!(
+                "Failed to read the expected stderr {}",
+                stderr_path.display()
+            )
+        })?;
+        Ok(Fixture { path, stderr })
+    }
+
+    /// Regex for the ICE message in the expected stderr
+    pub(crate) fn signature(&self) -> Option<String> {
+        issue::ice_message(&self.stderr).map(|m| issue::message_regex(&m))
+    }
+
+    /// Where the fixture is kept while it's replaced, glacier only runs `.rs`
+    /// files
+    pub(crate) fn original_path(&self) -> PathBuf {
+        self.path.with_extension("rs.orig")
+    }
+}
+
+/// Write the expected stderr of a reduced fixture at `path`, next to it,
+/// naming it instead of the candidate in the stderr
+pub(crate) fn write_stderr(path: &Path, stderr: &str) -> Result<PathBuf> {
+    let candidate = Regex::new(r"[^\s:'`]*icemelter-[^\s:'`]*").unwrap();
+    let stderr = candidate.replace_all(stderr, regex::NoExpand(&path.display().to_string()));
+    let stderr_path = path.with_extension("stderr");
+    atomic::write(&stderr_path, stderr.as_bytes())
+        .with_context(|| format!("Failed to write file to {}", stderr_path.display()))?;
+    Ok(stderr_path)
+}
@@ -0,0 +1,263 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::backtrace::Backtrace;
+use crate::environment::Environment;
+use crate::issue;
+
+/// One run of Icemelter
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Record {
+    /// Seconds since the Unix epoch
+    pub(crate) time: u64,
+    pub(crate) source: String,
+    pub(crate) issue: Option<usize>,
+    /// Repository of the issue, if not rust-lang/rust
+    #[serde(default)]
+    pub(crate) repo: Option<String>,
+    pub(crate) original_size: usize,
+    pub(crate) reduced_size: usize,
+    pub(crate) signature: Option<String>,
+    /// Hash of the innermost compiler frames in the backtrace
+    #[serde(default)]
+    pub(crate) backtrace_hash: Option<String>,
+    /// PR that introduced the ICE, from bisection
+    #[serde(default)]
+    pub(crate) bisect_pr: Option<String>,
+    /// Path to the reduced file
+    #[serde(default)]
+    pub(crate) output: Option<PathBuf>,
+    /// Path to the Markdown report
+    #[serde(default)]
+    pub(crate) report: Option<PathBuf>,
+    /// An earlier input in the same batch with the same ICE, so this one
+    /// wasn't reduced
+    #[serde(default)]
+    pub(crate) duplicate_of: Option<String>,
+    #[serde(default)]
+    pub(crate) passes: Vec<PassStats>,
+    #[serde(default)]
+    pub(crate) environment: Option<Environment>,
+}
+
+/// How much a reduction pass removed, and how many checks it took
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct PassStats {
+    pub(crate) name: String,
+    /// Bytes
+    pub(crate) removed: usize,
+    pub(crate) checks: usize,
+}
+
+impl Record {
+    pub(crate) fn now(
+        source: String,
+        issue: Option<usize>,
+        original_size: usize,
+        reduced_size: usize,
+        ice: Option<&Backtrace>,
+        bisect_pr: Option<String>,
+        report: Option<PathBuf>,
+    ) -> Self {
+        Record {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            source,
+            issue,
+            repo: None,
+            original_size,
+            reduced_size,
+            signature: ice.and_then(|bt| bt.message.clone()),
+            backtrace_hash: ice.and_then(Backtrace::hash),
+            bisect_pr,
+            output: None,
+            report,
+            duplicate_of: None,
+            passes: Vec::new(),
+            environment: None,
+        }
+    }
+
+    pub(crate) fn repo(&self) -> &str {
+        self.repo.as_deref().unwrap_or(issue::RUST_REPO)
+    }
+
+    /// E.g., `#N`, see [`issue::reference`]
+    pub(crate) fn issue_reference(&self) -> Option<String> {
+        self.issue.map(|n| issue::reference(self.repo(), n))
+    }
+}
+
+/// `$XDG_DATA_HOME/icemelter`, or `~/.local/share/icemelter`
+pub(crate) fn data_dir() -> Option<PathBuf> {
+    let data = match std::env::var_os("XDG_DATA_HOME") {
+        Some(d) if !d.is_empty() => PathBuf::from(d),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".local/share"),
+    };
+    Some(data.join("icemelter"))
+}
+
+/// `history.jsonl` in [`data_dir`]
+pub(crate) fn default_path() -> Option<PathBuf> {
+    Some(data_dir()?.join("history.jsonl"))
+}
+
+pub(crate) fn append(path: &Path, record: &Record) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    }
+    let mut f = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open history file {}", path.display()))?;
+    let line = serde_json::to_string(record).context("Failed to serialize history")?;
+    writeln!(f, "{}", line)
+        .with_context(|| format!("Failed to write history file {}", path.display()))?;
+    Ok(())
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// Append a record to a CSV file, writing a header if the file is new
+pub(crate) fn append_csv(path: &Path, record: &Record) -> Result<()> {
+    let new = !path.exists();
+    let mut f = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open CSV file {}", path.display()))?;
+    if new {
+        writeln!(
+            f,
+            "input,signature,original_size,reduced_size,bisect_pr,report"
+        )?;
+    }
+    writeln!(
+        f,
+        "{},{},{},{},{},{}",
+        csv_field(&record.source),
+        csv_field(record.signature.as_deref().unwrap_or_default()),
+        record.original_size,
+        record.reduced_size,
+        csv_field(record.bisect_pr.as_deref().unwrap_or_default()),
+        csv_field(
+            &record
+                .report
+                .as_ref()
+                .map(|p| p.display().to_string())
+                .unwrap_or_default()
+        ),
+    )
+    .with_context(|| format!("Failed to write CSV file {}", path.display()))?;
+    Ok(())
+}
+
+pub(crate) fn read(path: &Path) -> Result<Vec<Record>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read history file {}", path.display()))?;
+    text.lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| serde_json::from_str(l).context("Malformed history file"))
+        .collect()
+}
+
+/// Convert days since the Unix epoch to a (year, month, day) date
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + i64::from(m <= 2);
+    (y, m, d)
+}
+
+/// Print statistics about previous runs
+///
+/// `is_open` reports whether an issue is still open, if known.
+pub(crate) fn print_stats(records: &[Record], is_open: impl Fn(&str, usize) -> Option<bool>) {
+    if records.is_empty() {
+        println!("No runs recorded yet.");
+        return;
+    }
+
+    const DAY: u64 = 24 * 60 * 60;
+    println!("ICEs melted per week:");
+    let mut weeks = BTreeMap::new();
+    for r in records {
+        // The epoch was a Thursday, start weeks on Monday
+        let days = (r.time / DAY) as i64;
+        let monday = days - (days + 3).rem_euclid(7);
+        *weeks.entry(monday).or_insert(0) += 1;
+    }
+    for (monday, count) in weeks {
+        let (y, m, d) = civil_from_days(monday);
+        println!("  {y:04}-{m:02}-{d:02}: {count}");
+    }
+
+    let ratios = records
+        .iter()
+        .filter(|r| r.original_size != 0)
+        .map(|r| r.reduced_size as f64 / r.original_size as f64)
+        .collect::<Vec<_>>();
+    if !ratios.is_empty() {
+        let avg = ratios.iter().sum::<f64>() / ratios.len() as f64;
+        println!(
+            "Average reduction: {:.2}% (to {:.2}% of original size)",
+            100.0 - avg * 100.0,
+            avg * 100.0
+        );
+    }
+
+    let mut signatures: HashMap<&str, usize> = HashMap::new();
+    for sig in records.iter().filter_map(|r| r.signature.as_deref()) {
+        *signatures.entry(sig).or_insert(0) += 1;
+    }
+    let mut signatures = signatures.into_iter().collect::<Vec<_>>();
+    signatures.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    if !signatures.is_empty() {
+        println!("Most common ICEs:");
+        for (sig, count) in signatures.iter().take(10) {
+            println!("  {count:>4}  {sig}");
+        }
+    }
+
+    let mut issues = records
+        .iter()
+        .filter_map(|r| Some((r.repo(), r.issue?)))
+        .collect::<Vec<_>>();
+    issues.sort_unstable();
+    issues.dedup();
+    if !issues.is_empty() {
+        println!("Issues:");
+        for (repo, number) in issues {
+            let state = match is_open(repo, number) {
+                Some(true) => "open",
+                Some(false) => "closed",
+                None => "unknown",
+            };
+            println!("  {}: {state}", issue::reference(repo, number));
+        }
+    }
+}
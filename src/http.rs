@@ -0,0 +1,64 @@
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use once_cell::sync::{Lazy, OnceCell};
+use reqwest::blocking::{Client, ClientBuilder};
+
+fn builder() -> ClientBuilder {
+    // Proxies from HTTPS_PROXY, HTTP_PROXY, etc. are used by default
+    Client::builder().user_agent("langston-barrett/icemelter")
+}
+
+/// The client from [`configure`], if any
+static CONFIGURED: OnceCell<Client> = OnceCell::new();
+
+pub(crate) static CLIENT: Lazy<Client> = Lazy::new(|| {
+    CONFIGURED
+        .get()
+        .cloned()
+        .unwrap_or_else(|| builder().build().unwrap())
+});
+
+/// Use a proxy and/or trust an extra CA certificate (PEM), must be called
+/// before [`CLIENT`] is used
+pub(crate) fn configure(proxy: Option<&str>, ca_cert: Option<&Path>) -> Result<()> {
+    let mut builder = builder();
+    if let Some(url) = proxy {
+        builder = builder.proxy(reqwest::Proxy::all(url).context("Invalid proxy URL")?);
+    }
+    if let Some(path) = ca_cert {
+        let pem = fs::read(path)
+            .with_context(|| format!("Failed to read CA certificate {}", path.display()))?;
+        let cert = reqwest::Certificate::from_pem(&pem)
+            .with_context(|| format!("Invalid CA certificate {}", path.display()))?;
+        builder = builder.add_root_certificate(cert);
+    }
+    let client = builder.build().context("Failed to set up HTTP client")?;
+    if CONFIGURED.set(client).is_err() {
+        anyhow::bail!("Internal error: HTTP client configured twice");
+    }
+    Ok(())
+}
+
+/// GET `url`, failing if the body is larger than `max_size` bytes
+pub(crate) fn get_limited(url: &str, max_size: usize) -> Result<Vec<u8>> {
+    let resp = CLIENT
+        .get(url)
+        .send()
+        .and_then(|r| r.error_for_status())
+        .with_context(|| format!("Failed to download {url}"))?;
+    let too_large = || format!("{url} is too large (over {max_size} bytes)");
+    if resp.content_length().is_some_and(|n| n > max_size as u64) {
+        bail!(too_large());
+    }
+    let mut bytes = Vec::new();
+    resp.take(max_size as u64 + 1)
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("Failed to download {url}"))?;
+    if bytes.len() > max_size {
+        bail!(too_large());
+    }
+    Ok(bytes)
+}
@@ -0,0 +1,157 @@
+//! Grouping crashing inputs (e.g., from fuzz-rustc) by ICE, see
+//! `icemelter intake`
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use tracing::debug;
+use treereduce::Check;
+
+use crate::backtrace::{fnv1a, Backtrace};
+use crate::check::IceCheck;
+use crate::{atomic, issue};
+
+/// Inputs that produce the same ICE
+pub(crate) struct Group {
+    /// Backtrace hash, or a hash of the message, see [`group`]; names the
+    /// report directory, so that later runs find earlier results
+    pub(crate) id: String,
+    pub(crate) message: Option<String>,
+    /// Smallest first
+    pub(crate) inputs: Vec<PathBuf>,
+}
+
+/// Regular, non-hidden files in `dir`, fuzzers don't use the `.rs` extension
+pub(crate) fn inputs(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in
+        fs::read_dir(dir).with_context(|| format!("Failed to read directory {}", dir.display()))?
+    {
+        let entry = entry?;
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        if !hidden && entry.file_type()?.is_file() {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// rustc's stderr, if the file produces an ICE
+fn ice_stderr(chk: &IceCheck, file: &Path) -> Result<Option<String>> {
+    let code = fs::read(file).with_context(|| format!("Failed to read {}", file.display()))?;
+    let state = chk
+        .start(&code)
+        .with_context(|| format!("Failed to check {}", file.display()))?;
+    let (interesting, _status, _stdout, stderr) = chk
+        .wait_with_output(state)
+        .with_context(|| format!("Failed to check {}", file.display()))?;
+    Ok(interesting.then(|| String::from_utf8_lossy(&stderr).into_owned()))
+}
+
+/// Group the files that produce ICEs by backtrace hash (or message, if there's
+/// no backtrace), largest group first
+pub(crate) fn group(chk: &IceCheck, files: &[PathBuf]) -> Result<Vec<Group>> {
+    let mut groups: BTreeMap<String, Group> = BTreeMap::new();
+    for file in files {
+        let Some(stderr) = ice_stderr(chk, file)? else {
+            debug!("No ICE: {}", file.display());
+            continue;
+        };
+        let bt = Backtrace::parse(&stderr);
+        let id = match (bt.hash(), &bt.message) {
+            (Some(h), _) => h,
+            // Numbers are often `DefId`s and the like
+            (None, Some(m)) => fnv1a(issue::message_regex(m).as_bytes()),
+            (None, None) => String::from("unknown"),
+        };
+        debug!("{}: {}", file.display(), id);
+        groups
+            .entry(id.clone())
+            .or_insert_with(|| Group {
+                id,
+                message: bt.message,
+                inputs: Vec::new(),
+            })
+            .inputs
+            .push(file.clone());
+    }
+    let mut groups = groups.into_values().collect::<Vec<_>>();
+    for g in &mut groups {
+        g.inputs
+            .sort_by_key(|f| fs::metadata(f).map(|m| m.len()).unwrap_or(u64::MAX));
+    }
+    groups.sort_by_key(|g| std::cmp::Reverse(g.inputs.len()));
+    Ok(groups)
+}
+
+/// What happened to a group, for the summary
+#[derive(Clone, Copy)]
+pub(crate) enum Outcome {
+    Melted,
+    /// By an earlier run, into the same directory
+    AlreadyMelted,
+    Failed,
+}
+
+/// Inputs already recorded in a report directory by earlier runs, and these
+fn merged_inputs(group_dir: &Path, inputs: &[PathBuf]) -> Vec<String> {
+    let mut all = fs::read_to_string(group_dir.join(INPUTS))
+        .unwrap_or_default()
+        .lines()
+        .map(String::from)
+        .collect::<Vec<_>>();
+    for input in inputs {
+        let input = input.display().to_string();
+        if !all.contains(&input) {
+            all.push(input);
+        }
+    }
+    all
+}
+
+const INPUTS: &str = "inputs.txt";
+
+/// Record the group's inputs (and message) in its report directory
+pub(crate) fn write_group(group_dir: &Path, group: &Group) -> Result<()> {
+    fs::create_dir_all(group_dir)
+        .with_context(|| format!("Failed to create directory {}", group_dir.display()))?;
+    let mut inputs = merged_inputs(group_dir, &group.inputs).join("\n");
+    inputs.push('\n');
+    atomic::write(group_dir.join(INPUTS), inputs)
+        .with_context(|| format!("Failed to write to {}", group_dir.display()))?;
+    if let Some(msg) = &group.message {
+        atomic::write(group_dir.join("message.txt"), format!("{msg}\n"))
+            .with_context(|| format!("Failed to write to {}", group_dir.display()))?;
+    }
+    Ok(())
+}
+
+/// Markdown summary of a run, linking each input to its group's result
+pub(crate) fn summary(groups: &[(Group, Outcome)]) -> String {
+    let mut md = String::from("| ICE | Message | Inputs | Result |\n|---|---|---|---|\n");
+    for (g, outcome) in groups {
+        let result = match outcome {
+            Outcome::Melted => format!("[melted]({}/melted.rs)", g.id),
+            Outcome::AlreadyMelted => format!("[melted earlier]({}/melted.rs)", g.id),
+            Outcome::Failed => String::from("failed"),
+        };
+        md.push_str(&format!(
+            "| `{}` | {} | {} | {} |\n",
+            g.id,
+            g.message
+                .as_deref()
+                .unwrap_or("unknown")
+                .replace('|', "\\|"),
+            g.inputs
+                .iter()
+                .map(|i| format!("`{}`", i.display()))
+                .collect::<Vec<_>>()
+                .join(", "),
+            result
+        ));
+    }
+    md
+}
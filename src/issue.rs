@@ -0,0 +1,257 @@
+use regex::Regex;
+
+use crate::check;
+
+/// Where issues come from unless another repository is given
+pub(crate) const RUST_REPO: &str = "rust-lang/rust";
+
+/// The repository (if given) and number of an issue reference: `#N`,
+/// `owner/name#N`, or a link to the issue (or one of its comments)
+pub(crate) fn parse_reference(source: &str) -> Option<(Option<String>, usize)> {
+    let rx = Regex::new(
+        r"^(?:#|(?P<repo>[\w.-]+/[\w.-]+)#|(?:https?://)?(?:www\.)?github\.com/(?P<url_repo>[\w.-]+/[\w.-]+)/issues/)(?P<number>\d+)(?:[/?#]|$)",
+    )
+    .unwrap();
+    let c = rx.captures(source)?;
+    let repo = c.name("repo").or_else(|| c.name("url_repo"));
+    let number = c["number"].parse().ok()?;
+    Some((repo.map(|r| r.as_str().to_string()), number))
+}
+
+/// `#N` for rust-lang/rust, `owner/name#N` otherwise
+pub(crate) fn reference(repo: &str, number: usize) -> String {
+    if repo == RUST_REPO {
+        format!("#{number}")
+    } else {
+        format!("{repo}#{number}")
+    }
+}
+
+/// Extract the ICE message from the error output in an issue
+///
+/// Handles both `internal compiler error:` lines and `thread 'rustc' panicked`
+/// messages, in both the old (`panicked at 'msg', file`) and new
+/// (`panicked at file:line:col:\nmsg`) formats.
+pub(crate) fn ice_message(body: &str) -> Option<String> {
+    let ice_regex =
+        Regex::new(r"(?m)^error: internal compiler error: (?:[^\s:]+:\d+:\d+: )?(?P<msg>.+)$")
+            .unwrap();
+    for c in ice_regex.captures_iter(body) {
+        let msg = c["msg"].trim();
+        // Older versions of rustc print this for all panics
+        if msg != "unexpected panic" {
+            return Some(msg.to_string());
+        }
+    }
+    let old_panic_regex =
+        Regex::new(r"(?m)^thread '[^']+' panicked at '(?P<msg>.+)', \S+:\d+:\d+").unwrap();
+    if let Some(c) = old_panic_regex.captures(body) {
+        return Some(c["msg"].trim().to_string());
+    }
+    let new_panic_regex =
+        Regex::new(r"(?m)^thread '[^']+' panicked at \S+:\d+:\d+:\r?\n(?P<msg>.+)$").unwrap();
+    if let Some(c) = new_panic_regex.captures(body) {
+        return Some(c["msg"].trim().to_string());
+    }
+    None
+}
+
+/// The `rustc --version --verbose` output in an issue (preferably in its
+/// `### Meta` section), as `release:` and `commit-date:` lines
+pub(crate) fn rustc_version(body: &str) -> Option<String> {
+    let meta = body.find("### Meta").map_or(body, |i| &body[i..]);
+    let release = Regex::new(r"(?m)^\s*release:\s*(\S+)").unwrap();
+    let date = Regex::new(r"(?m)^\s*commit-date:\s*(\d{4}-\d{2}-\d{2})").unwrap();
+    // Just `rustc --version`
+    let short =
+        Regex::new(r"rustc (\d+\.\d+\.\d+(?:-[a-z]+)?) \([0-9a-f]+ (\d{4}-\d{2}-\d{2})\)").unwrap();
+    let (release, date) = match (release.captures(meta), date.captures(meta)) {
+        (Some(r), Some(d)) => (r[1].to_string(), d[1].to_string()),
+        _ => {
+            let c = short.captures(meta)?;
+            (c[1].to_string(), c[2].to_string())
+        }
+    };
+    Some(format!("release: {release}\ncommit-date: {date}\n"))
+}
+
+/// Flags that take a value, either inline (`--edition=2021`, `-Zflag`) or as
+/// the next argument
+const VALUE_FLAGS: &[&str] = &["--edition", "--crate-type", "--cfg", "--target", "-Z", "-C"];
+
+/// Flags in a rustc command line that likely matter for an ICE (e.g., not the
+/// input or output), each with its value
+pub(crate) fn relevant_flags(args: &[String]) -> Vec<Vec<String>> {
+    let mut flags = Vec::new();
+    let mut args = args.iter().map(String::as_str);
+    while let Some(arg) = args.next() {
+        if VALUE_FLAGS.contains(&arg) {
+            if let Some(value) = args.next() {
+                flags.push(vec![arg.to_string(), value.to_string()]);
+            }
+            continue;
+        }
+        let inline = VALUE_FLAGS.iter().any(|f| {
+            arg.starts_with(&format!("{f}="))
+                || (f.len() == 2 && arg.len() > 2 && arg.starts_with(f))
+        });
+        if inline || arg == "-O" || arg == "--test" {
+            flags.push(vec![arg.to_string()]);
+        }
+    }
+    flags
+}
+
+/// What a flag sets, e.g., `--edition` or `-Zmir-opt-level`, to avoid
+/// passing it twice
+pub(crate) fn flag_name(flag: &[String]) -> String {
+    let flag = flag.concat();
+    if flag.starts_with("--cfg") {
+        return flag;
+    }
+    let flag = flag.split('=').next().unwrap_or_default();
+    VALUE_FLAGS
+        .iter()
+        .find(|f| f.starts_with("--") && flag.starts_with(*f))
+        .map_or(flag, |f| f)
+        .to_string()
+}
+
+/// Compiler flags mentioned in an issue: in rustc command lines,
+/// `RUSTFLAGS`, and compiletest `compile-flags` headers
+pub(crate) fn flags(body: &str) -> Vec<Vec<String>> {
+    // At the start of a line (e.g., in a code block) or in inline code
+    let command = Regex::new(r"(?m)(?:^\s*[$>]?\s*|`)rustc(?: \+\S+)? ([^`\n]*)").unwrap();
+    let rustflags = Regex::new(r#"(?m)RUSTFLAGS=["']?([^"'`\n]*)"#).unwrap();
+    let header = Regex::new(r"(?m)^\s*//@?\s*compile-flags:([^\n]*)").unwrap();
+    let mut flags = Vec::new();
+    for rx in [&command, &rustflags, &header] {
+        for c in rx.captures_iter(body) {
+            flags.extend(relevant_flags(&check::split_words(&c[1])));
+        }
+    }
+    flags
+}
+
+/// Build a regex that matches an ICE message
+///
+/// The result works both as a Rust regex and as an `egrep` pattern in a
+/// single-quoted shell string (for `cargo-bisect-rustc`). Numbers are
+/// generalized, as they often refer to `DefId`s and the like, which change
+/// during reduction.
+pub(crate) fn message_regex(msg: &str) -> String {
+    let mut rx = String::with_capacity(msg.len() * 2);
+    let mut in_number = false;
+    for c in msg.chars() {
+        if c.is_ascii_digit() {
+            if !in_number {
+                rx.push_str("[0-9]+");
+            }
+            in_number = true;
+            continue;
+        }
+        in_number = false;
+        match c {
+            '\\' | '.' | '+' | '*' | '?' | '(' | ')' | '|' | '[' | ']' | '{' | '}' | '^' | '$' => {
+                rx.push('\\');
+                rx.push(c);
+            }
+            '\'' => rx.push('.'),
+            _ => rx.push(c),
+        }
+    }
+    rx
+}
+
+/// Fenced Rust code blocks in Markdown
+#[cfg(feature = "fetch")]
+pub(crate) fn rust_blocks(markdown: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut block: Option<Vec<&str>> = None;
+    for line in markdown.lines() {
+        let trimmed = line.trim_start();
+        match &mut block {
+            Some(b) if trimmed.starts_with("```") => {
+                blocks.push(b.join("\n"));
+                block = None;
+            }
+            Some(b) => b.push(line),
+            None => {
+                let lang = trimmed.strip_prefix("```").map(|l| l.trim().to_lowercase());
+                if matches!(lang.as_deref(), Some("rust" | "rs")) {
+                    block = Some(Vec::new());
+                }
+            }
+        }
+    }
+    blocks
+}
+
+/// Each Rust code block in an issue body, those under `### Code` (from the
+/// ICE issue template) first
+#[cfg(feature = "fetch")]
+pub(crate) fn body_blocks(body: &str) -> Vec<String> {
+    let heading = Regex::new(r"^#{1,6}\s").unwrap();
+    let mut section = Vec::new();
+    let mut in_code_section = false;
+    let mut in_fence = false;
+    for line in body.lines() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+        } else if !in_fence && heading.is_match(line) {
+            in_code_section = line.starts_with("### Code");
+            continue;
+        }
+        if in_code_section {
+            section.push(line);
+        }
+    }
+    let mut blocks = rust_blocks(&section.join("\n"));
+    for block in rust_blocks(body) {
+        if !blocks.contains(&block) {
+            blocks.push(block);
+        }
+    }
+    blocks
+}
+
+/// How promising a comment is as a source of a reproduction, higher is better
+#[cfg(feature = "fetch")]
+pub(crate) fn comment_priority(body: &str, by_issue_author: bool) -> usize {
+    let lower = body.to_lowercase();
+    let keywords = ["reduced", "minimal", "minimized", "mcve"]
+        .iter()
+        .filter(|k| lower.contains(*k))
+        .count();
+    usize::from(by_issue_author) * 2 + usize::from(keywords > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_cfg() {
+        let flags = flags("```\n$ rustc --cfg 'feature=\"foo\"' -Zmir-opt-level=3 x.rs\n```");
+        assert_eq!(
+            flags,
+            vec![
+                vec![String::from("--cfg"), String::from("feature=\"foo\"")],
+                vec![String::from("-Zmir-opt-level=3")],
+            ]
+        );
+    }
+
+    #[test]
+    fn header_flags() {
+        let flags = flags("//@ compile-flags: --edition 2021 -O --crate-type=lib");
+        assert_eq!(
+            flags,
+            vec![
+                vec![String::from("--edition"), String::from("2021")],
+                vec![String::from("-O")],
+                vec![String::from("--crate-type=lib")],
+            ]
+        );
+    }
+}
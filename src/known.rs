@@ -0,0 +1,60 @@
+//! Signatures of known ICEs, to warn about duplicates before reducing
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{history, issue};
+
+/// An open issue and its ICE message
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Known {
+    pub(crate) issue: usize,
+    pub(crate) title: String,
+    pub(crate) signature: String,
+}
+
+/// `known.jsonl` in the data directory, see `icemelter update-known`
+pub(crate) fn default_path() -> Option<PathBuf> {
+    Some(history::data_dir()?.join("known.jsonl"))
+}
+
+pub(crate) fn read(path: &Path) -> Result<Vec<Known>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read known ICEs from {}", path.display()))?;
+    text.lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| serde_json::from_str(l).context("Malformed known ICEs file"))
+        .collect()
+}
+
+#[cfg(feature = "fetch")]
+pub(crate) fn write(path: &Path, known: &[Known]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    }
+    let mut text = String::new();
+    for k in known {
+        text.push_str(&serde_json::to_string(k).context("Failed to serialize known ICE")?);
+        text.push('\n');
+    }
+    crate::atomic::write(path, text)
+        .with_context(|| format!("Failed to write known ICEs to {}", path.display()))
+}
+
+/// Known ICEs with the same message, up to numbers (see
+/// [`issue::message_regex`])
+pub(crate) fn matching<'a>(known: &'a [Known], message: &str) -> Vec<&'a Known> {
+    known
+        .iter()
+        .filter(|k| {
+            Regex::new(&format!("^{}$", issue::message_regex(&k.signature)))
+                .map(|r| r.is_match(message))
+                .unwrap_or(false)
+        })
+        .collect()
+}
@@ -0,0 +1,76 @@
+use crate::backtrace::{frame_crate, Backtrace};
+
+/// Compiler crates and the area labels they suggest
+const CRATE_LABELS: &[(&str, &str)] = &[
+    ("rustc_borrowck", "A-borrow-checker"),
+    ("rustc_codegen_llvm", "A-LLVM"),
+    ("rustc_codegen_ssa", "A-codegen"),
+    ("rustc_const_eval", "A-const-eval"),
+    ("rustc_expand", "A-macros"),
+    ("rustc_hir_typeck", "A-type-system"),
+    ("rustc_incremental", "A-incr-comp"),
+    ("rustc_lint", "A-lints"),
+    ("rustc_mir_build", "A-MIR"),
+    ("rustc_mir_transform", "A-mir-opt"),
+    ("rustc_monomorphize", "A-monomorphization"),
+    ("rustc_parse", "A-parser"),
+    ("rustc_pattern_analysis", "A-patterns"),
+    ("rustc_resolve", "A-resolve"),
+    ("rustc_trait_selection", "A-trait-system"),
+    ("rustc_ty_utils", "A-layout"),
+];
+
+/// Queries and the area labels they suggest
+const QUERY_LABELS: &[(&str, &str)] = &[
+    ("codegen_select_candidate", "A-trait-system"),
+    ("collect_and_partition_mono_items", "A-monomorphization"),
+    ("eval_to_allocation_raw", "A-const-eval"),
+    ("eval_to_const_value_raw", "A-const-eval"),
+    ("evaluate_obligation", "A-trait-system"),
+    ("layout_of", "A-layout"),
+    ("mir_borrowck", "A-borrow-checker"),
+    ("optimized_mir", "A-mir-opt"),
+    ("resolver_for_lowering", "A-resolve"),
+    ("typeck", "A-type-system"),
+];
+
+fn crate_label(krate: &str) -> Option<&'static str> {
+    CRATE_LABELS
+        .iter()
+        .find(|(k, _)| *k == krate)
+        .map(|(_, label)| *label)
+}
+
+fn query_label(query: &str) -> Option<&'static str> {
+    QUERY_LABELS
+        .iter()
+        .find(|(q, _)| *q == query)
+        .map(|(_, label)| *label)
+}
+
+/// Suggest area labels (`A-*`) for an ICE
+///
+/// Looks at where the compiler panicked, the innermost query, and the
+/// innermost backtrace frame in a compiler crate with a known area.
+pub(crate) fn suggest(bt: &Backtrace) -> Vec<&'static str> {
+    let mut labels = Vec::new();
+    let mut add = |label: Option<&'static str>| {
+        if let Some(l) = label {
+            if !labels.contains(&l) {
+                labels.push(l);
+            }
+        }
+    };
+    for krate in &bt.location_crates {
+        add(crate_label(krate));
+    }
+    if let Some(query) = bt.queries.first() {
+        add(query_label(query));
+    }
+    add(bt
+        .frames
+        .iter()
+        .filter_map(|f| frame_crate(f))
+        .find_map(crate_label));
+    labels
+}
@@ -1,14 +1,15 @@
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::fs;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
 use std::os::unix::prelude::PermissionsExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process;
 use std::process::Command;
 use std::process::Stdio;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{Context, Result};
 use clap::Parser;
 use clap_verbosity_flag::{InfoLevel, Verbosity};
 use regex::Regex;
@@ -18,27 +19,176 @@ use tracing::info;
 use tracing::warn;
 use tracing_subscriber::fmt::format::FmtSpan;
 use treereduce::Check;
-use treereduce::CmdCheck;
+
+use crate::check::{Aux, IceCheck};
 use treereduce::Config;
 use treereduce::NodeTypes;
 use treereduce::Original;
 
+mod atomic;
+#[cfg(feature = "fetch")]
+mod attachments;
+mod backend;
+mod backtrace;
+mod batch;
+mod bisect_local;
+#[cfg(feature = "fetch")]
+mod cache;
+mod cargo;
+mod check;
+mod crashes;
+#[cfg(feature = "fetch")]
+mod crater;
+mod environment;
+mod externs;
+mod flatten;
 mod formatter;
 #[cfg(feature = "fetch")]
 mod github;
+mod glacier;
+mod history;
+#[cfg(feature = "fetch")]
+mod http;
+mod intake;
+mod issue;
+mod known;
+mod labels;
+mod notify;
+mod passes;
+mod phase;
+#[cfg(feature = "fetch")]
+mod playground;
+#[cfg(feature = "fetch")]
+mod post;
+mod project;
+mod runs;
+mod sanitize;
+mod serve;
+mod temp;
+mod trace;
+mod tui;
+#[cfg(feature = "fetch")]
+mod update;
+mod watch;
+mod wrap;
+#[cfg(feature = "fetch")]
+mod zulip;
+
+const DEFAULT_INTERESTING_STDERR: &str =
+    r"(internal compiler error:|error: the compiler unexpectedly panicked\. this is a bug\.)";
 
 /// A tool to minimize Rust files that trigger internal compiler errors (ICEs)
 #[derive(Clone, Debug, clap::Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<SubCommand>,
+
     /// Allow introducing type/syntax/borrow errors to achieve smaller tests
     #[arg(long)]
     allow_errors: bool,
 
+    /// Mark the report as partially reduced unless the output is at least
+    /// this fraction smaller than the input (e.g., 0.5)
+    #[arg(long, value_name = "RATIO", value_parser = parse_ratio)]
+    min_reduction_ratio: Option<f64>,
+
+    /// Only accept candidates that don't ICE with this toolchain (e.g.,
+    /// +stable), to keep regressions from turning into older bugs
+    #[arg(
+        long,
+        value_name = "TOOLCHAIN",
+        value_parser = parse_toolchain,
+        conflicts_with_all = ["local_rustc", "docker"]
+    )]
+    baseline: Option<String>,
+
+    /// After reducing, try adding `#![no_std]`, keeping it if the ICE
+    /// persists
+    #[arg(long)]
+    try_no_std: bool,
+
+    /// After reducing, try adding `#![allow(...)]` for the lints that warn, so
+    /// that the output only shows the ICE
+    #[arg(long)]
+    suppress_warnings: bool,
+
+    /// Require candidates to emit exactly the same error codes as the input,
+    /// as many times each, not just no new ones
+    #[arg(long, conflicts_with = "allow_errors")]
+    preserve_diagnostics: bool,
+
+    /// Accept candidates with any ICE, not just one with the input's location
+    /// (or message, without one) and query stack
+    #[arg(long)]
+    any_ice: bool,
+
     /// Run `cargo-bisect-rustc`; takes a long time, but is very helpful!
     #[arg(short, long)]
     bisect: bool,
 
+    /// Write a script for cargo-bisect-rustc and print the command to run it,
+    /// instead of bisecting
+    #[arg(
+        long,
+        conflicts_with_all = ["bisect", "local_rustc", "alt", "docker", "aux", "proc_macro"]
+    )]
+    bisect_print: bool,
+
+    /// Run `git bisect` in this rust-lang/rust checkout, building stage 1 at each step
+    #[arg(long, value_name = "DIR", requires = "bisect_local_good")]
+    bisect_local: Option<PathBuf>,
+
+    /// Known good commit for --bisect-local
+    #[arg(long, value_name = "REV")]
+    bisect_local_good: Option<String>,
+
+    /// Known bad commit for --bisect-local
+    #[arg(long, value_name = "REV", default_value = "HEAD")]
+    bisect_local_bad: String,
+
+    /// Auxiliary crate the ICE needs, compiled as a library before the main
+    /// file; can be repeated, in dependency order
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["bisect", "bisect_local"])]
+    aux: Vec<PathBuf>,
+
+    /// Proc macro crate the ICE needs, compiled before the main file; can be
+    /// repeated
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["bisect", "bisect_local"])]
+    proc_macro: Vec<PathBuf>,
+
+    /// Codegen backend to use; also catches backend-specific errors
+    #[arg(long, value_enum)]
+    backend: Option<backend::Backend>,
+
+    /// Set RUSTC_BOOTSTRAP=1 to allow -Z flags on stable and beta toolchains
+    #[arg(long)]
+    bootstrap: bool,
+
+    /// Don't add compiler flags mentioned in the issue (e.g., `--edition 2021`)
+    #[arg(long)]
+    no_auto_flags: bool,
+
+    /// File to record runs in, for `icemelter stats` (default:
+    /// `$XDG_DATA_HOME/icemelter/history.jsonl`)
+    #[arg(long, value_name = "FILE")]
+    history: Option<PathBuf>,
+
+    /// Don't record this run in the history file
+    #[arg(long, conflicts_with = "history")]
+    no_history: bool,
+
+    /// When to use color (and status glyphs) in output
+    #[arg(long, value_name = "WHEN", value_enum, default_value_t)]
+    color: formatter::ColorChoice,
+
     /// Run a single thread and show stdout, stderr of rustc
     #[arg(short, long)]
     debug: bool,
@@ -47,42 +197,465 @@ struct Args {
     #[arg(
         long,
         value_name = "REGEX",
-        default_value_t = String::from(r"(internal compiler error:|error: the compiler unexpectedly panicked\. this is a bug\.)")
+        default_value_t = String::from(DEFAULT_INTERESTING_STDERR)
     )]
     interesting_stderr: String,
 
+    /// Known ICE signatures to check for duplicates [default: see
+    /// `icemelter update-known`]
+    #[arg(long, value_name = "FILE")]
+    known: Option<PathBuf>,
+
     /// Regex to match *uninteresting* stderr, overrides interesting regex
     #[arg(long, value_name = "REGEX", requires = "interesting_stderr")]
     uninteresting_stderr: Option<String>,
 
+    /// Proceed even if the issue is closed, already has an MCVE, or the ICE
+    /// matches a known issue, and overwrite existing output files
+    #[arg(long)]
+    force: bool,
+
+    /// Append a summary of the run to this CSV file
+    #[arg(long, value_name = "FILE")]
+    export_csv: Option<PathBuf>,
+
+    /// Post the Markdown report as a comment on the issue, after confirmation
+    #[arg(long)]
+    post: bool,
+
+    /// Check that --post would work, without commenting
+    #[arg(long, conflicts_with = "post")]
+    post_dry_run: bool,
+
+    /// Open a draft PR adding the reduced file to rust-lang/rust's
+    /// tests/crashes, after confirmation
+    #[arg(long)]
+    crashes_pr: bool,
+
+    /// Fork of rust-lang/rust (OWNER/REPO) to push the --crashes-pr branch
+    /// to [default: fork it]
+    #[arg(long, value_name = "REPO", requires = "crashes_pr")]
+    fork: Option<String>,
+
+    /// Don't ask for confirmation before posting or opening a PR
+    #[arg(short, long)]
+    yes: bool,
+
+    /// Don't replace home directories, usernames, and machine names in
+    /// reports, PRs, and summaries
+    #[arg(long)]
+    no_sanitize: bool,
+
+    /// First check if the ICE still reproduces on the latest nightly
+    #[arg(long)]
+    preflight: bool,
+
+    /// Run `rustup update nightly` before checking the latest nightly (with
+    /// --preflight, --alt, or --local-rustc), instead of using the installed
+    /// one
+    #[arg(long)]
+    update_nightly: bool,
+
+    /// Keep temporary files (candidates, scripts, etc.) in a new directory,
+    /// for debugging
+    #[arg(long)]
+    keep_temps: bool,
+
+    /// Suffix for candidate files, when the check command has no `@@`
+    /// placeholder (e.g., `.txt`, or '' for none)
+    #[arg(long, value_name = "SUFFIX", default_value = ".rs")]
+    candidate_suffix: String,
+
+    /// Name candidate files NAME (plus the suffix) instead of giving them
+    /// random names, e.g., for a stable crate name
+    #[arg(long, value_name = "NAME")]
+    candidate_name: Option<String>,
+
+    /// File to reduce in a Cargo project, relative to its root [default:
+    /// src/main.rs or src/lib.rs]
+    #[arg(long, value_name = "FILE")]
+    project_file: Option<PathBuf>,
+
+    /// Set an environment variable for rustc, can be repeated
+    #[arg(long, value_name = "KEY=VALUE", value_parser = parse_env)]
+    env: Vec<(String, String)>,
+
     /// Number of threads
     #[arg(short, long, default_value_t = num_cpus::get())]
     jobs: usize,
 
+    /// Prefer candidates that compile quickly, rejecting those that take much
+    /// longer to check than the input
+    #[arg(long)]
+    optimize_check_time: bool,
+
+    /// Use the alt build (with debug assertions) of this rust-lang/rust
+    /// commit, installed with rustup-toolchain-install-master
+    #[arg(
+        long,
+        value_name = "COMMIT",
+        conflicts_with_all = ["bisect", "preflight", "local_rustc"]
+    )]
+    alt: Option<String>,
+
+    /// Run every check in a container from this image, which must have
+    /// rustc; consider raising --timeout
+    #[arg(
+        long,
+        value_name = "IMAGE",
+        conflicts_with_all = ["bisect", "bisect_local", "preflight", "local_rustc", "alt"]
+    )]
+    docker: Option<String>,
+
+    /// Path to a locally built rustc (e.g., build/<target>/stage1/bin/rustc)
+    #[arg(long, value_name = "RUSTC", conflicts_with_all = ["bisect", "preflight"])]
+    local_rustc: Option<PathBuf>,
+
     /// Also output markdown
     #[arg(long)]
     markdown: bool,
 
-    /// Where to save reduced test case
-    #[arg(short, long, default_value_os = "melted.rs")]
-    output: PathBuf,
+    /// Also write a Cargo project that reproduces the ICE (with a pinned
+    /// toolchain, flags, and target) to this directory
+    #[arg(long, value_name = "DIR")]
+    emit_project: Option<PathBuf>,
+
+    /// Include the original code in the Markdown report, in a collapsed
+    /// section
+    #[arg(long)]
+    report_include_original: bool,
+
+    /// Notify when finished: 'desktop' or 'url:<webhook>'
+    #[arg(long, value_name = "HOW", value_parser = notify::parse)]
+    notify: Option<notify::Notify>,
+
+    /// Where to save reduced test case [default: melted.rs, or
+    /// issue-N/run-K/melted.rs for issues]
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Serve a dashboard showing progress at this address (e.g., 127.0.0.1:8080),
+    /// also for several inputs
+    #[arg(long, value_name = "ADDR")]
+    serve: Option<String>,
+
+    /// Show reduction progress in the terminal, with keys to pause, skip to
+    /// formatting, or abort and save the best candidate so far
+    #[arg(long)]
+    tui: bool,
+
+    /// Compile the test harness (`rustc --test`), for ICEs in `#[cfg(test)]`
+    /// code or tests
+    #[arg(long)]
+    test: bool,
+
+    /// Compile for this target (installed with rustup if needed)
+    #[arg(long, value_name = "TRIPLE")]
+    target: Option<String>,
+
+    /// Also check whether the reduced file ICEs on these targets, for the
+    /// report
+    #[arg(long, value_name = "TRIPLE", value_delimiter = ',')]
+    verify_targets: Vec<String>,
+
+    /// Timeout for each check (e.g., 2s, or milliseconds)
+    #[arg(long, value_name = "DURATION", default_value = "2s", value_parser = parse_duration)]
+    timeout: Duration,
+
+    /// Give each candidate time in proportion to its size, relative to how
+    /// long the input takes to check, instead of --timeout
+    #[arg(long)]
+    scale_timeout: bool,
+
+    /// Record every candidate checked during reduction in this JSON lines
+    /// file
+    #[arg(long, value_name = "FILE")]
+    trace: Option<PathBuf>,
+
+    /// Proxy for GitHub and Zulip requests [default: from HTTPS_PROXY, etc.]
+    #[arg(long, value_name = "URL")]
+    proxy: Option<String>,
+
+    /// Extra CA certificate (PEM) to trust, e.g., for a corporate proxy
+    #[arg(long, value_name = "FILE")]
+    ca_cert: Option<PathBuf>,
+
+    /// Fetch issues and comments again instead of revalidating cached copies
+    #[arg(long)]
+    refresh: bool,
 
-    /// Timeout (ms)
-    #[arg(long, default_value_t = 2000)]
-    timeout: u64,
+    /// GitHub API to use, e.g., https://github.example.com/api/v3 for GitHub
+    /// Enterprise [default: GITHUB_API_URL or https://api.github.com]
+    #[arg(long, value_name = "URL")]
+    github_api_url: Option<String>,
 
     #[clap(flatten)]
     verbose: Verbosity<InfoLevel>,
 
-    /// Rust source file that causes the ICE, or rust-lang/rust issue number
-    #[arg(value_name = "ICE", required = true)]
-    source: String,
+    /// Post a summary to this Zulip stream (see ZULIP_EMAIL, ZULIP_API_KEY)
+    #[arg(long, value_name = "STREAM")]
+    zulip_stream: Option<String>,
 
-    /// rustc command line (without the file)
-    #[arg(value_name = "CMD", default_values_t = vec![String::from("rustc")], num_args = 1..)]
+    /// Zulip topic, defaults to the issue number or input
+    #[arg(long, value_name = "TOPIC", requires = "zulip_stream")]
+    zulip_topic: Option<String>,
+
+    /// Repository of the issue, e.g., rust-lang/rust-clippy (a URL or
+    /// OWNER/NAME#N input names its own)
+    #[arg(long, value_name = "OWNER/NAME", default_value = issue::RUST_REPO)]
+    repo: String,
+
+    /// Rust source file that causes the ICE (path or URL), Cargo project
+    /// (directory or Cargo.toml), issue (`#N`, `OWNER/NAME#N`, or a URL), or
+    /// `-` for stdin; several (or a directory or glob of Rust files) are
+    /// melted one after another
+    #[arg(
+        value_name = "ICE",
+        required_unless_present = "query",
+        conflicts_with = "query",
+        num_args = 1..
+    )]
+    sources: Vec<String>,
+
+    /// Melt every issue matching a GitHub search, e.g., 'label:I-ICE
+    /// label:E-needs-mcve state:open' (in --repo unless it has `repo:`)
+    #[arg(long, value_name = "QUERY")]
+    query: Option<String>,
+
+    /// Treat the input as a crater log (file or URL), and reduce the crate
+    /// that ICEd. This builds a third-party crate, which runs its build
+    /// script and procedural macros.
+    #[arg(long)]
+    crater: bool,
+
+    /// Treat the input as a glacier fixture (`fixtures/N.rs`), requiring the
+    /// ICE in its expected stderr (`fixtures/N.stderr`) and replacing both
+    /// with the reduction
+    #[arg(long)]
+    glacier: bool,
+
+    /// rustc command line (without the file), `cargo build` for a Cargo
+    /// project if not given
+    #[arg(value_name = "CMD", default_values_t = vec![String::from("rustc")], num_args = 1.., last = true)]
     check: Vec<String>,
 }
 
+#[derive(Clone, Debug, clap::Subcommand)]
+enum SubCommand {
+    /// Show statistics about previous runs
+    Stats {
+        /// File where runs were recorded
+        #[arg(long, value_name = "FILE")]
+        history: Option<PathBuf>,
+    },
+    /// Download the ICE messages of open I-ICE issues, to warn about
+    /// duplicates before reducing
+    UpdateKnown {
+        /// File to save them in
+        #[arg(long, value_name = "FILE")]
+        known: Option<PathBuf>,
+    },
+    /// Manage the GitHub token in the system keyring
+    Auth {
+        #[command(subcommand)]
+        command: AuthCommand,
+    },
+    /// Replace this binary with the latest release
+    SelfUpdate {
+        /// Only check whether there's a newer release
+        #[arg(long)]
+        check: bool,
+    },
+    /// Group crashing inputs (e.g., from fuzz-rustc) by ICE, and melt one from
+    /// each group
+    Intake {
+        /// Directory of crashing inputs
+        #[arg(value_name = "DIR")]
+        dir: PathBuf,
+
+        /// Where to put a report directory for each ICE
+        #[arg(short, long, value_name = "DIR", default_value = "intake")]
+        output: PathBuf,
+
+        /// Serve a dashboard showing progress at this address (e.g.,
+        /// 127.0.0.1:8080)
+        #[arg(long, value_name = "ADDR")]
+        serve: Option<String>,
+
+        /// Timeout for each check (e.g., 2s, or milliseconds)
+        #[arg(long, value_name = "DURATION", default_value = "2s", value_parser = parse_duration)]
+        timeout: Duration,
+
+        /// rustc command line (without the file)
+        #[arg(value_name = "CMD", default_values_t = vec![String::from("rustc")], num_args = 1..)]
+        check: Vec<String>,
+    },
+    /// Compare two ICEs (stderr captures, or .rs files to run) and report
+    /// how likely they are to be the same bug
+    DiffIce {
+        /// rustc's stderr after an ICE, or a .rs file that ICEs
+        #[arg(value_name = "ICE")]
+        first: PathBuf,
+
+        /// rustc's stderr after an ICE, or a .rs file that ICEs
+        #[arg(value_name = "ICE")]
+        second: PathBuf,
+
+        /// Timeout for running .rs files (e.g., 2s, or milliseconds)
+        #[arg(long, value_name = "DURATION", default_value = "2s", value_parser = parse_duration)]
+        timeout: Duration,
+
+        /// rustc command line for .rs files (without the file)
+        #[arg(value_name = "CMD", default_values_t = vec![String::from("rustc")], num_args = 1..)]
+        check: Vec<String>,
+    },
+    /// Melt each file that appears in a directory (e.g., from a fuzzer),
+    /// moving it and the results into done/ or failed/
+    WatchDir {
+        /// Directory to watch
+        #[arg(value_name = "DIR")]
+        dir: PathBuf,
+
+        /// How often to look for new files (e.g., 2s, or seconds)
+        #[arg(long, value_name = "DURATION", default_value = "2s", value_parser = parse_interval)]
+        interval: Duration,
+
+        /// Serve a dashboard showing progress at this address (e.g.,
+        /// 127.0.0.1:8080)
+        #[arg(long, value_name = "ADDR")]
+        serve: Option<String>,
+
+        /// Timeout for each check (e.g., 2s, or milliseconds)
+        #[arg(long, value_name = "DURATION", default_value = "2s", value_parser = parse_duration)]
+        timeout: Duration,
+
+        /// rustc command line (without the file)
+        #[arg(value_name = "CMD", default_values_t = vec![String::from("rustc")], num_args = 1..)]
+        check: Vec<String>,
+    },
+    /// Run a build (e.g., `cargo build`) with icemelter as RUSTC_WRAPPER,
+    /// then melt each rustc invocation that ICEd
+    Wrap {
+        /// Directory for the captured invocations and results
+        #[arg(long, value_name = "DIR", default_value = "icemelter-wrap")]
+        output: PathBuf,
+
+        /// Timeout for each check (e.g., 2s, or milliseconds)
+        #[arg(long, value_name = "DURATION", default_value = "2s", value_parser = parse_duration)]
+        timeout: Duration,
+
+        /// Build command line
+        #[arg(value_name = "CMD", required = true, num_args = 1..)]
+        cmd: Vec<String>,
+    },
+    /// Rebuild the output of a run from its --trace without running rustc,
+    /// optionally re-checking some steps
+    Replay {
+        /// Trace written with --trace
+        #[arg(value_name = "TRACE")]
+        trace: PathBuf,
+
+        /// The original input
+        #[arg(value_name = "ICE")]
+        original: PathBuf,
+
+        /// Original auxiliary crate, as passed to --aux
+        #[arg(long, value_name = "FILE")]
+        aux: Vec<PathBuf>,
+
+        /// Original proc macro crate, as passed to --proc-macro
+        #[arg(long, value_name = "FILE")]
+        proc_macro: Vec<PathBuf>,
+
+        /// Re-run the recorded command on the candidates from these steps
+        #[arg(long, value_name = "STEP", value_delimiter = ',')]
+        verify: Vec<usize>,
+
+        /// Set an environment variable for rustc, can be repeated
+        #[arg(long, value_name = "KEY=VALUE", value_parser = parse_env)]
+        env: Vec<(String, String)>,
+
+        /// Timeout for each check (e.g., 2s, or milliseconds)
+        #[arg(long, value_name = "DURATION", default_value = "2s", value_parser = parse_duration)]
+        timeout: Duration,
+
+        /// Where to write the rebuilt file
+        #[arg(short, long, default_value = "replayed.rs")]
+        output: PathBuf,
+    },
+}
+
+#[derive(Clone, Debug, clap::Subcommand)]
+enum AuthCommand {
+    /// Save a GitHub token, read from stdin
+    Login,
+    /// Delete the saved GitHub token
+    Logout,
+}
+
+/// A fraction in (0, 1]
+fn parse_ratio(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(r) if r > 0.0 && r <= 1.0 => Ok(r),
+        _ => Err(format!("expected a number between 0 and 1, found '{s}'")),
+    }
+}
+
+/// Durations like `2s`, `30m`, or `1h30m`; bare numbers are milliseconds
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    if let Ok(ms) = s.parse::<u64>() {
+        return Ok(Duration::from_millis(ms));
+    }
+    let unit_regex = Regex::new(r"^(\d+(?:\.\d+)?)\s*(ms|sec|s|min|m|h)").unwrap();
+    let mut total = Duration::ZERO;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let Some(c) = unit_regex.captures(rest) else {
+            return Err(format!(
+                "expected a duration like 2s, 30m, or 1h, found '{s}'"
+            ));
+        };
+        let n = c[1].parse::<f64>().map_err(|e| e.to_string())?;
+        let unit = match &c[2] {
+            "ms" => 0.001,
+            "s" | "sec" => 1.0,
+            "m" | "min" => 60.0,
+            _ => 3600.0,
+        };
+        total += Duration::from_secs_f64(n * unit);
+        rest = rest[c[0].len()..].trim_start();
+    }
+    if total.is_zero() {
+        return Err(String::from("expected a positive duration"));
+    }
+    Ok(total)
+}
+
+/// Like [`parse_duration`], but a bare number is in seconds, as it always was
+/// for `--interval`
+fn parse_interval(s: &str) -> Result<Duration, String> {
+    match s.trim().parse::<u64>() {
+        Ok(0) => Err(String::from("expected a positive duration")),
+        Ok(secs) => Ok(Duration::from_secs(secs)),
+        Err(_) => parse_duration(s),
+    }
+}
+
+fn parse_toolchain(s: &str) -> Result<String, String> {
+    match s.strip_prefix('+').unwrap_or(s) {
+        "" => Err(String::from("expected a toolchain, like +stable")),
+        tc => Ok(tc.to_string()),
+    }
+}
+
+fn parse_env(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((k, v)) if !k.is_empty() => Ok((k.to_string(), v.to_string())),
+        _ => Err(format!("expected KEY=VALUE, found '{s}'")),
+    }
+}
+
 #[inline]
 fn log_tracing_level(level: &log::Level) -> tracing::Level {
     match level {
@@ -96,72 +669,340 @@ fn log_tracing_level(level: &log::Level) -> tracing::Level {
 
 #[inline]
 fn init_tracing(args: &Args) {
+    let color = args.color.enabled();
     let builder = tracing_subscriber::fmt::fmt()
         .with_span_events(FmtSpan::ENTER | FmtSpan::CLOSE)
         .with_target(false)
+        .with_ansi(color)
         .with_max_level(log_tracing_level(
             &args.verbose.log_level().unwrap_or(log::Level::Info),
         ));
-    builder.event_format(formatter::TerseFormatter).init();
+    #[cfg(feature = "tui")]
+    if args.tui {
+        builder
+            .event_format(formatter::TerseFormatter { color })
+            .with_writer(tui::LogWriter::default)
+            .init();
+        return;
+    }
+    builder
+        .event_format(formatter::TerseFormatter { color })
+        .init();
 }
 
 fn read_file(file: &str) -> Result<String> {
     fs::read_to_string(file).with_context(|| format!("Failed to read file {}", file))
 }
 
+/// Rust source code, and the body of the issue it came from (if any)
+struct Source {
+    code: String,
+    /// Other code from the issue, to try if `code` doesn't produce the ICE
+    alternatives: Vec<Alternative>,
+    /// Where the issue is (`owner/name`)
+    repo: String,
+    issue: Option<usize>,
+    issue_body: Option<String>,
+}
+
+struct Alternative {
+    code: String,
+    /// Where the code is from, e.g., "a comment by someone"
+    origin: String,
+}
+
 #[cfg(feature = "fetch")]
-fn retrieve_from_github(issue_number: usize) -> Result<String> {
-    let gh_config = github::Config::from_env()
-        .with_context(|| format!("Missing {} environment variable", github::Config::ENV_VAR))?;
-    let issue = github::get_issue(&gh_config, issue_number)
+fn retrieve_from_github(repo: &str, issue_number: usize, force: bool) -> Result<Source> {
+    let gh_config = github::Config::load_or_anonymous();
+    let issue = github::get_issue(&gh_config, repo, issue_number)
         .context("Failed to retrieve issue from Github")?;
     debug_assert_eq!(issue.number, issue_number);
-    let mut reproduction = Vec::new();
-    let mut in_code_section = false;
-    let mut in_code = false;
-    for line in issue.body.lines() {
-        if in_code {
-            if line.starts_with("```") {
-                in_code = false;
-                continue;
-            }
-            reproduction.push(line);
+    let reference = issue::reference(repo, issue_number);
+    let closed = issue.is_closed();
+    let has_mcve = issue.has_label("S-bug-has-mcve");
+    if closed {
+        warn!("Issue {} is closed!", reference);
+    }
+    if has_mcve {
+        warn!("Issue {} already has an MCVE (S-bug-has-mcve)!", reference);
+    }
+    if (closed || has_mcve) && !force {
+        error!("Reduction is probably unnecessary. Pass --force to proceed anyway.");
+        std::process::exit(1);
+    }
+    let blocks = issue::body_blocks(issue.body.as_deref().unwrap_or_default());
+    let count = blocks.len();
+    let mut alternatives = blocks
+        .into_iter()
+        .enumerate()
+        .map(|(i, code)| Alternative {
+            code,
+            origin: format!("code block {} of {} in the issue", i + 1, count),
+        })
+        .collect::<Vec<_>>();
+    for link in playground::links(issue.body.as_deref().unwrap_or_default()) {
+        match playground::code(&gh_config, &link) {
+            Ok(code) => alternatives.push(Alternative {
+                code,
+                origin: format!("the playground link {link}"),
+            }),
+            Err(e) => warn!("{:#}", e),
         }
-        if line.starts_with("### Code") {
-            in_code_section = true;
-        } else if line.starts_with('#') && in_code_section {
-            in_code_section = false;
+    }
+    for link in attachments::links(issue.body.as_deref().unwrap_or_default()) {
+        match attachments::code(&link) {
+            Ok(code) => alternatives.push(Alternative {
+                code,
+                origin: format!("the attachment {link}"),
+            }),
+            Err(e) => warn!("{:#}", e),
         }
-        if (line.starts_with("```rust") || line.starts_with("```Rust")) && in_code_section {
-            in_code = true;
+    }
+    match comment_blocks(&gh_config, repo, &issue) {
+        Ok(blocks) => alternatives.extend(blocks),
+        Err(e) if !alternatives.is_empty() => warn!("{:#}", e),
+        Err(e) => return Err(e),
+    }
+    let mut reproduction_str = String::new();
+    if !alternatives.is_empty() {
+        let first = alternatives.remove(0);
+        if count == 0 {
+            info!("No code in the issue, using code from {}", first.origin);
         }
+        reproduction_str = first.code;
     }
-    let reproduction_str = reproduction.join("\n");
     debug!("Reproduction:\n{}", reproduction_str);
-    Ok(reproduction_str)
+    Ok(Source {
+        code: reproduction_str,
+        alternatives,
+        repo: repo.to_string(),
+        issue: Some(issue_number),
+        issue_body: issue.body,
+    })
+}
+
+/// Rust code blocks in the comments, most promising first, see
+/// [`issue::comment_priority`]
+#[cfg(feature = "fetch")]
+fn comment_blocks(
+    gh_config: &github::Config,
+    repo: &str,
+    issue: &github::Issue,
+) -> Result<Vec<Alternative>> {
+    let comments = github::get_comments(gh_config, repo, issue.number)
+        .context("Failed to retrieve comments from Github")?;
+    debug!(
+        "Issue {} has {} comments",
+        issue::reference(repo, issue.number),
+        comments.len()
+    );
+    let author = issue.user.as_ref().map(|u| u.login.as_str());
+    let mut candidates = comments
+        .iter()
+        .filter_map(|c| {
+            let body = c.body.as_deref()?;
+            let login = c.user.as_ref().map(|u| u.login.as_str());
+            let priority = issue::comment_priority(body, login.is_some() && login == author);
+            Some((priority, login, issue::rust_blocks(body)))
+        })
+        .collect::<Vec<_>>();
+    // Stable, so earlier comments come first among equals
+    candidates.sort_by_key(|(priority, _, _)| std::cmp::Reverse(*priority));
+    Ok(candidates
+        .into_iter()
+        .flat_map(|(_, login, blocks)| {
+            let origin = format!("a comment by {}", login.unwrap_or("an unknown user"));
+            blocks.into_iter().map(move |code| Alternative {
+                code,
+                origin: origin.clone(),
+            })
+        })
+        .collect())
+}
+
+#[cfg(not(feature = "fetch"))]
+fn retrieve_from_github(_repo: &str, _issue_number: usize, _force: bool) -> Result<Source> {
+    Err(anyhow::anyhow!("You provided an issue number, but this version of Icemelter was compiled without the 'fetch' feature."))
+}
+
+/// Issues (`OWNER/NAME#N`) matching a search, in `repo` unless the query
+/// names one
+#[cfg(feature = "fetch")]
+fn search(query: &str, repo: &str) -> Result<Vec<String>> {
+    let query = if query.split_whitespace().any(|q| q.starts_with("repo:")) {
+        query.to_string()
+    } else {
+        format!("repo:{repo} {query}")
+    };
+    let gh_config = github::Config::load_or_anonymous();
+    let issues = github::search_issues(&gh_config, &query)?;
+    info!("Found {} issues matching {}", issues.len(), query);
+    Ok(issues
+        .iter()
+        .map(|i| format!("{}#{}", i.repo().unwrap_or(repo), i.number))
+        .collect())
+}
+
+#[cfg(not(feature = "fetch"))]
+fn search(_query: &str, _repo: &str) -> Result<Vec<String>> {
+    Err(anyhow::anyhow!(
+        "Icemelter was built without the `fetch` feature, can't search GitHub"
+    ))
+}
+
+fn is_url(source: &str) -> bool {
+    source.starts_with("https://") || source.starts_with("http://")
+}
+
+/// Larger downloads probably aren't reproductions
+#[cfg(feature = "fetch")]
+const MAX_DOWNLOAD_SIZE: usize = 1024 * 1024;
+
+/// Download Rust source from a link to a raw file (a GitHub file page is
+/// fetched raw)
+#[cfg(feature = "fetch")]
+fn download_source(url: &str) -> Result<String> {
+    let blob = Regex::new(r"^https://github\.com/([\w.-]+/[\w.-]+)/blob/(.+)$").unwrap();
+    let url = match blob.captures(url) {
+        Some(c) => format!("https://raw.githubusercontent.com/{}/{}", &c[1], &c[2]),
+        None => url.to_string(),
+    };
+    info!("Downloading {}...", url);
+    let bytes = http::get_limited(&url, MAX_DOWNLOAD_SIZE)?;
+    let code = String::from_utf8(bytes).with_context(|| format!("{url} isn't UTF-8"))?;
+    let start = code
+        .trim_start()
+        .get(..15)
+        .unwrap_or_default()
+        .to_lowercase();
+    if start.starts_with("<!doctype html") || start.starts_with("<html") {
+        anyhow::bail!("{url} is a web page, link to the raw file instead");
+    }
+    Ok(code)
+}
+
+#[cfg(not(feature = "fetch"))]
+fn download_source(url: &str) -> Result<String> {
+    Err(anyhow::anyhow!(
+        "Icemelter was built without the `fetch` feature, can't download {url}"
+    ))
 }
 
-fn retrieve(source: &str) -> Result<String> {
-    let issue_number_rx =
-        Regex::new(r"^#(\d+)").context("Internal error: bad issue number regex")?;
-    match issue_number_rx.find(source) {
+fn retrieve(source: &str, repo: &str, force: bool) -> Result<Source> {
+    if source == "-" {
+        debug!("Reading source from stdin");
+        let mut code = String::new();
+        io::stdin()
+            .read_to_string(&mut code)
+            .context("Failed to read from stdin")?;
+        return Ok(Source {
+            code,
+            alternatives: Vec::new(),
+            repo: repo.to_string(),
+            issue: None,
+            issue_body: None,
+        });
+    }
+    match issue::parse_reference(source) {
+        None if is_url(source) => {
+            debug!("Source looks like a URL");
+            Ok(Source {
+                code: download_source(source)?,
+                alternatives: Vec::new(),
+                repo: repo.to_string(),
+                issue: None,
+                issue_body: None,
+            })
+        }
         None => {
             debug!("Source looks like a file");
-            read_file(source)
+            Ok(Source {
+                code: read_file(source)?,
+                alternatives: Vec::new(),
+                repo: repo.to_string(),
+                issue: None,
+                issue_body: None,
+            })
         }
-        Some(m) => {
+        Some((source_repo, issue_number)) => {
             debug!("Source looks like an issue number");
-            let issue_number_str = m.as_str();
-            debug!("Match: {}", issue_number_str);
-            #[cfg(feature = "fetch")]
-            {
-                let issue_number = issue_number_str[1..]
-                    .parse::<usize>()
-                    .context("Internal error: Couldn't extract number from issue number regex")?;
-                return retrieve_from_github(issue_number);
+            let source_repo = source_repo.as_deref().unwrap_or(repo);
+            if source_repo != repo && repo != issue::RUST_REPO {
+                warn!("Ignoring --repo {}, the input names {}", repo, source_repo);
             }
-            #[cfg(not(feature = "fetch"))]
-            Err(anyhow!("You provided an issue number, but this version of Icemelter was compiled without the 'fetch' feature."))
+            retrieve_from_github(source_repo, issue_number, force)
+        }
+    }
+}
+
+const CRATER_REPORTS: &str = "https://crater-reports.s3.amazonaws.com/";
+
+#[cfg(feature = "fetch")]
+fn crater_project(source: &str) -> Result<(PathBuf, Vec<String>)> {
+    crater::setup(source)
+}
+
+#[cfg(not(feature = "fetch"))]
+fn crater_project(_source: &str) -> Result<(PathBuf, Vec<String>)> {
+    Err(anyhow::anyhow!(
+        "Icemelter was built without the `fetch` feature, can't download crates"
+    ))
+}
+
+/// The root of the Cargo project that `source` names (its directory or its
+/// `Cargo.toml`), and the file in it to reduce, if it's a project
+fn cargo_project(source: &str, file: Option<&Path>) -> Result<Option<(PathBuf, PathBuf)>> {
+    let path = Path::new(source);
+    let root = if path.is_dir() && path.join("Cargo.toml").is_file() {
+        path
+    } else if path.file_name().is_some_and(|f| f == "Cargo.toml") {
+        path.parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."))
+    } else {
+        return Ok(None);
+    };
+    let root = root
+        .canonicalize()
+        .with_context(|| format!("Failed to canonicalize {}", root.display()))?;
+    let file = match file {
+        Some(f) if f.is_absolute() => f
+            .strip_prefix(&root)
+            .map(Path::to_path_buf)
+            .with_context(|| format!("{} isn't in {}", f.display(), root.display()))?,
+        Some(f) => f.to_path_buf(),
+        None => ["src/main.rs", "src/lib.rs"]
+            .iter()
+            .map(PathBuf::from)
+            .find(|f| root.join(f).is_file())
+            .context("Couldn't find src/main.rs or src/lib.rs, pass --project-file")?,
+    };
+    if !root.join(&file).is_file() {
+        anyhow::bail!("{} isn't a file in {}", file.display(), root.display());
+    }
+    Ok(Some((root, file)))
+}
+
+/// The root of the Cargo workspace containing the project at `root` (which is
+/// `root` itself if it isn't a workspace member)
+fn workspace_root(root: &Path) -> PathBuf {
+    let out = Command::new("cargo")
+        .args(["locate-project", "--workspace", "--message-format", "plain"])
+        .current_dir(root)
+        .output();
+    let workspace = match out {
+        Ok(out) if out.status.success() => String::from_utf8_lossy(&out.stdout)
+            .trim()
+            .parse::<PathBuf>()
+            .ok()
+            .and_then(|manifest| manifest.parent()?.canonicalize().ok()),
+        _ => None,
+    };
+    match workspace {
+        Some(w) if root.starts_with(&w) => w,
+        _ => {
+            debug!("Couldn't find the workspace of {}", root.display());
+            root.to_path_buf()
         }
     }
 }
@@ -174,7 +1015,32 @@ fn parse(language: tree_sitter::Language, code: &str) -> Result<tree_sitter::Tre
     parser.parse(code, None).context("Failed to parse code")
 }
 
-fn check_initial_ice(chk: &CmdCheck, src: &[u8]) -> Result<(Vec<String>, String)> {
+/// Error codes and stderr of the initial ICE
+type InitialIce = (Vec<String>, String);
+
+/// Check `rs` and its [`snippet_variants`] for the ICE, returning the code
+/// and command line that produce it
+fn reproduce(
+    rs: &str,
+    check_cmd: &[String],
+    make_check: impl Fn(Vec<String>) -> Result<IceCheck>,
+) -> Result<Option<(String, Vec<String>, InitialIce)>> {
+    let chk = make_check(check_cmd.to_vec())?;
+    if let Some(initial) = check_initial_ice(&chk, rs.as_bytes())? {
+        return Ok(Some((rs.to_string(), check_cmd.to_vec(), initial)));
+    }
+    for (variant, variant_check) in snippet_variants(rs, check_cmd) {
+        debug!("Trying variant with command line {:?}", variant_check);
+        let variant_chk = make_check(variant_check.clone())?;
+        if let Some(initial) = check_initial_ice(&variant_chk, variant.as_bytes())? {
+            info!("The input lacks `fn main`, using a variant that produces the ICE");
+            return Ok(Some((variant, variant_check, initial)));
+        }
+    }
+    Ok(None)
+}
+
+fn check_initial_ice(chk: &IceCheck, src: &[u8]) -> Result<Option<InitialIce>> {
     debug!("Doing initial check for ICE");
     let state = chk
         .start(src)
@@ -183,22 +1049,115 @@ fn check_initial_ice(chk: &CmdCheck, src: &[u8]) -> Result<(Vec<String>, String)
         .wait_with_output(state)
         .context("Failed to check that initial input caused an ICE")?;
     if !interesting {
-        error!("The file doesn't seem to produce an ICE.");
-        std::process::exit(1);
+        return Ok(None);
     }
-    let error_code_regex =
-        Regex::new(r"(?m)^error\[E(?P<code>\d\d\d\d)\]: ").context("Internal error: Bad regex?")?;
     let stderr = String::from_utf8_lossy(&stderr_bytes);
-    let mut error_codes = Vec::new();
-    for capture in error_code_regex.captures_iter(&stderr) {
-        error_codes.push(String::from(
-            capture
-                .name("code")
-                .context("Internal error: bad capture group name")?
-                .as_str(),
-        ));
+    Ok(Some((check::error_codes(&stderr), String::from(stderr))))
+}
+
+/// Variants of a snippet to try if it doesn't produce an ICE as-is
+///
+/// Snippets from issues are often item fragments or function bodies, neither
+/// of which compiles standalone without a `main` function.
+fn snippet_variants(rs: &str, check: &[String]) -> Vec<(String, Vec<String>)> {
+    let main_regex = Regex::new(r"\bfn\s+main\s*\(").unwrap();
+    if main_regex.is_match(rs) {
+        return Vec::new();
+    }
+    let mut lib_check = check.to_vec();
+    lib_check.extend([String::from("--crate-type"), String::from("lib")]);
+    vec![
+        (String::from(rs), lib_check),
+        (format!("fn main() {{\n{}\n}}\n", rs), check.to_vec()),
+    ]
+}
+
+/// Command line that compiles the file as a library
+fn lib_command(check: &[String]) -> Vec<String> {
+    let mut cmd = Vec::with_capacity(check.len() + 2);
+    let mut found = false;
+    let mut args = check.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--crate-type" {
+            args.next();
+        } else if !arg.starts_with("--crate-type=") {
+            cmd.push(arg.clone());
+            continue;
+        }
+        if !found {
+            found = true;
+            cmd.extend([String::from("--crate-type"), String::from("lib")]);
+        }
+    }
+    if !found {
+        cmd.extend([String::from("--crate-type"), String::from("lib")]);
+    }
+    cmd
+}
+
+/// Drop a trivial `fn main() {}`, compiling the file as a library instead
+///
+/// Returns `None` if there's no such `main`.
+fn without_trivial_main(file: &[u8], check: &[String]) -> Option<(Vec<u8>, Vec<String>)> {
+    let main_regex = Regex::new(r"\bfn\s+main\s*\(\s*\)\s*\{\s*\}\s*").unwrap();
+    let text = String::from_utf8_lossy(file);
+    if !main_regex.is_match(&text) {
+        return None;
+    }
+    let without_main = main_regex.replace(&text, "").into_owned();
+    Some((without_main.into_bytes(), lib_command(check)))
+}
+
+/// Panic handler for `#![no_std]` binaries
+const NO_STD_PANIC_HANDLER: &str =
+    "\n#[panic_handler]\nfn panic(_: &core::panic::PanicInfo<'_>) -> ! {\n    loop {}\n}\n";
+
+/// Variants of a file with `#![no_std]`, as-is and with `std::` paths
+/// replaced by `core::`, and the command line to compile them with
+///
+/// Binaries also get `#![no_main]`, a panic handler, and `-Cpanic=abort`.
+fn no_std_variants(file: &[u8], check: &[String]) -> Vec<(Vec<u8>, Vec<String>)> {
+    let bin = flag_value(check, "--crate-type").unwrap_or("bin") == "bin";
+    let mut cmd = check.to_vec();
+    if bin {
+        // Before the file, for wrappers that expect it last
+        let at = cmd
+            .iter()
+            .position(|a| check::is_marker(a))
+            .unwrap_or(cmd.len());
+        cmd.insert(at, String::from("-Cpanic=abort"));
+    }
+    let text = String::from_utf8_lossy(file);
+    let std_regex = Regex::new(r"\bstd::").unwrap();
+    let with_core = std_regex.replace_all(&text, "core::");
+    let mut variants = vec![text.clone()];
+    if with_core != text {
+        variants.push(with_core);
+    }
+    variants
+        .into_iter()
+        .map(|t| {
+            if bin {
+                format!("#![no_std]\n#![no_main]\n{t}{NO_STD_PANIC_HANDLER}")
+            } else {
+                format!("#![no_std]\n{t}")
+            }
+            .into_bytes()
+        })
+        .map(|v| (v, cmd.clone()))
+        .collect()
+}
+
+/// Lints that warned, from notes like ``#[warn(dead_code)]` on by default``
+fn warned_lints(stderr: &str) -> Vec<String> {
+    let lint_regex = Regex::new(r"(?m)^\s*= note: `#\[warn\(([\w:]+)\)\]`").unwrap();
+    let mut lints = Vec::new();
+    for c in lint_regex.captures_iter(stderr) {
+        if !lints.iter().any(|l| l == &c[1]) {
+            lints.push(c[1].to_string());
+        }
     }
-    Ok((error_codes, String::from(stderr)))
+    lints
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -206,9 +1165,11 @@ fn check(
     debug: bool,
     timeout: Duration,
     check: Vec<String>,
+    env: Vec<(String, String)>,
     interesting_stderr: Option<String>,
     uninteresting_stderr: Option<String>,
-) -> Result<CmdCheck> {
+    aux: &[Aux],
+) -> Result<IceCheck> {
     if check.is_empty() {
         error!("Internal error: empty interestingness check!");
         std::process::exit(1);
@@ -216,7 +1177,10 @@ fn check(
     let mut argv = check;
     let cmd = argv[0].clone();
     argv.remove(0);
-    argv.push(String::from("@@.rs"));
+    // Cargo finds the file itself
+    if !argv.iter().any(|a| check::is_marker(a)) && temp::project().is_none() {
+        argv.push(String::from("@@.rs"));
+    }
     let stderr_regex = match &interesting_stderr {
         Some(r) => Some(Regex::new(r).context("Invalid interesting stderr regex")?),
         None => None,
@@ -225,23 +1189,21 @@ fn check(
         Some(r) => Some(Regex::new(r).context("Invalid uninteresting stderr regex")?),
         None => None,
     };
-    Ok(CmdCheck::new(
+    Ok(IceCheck::new(
         cmd,
-        argv.iter().map(|s| s.to_string()).collect(),
-        Vec::new(), // interesting exit codes
-        None,
-        None, // interesting stdout regex
+        argv,
+        env,
         stderr_regex,
-        None, // uninteresting stdout regex
         un_stderr_regex,
         debug,
-        debug,
         Some(timeout),
-    ))
+    )
+    .with_aux(aux.to_vec()))
 }
 
-// Regex to match errors other than those in the set
-fn error_regex(codes: HashSet<String>) -> String {
+// Regex to match errors other than those in the set (and, in a Cargo project,
+// other than Cargo's own)
+fn error_regex(codes: HashSet<String>, cargo: bool) -> String {
     let mut rx = String::from(r"^error\[E(0000");
     // Last is E0789, this should be safe for a bit...
     // https://doc.rust-lang.org/error_codes/error-index.html
@@ -254,10 +1216,63 @@ fn error_regex(codes: HashSet<String>) -> String {
     rx += r")\]: ";
     // error: internal...
     // error: the compiler...
-    format!(r"(^error: [^it]|{})", rx)
+    // error: could not compile... (from Cargo)
+    if cargo {
+        format!(r"(^error: ([^itc]|c[^o]|co[^u])|{})", rx)
+    } else {
+        format!(r"(^error: [^it]|{})", rx)
+    }
+}
+
+/// The first error in `stderr` if it's about the command line or toolchain
+/// rather than the code (which has a location), e.g., `-Z` flags on stable
+fn rejected_command(stderr: &str) -> Option<&str> {
+    if stderr.contains(" --> ") {
+        return None;
+    }
+    stderr.lines().find(|l| l.starts_with("error"))
+}
+
+/// How long the slowest of `jobs` concurrent checks of `code` takes, as
+/// candidates are checked concurrently too
+fn check_time(chk: &IceCheck, code: &[u8], jobs: usize) -> Result<Duration> {
+    let started = Instant::now();
+    let states = (0..std::cmp::max(1, jobs))
+        .map(|_| chk.start(code))
+        .collect::<Result<Vec<_>, _>>()?;
+    for state in states {
+        chk.wait(state)?;
+    }
+    Ok(started.elapsed())
+}
+
+/// Inputs at least this large get a coarse reduction first, see [`reduce`]
+const COARSE_MIN_SIZE: usize = 4096;
+
+/// The coarse reduction only tries subtrees at least this large (in bytes),
+/// skipping identifiers, literals, and other small nodes
+const COARSE_MIN_REDUCTION: usize = 32;
+
+/// Run a reduction pass on `text`, recording its effect in `stats`
+fn run_pass(
+    stats: &mut Vec<history::PassStats>,
+    name: &str,
+    chk: &IceCheck,
+    text: Vec<u8>,
+    pass: impl FnOnce(Vec<u8>) -> Result<Vec<u8>>,
+) -> Result<Vec<u8>> {
+    trace::pass(Some(name));
+    let (size, checks) = (text.len(), chk.checks());
+    let reduced = pass(text)?;
+    stats.push(history::PassStats {
+        name: String::from(name),
+        removed: size.saturating_sub(reduced.len()),
+        checks: chk.checks() - checks,
+    });
+    Ok(reduced)
 }
 
-fn reduce(rs: &str, jobs: usize, chk: CmdCheck) -> Result<Vec<u8>> {
+fn reduce_with(rs: &str, jobs: usize, chk: IceCheck, min_reduction: usize) -> Result<Vec<u8>> {
     let language = tree_sitter_rust::language();
     let node_types = NodeTypes::new(tree_sitter_rust::NODE_TYPES).unwrap();
     let tree = parse(language, rs).unwrap();
@@ -265,7 +1280,7 @@ fn reduce(rs: &str, jobs: usize, chk: CmdCheck) -> Result<Vec<u8>> {
         check: chk,
         delete_non_optional: true,
         jobs,
-        min_reduction: 1,
+        min_reduction,
         replacements: HashMap::new(),
     };
     let (reduced, _) = treereduce::treereduce_multi_pass(
@@ -279,6 +1294,17 @@ fn reduce(rs: &str, jobs: usize, chk: CmdCheck) -> Result<Vec<u8>> {
     Ok(reduced.text)
 }
 
+/// Reduce big inputs in two stages: first only try deleting large subtrees
+/// (e.g., whole items), which quickly shrinks the input, then every node
+fn reduce(rs: &str, jobs: usize, chk: IceCheck) -> Result<Vec<u8>> {
+    if rs.len() < COARSE_MIN_SIZE {
+        return reduce_with(rs, jobs, chk, 1);
+    }
+    let coarse = reduce_with(rs, jobs, chk.clone(), COARSE_MIN_REDUCTION)?;
+    debug!("Coarse reduction: {} to {} bytes", rs.len(), coarse.len());
+    reduce_with(&String::from_utf8_lossy(&coarse), jobs, chk, 1)
+}
+
 enum FormatResult {
     CouldntFormat,
     NoChange,
@@ -298,12 +1324,9 @@ fn format_result(result: &FormatResult) -> &'static str {
 
 // NB: errors from this function are ignored as non-fatal
 // TODO: Strip leading/trailing whitespace
-fn fmt(check: &CmdCheck, file: &[u8]) -> Result<FormatResult> {
+fn fmt(check: &IceCheck, file: &[u8]) -> Result<FormatResult> {
     debug!("Formatting reduced file with rustfmt");
-    let tmp = tempfile::Builder::new()
-        .prefix("icemelter")
-        .suffix(".rs")
-        .tempfile()?;
+    let tmp = temp::file("icemelter", ".rs")?;
     let path = tmp.path();
     fs::write(path, file)?;
     Command::new("rustfmt")
@@ -322,54 +1345,411 @@ fn fmt(check: &CmdCheck, file: &[u8]) -> Result<FormatResult> {
     }
 }
 
-fn bisect(args: Vec<String>, file: &[u8], stderr_regex: &str) -> Result<process::Output> {
-    let rs_tmp = tempfile::Builder::new()
-        .prefix("icemelter-")
-        .suffix(".rs")
-        .tempfile()?;
+/// Run rustc in a container, without network access, with the temporary
+/// directory (where candidates go) mounted at the same path
+///
+/// Variables in `env` are forwarded from the `docker` process.
+fn docker_command(image: &str, check: &[String], env: &[(String, String)]) -> Vec<String> {
+    let tmp = std::env::temp_dir().display().to_string();
+    let mut cmd = [
+        "docker",
+        "run",
+        "--rm",
+        "--network",
+        "none",
+        "--volume",
+        &format!("{tmp}:{tmp}"),
+    ]
+    .map(String::from)
+    .to_vec();
+    let mut keys = Vec::new();
+    for k in env
+        .iter()
+        .map(|(k, _)| k.as_str())
+        .chain(["RUSTC_BOOTSTRAP"])
+    {
+        if !keys.contains(&k) {
+            keys.push(k);
+            cmd.extend([String::from("--env"), k.to_string()]);
+        }
+    }
+    cmd.push(image.to_string());
+    cmd.extend(check.iter().cloned());
+    cmd
+}
+
+/// A command line without a run of flags, e.g., those from the issue
+fn without_flags(check: &[String], flags: &[String]) -> Vec<String> {
+    match check.windows(flags.len()).position(|w| w == flags) {
+        Some(i) => [&check[..i], &check[i + flags.len()..]].concat(),
+        None => check.to_vec(),
+    }
+}
+
+/// The command a [`docker_command`] runs in the container
+fn in_container(check: &[String]) -> &[String] {
+    // After `docker run` and its options
+    let mut i = 2;
+    while let Some(arg) = check.get(i) {
+        match arg.as_str() {
+            "--network" | "--volume" | "--env" | "--name" => i += 2,
+            a if a.starts_with('-') => i += 1,
+            _ => break,
+        }
+    }
+    // After the image
+    check.get(i + 1..).unwrap_or_default()
+}
+
+/// rustc with the same toolchain, target, and edition as a check, for
+/// building stub crates
+fn stub_command(check: &[String]) -> Vec<String> {
+    let mut cmd = vec![check[0].clone()];
+    if let Some(tc) = toolchain(check) {
+        cmd.push(format!("+{tc}"));
+    }
+    for flag in ["--target", "--edition"] {
+        if let Some(v) = flag_value(check, flag) {
+            cmd.extend([flag.to_string(), v.to_string()]);
+        }
+    }
+    cmd
+}
+
+/// The toolchain override (e.g., `+nightly`) in a rustc command line, if any
+fn toolchain(check: &[String]) -> Option<&str> {
+    check.get(1).and_then(|a| a.strip_prefix('+'))
+}
+
+/// Whether the check runs rustdoc instead of rustc
+fn is_rustdoc(check: &[String]) -> bool {
+    check
+        .iter()
+        .any(|a| Path::new(a).file_name().is_some_and(|f| f == "rustdoc"))
+}
+
+/// Arguments to rustc, without the command and toolchain override
+fn rustc_args(check: &[String]) -> Vec<String> {
+    let skip = if toolchain(check).is_some() { 2 } else { 1 };
+    check.iter().skip(skip).cloned().collect()
+}
+
+/// The value of a flag (e.g., `--target`) in a rustc command line, if any
+fn flag_value<'a>(check: &'a [String], flag: &str) -> Option<&'a str> {
+    let prefix = format!("{flag}=");
+    let mut args = check.iter();
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next().map(String::as_str);
+        }
+        if let Some(v) = arg.strip_prefix(&prefix) {
+            return Some(v);
+        }
+    }
+    None
+}
+
+/// Replace the value of a flag in a rustc command line
+fn with_flag(check: &[String], flag: &str, value: &str) -> Vec<String> {
+    let prefix = format!("{flag}=");
+    let mut cmd = Vec::with_capacity(check.len() + 2);
+    let mut args = check.iter();
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            args.next();
+        } else if !arg.starts_with(&prefix) {
+            cmd.push(arg.clone());
+        }
+    }
+    cmd.extend([flag.to_string(), value.to_string()]);
+    cmd
+}
+
+fn target(check: &[String]) -> Option<&str> {
+    flag_value(check, "--target")
+}
+
+fn with_target(check: &[String], triple: &str) -> Vec<String> {
+    with_flag(check, "--target", triple)
+}
+
+const EDITIONS: &[&str] = &["2015", "2018", "2021", "2024"];
+
+/// The edition in a rustc command line, rustc defaults to 2015
+fn edition(check: &[String]) -> &str {
+    flag_value(check, "--edition").unwrap_or(EDITIONS[0])
+}
+
+// NB: errors from this function are ignored as non-fatal
+fn install_target(toolchain: Option<&str>, triple: &str) -> Result<()> {
+    debug!("Installing target {}", triple);
+    let mut cmd = Command::new("rustup");
+    cmd.args(["target", "add", triple]);
+    if let Some(t) = toolchain {
+        cmd.args(["--toolchain", t]);
+    }
+    let status = cmd.stdout(Stdio::null()).stderr(Stdio::null()).status()?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("rustup target add failed"));
+    }
+    Ok(())
+}
+
+// NB: errors from this function are ignored as non-fatal
+fn install_component(toolchain: Option<&str>, component: &str) -> Result<()> {
+    debug!("Installing component {}", component);
+    let mut cmd = Command::new("rustup");
+    cmd.args(["component", "add", component]);
+    if let Some(t) = toolchain {
+        cmd.args(["--toolchain", t]);
+    }
+    let status = cmd.stdout(Stdio::null()).stderr(Stdio::null()).status()?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("rustup component add failed"));
+    }
+    Ok(())
+}
+
+/// Replace the toolchain override in a rustc command line
+fn with_toolchain(check: &[String], toolchain: &str) -> Vec<String> {
+    let mut cmd = vec![check[0].clone(), format!("+{toolchain}")];
+    cmd.extend(rustc_args(check));
+    cmd
+}
+
+/// Install a toolchain (e.g., `nightly-2024-01-02`) with rustup, unless it's
+/// already installed
+fn install_toolchain(toolchain: &str) -> Result<()> {
+    let installed = Command::new("rustup")
+        .args(["run", toolchain, "rustc", "--version"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false);
+    if !installed {
+        info!("Installing {}...", toolchain);
+        let status = Command::new("rustup")
+            .args(["toolchain", "install", toolchain])
+            .args(["--profile", "minimal", "--no-self-update"])
+            .stdout(Stdio::null())
+            .status()
+            .context("Failed to run rustup")?;
+        if !status.success() {
+            anyhow::bail!("rustup toolchain install failed");
+        }
+    }
+    Ok(())
+}
+
+/// Install the alt build of a rust-lang/rust commit, returning the name of the
+/// toolchain
+fn install_alt(commit: &str) -> Result<String> {
+    let name = format!("{commit}-alt");
+    let installed = Command::new("rustup")
+        .args(["run", &name, "rustc", "--version"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false);
+    if !installed {
+        info!("Installing alt build of {}...", commit);
+        let status = Command::new("rustup-toolchain-install-master")
+            .args(["--alt", commit])
+            .status()
+            .context("Failed to run rustup-toolchain-install-master")?;
+        if !status.success() {
+            anyhow::bail!("rustup-toolchain-install-master failed");
+        }
+    }
+    Ok(name)
+}
+
+/// The latest release nightly, for checking whether an ICE found with a
+/// debug-assertions compiler also reproduces without them
+fn release_nightly_command(check: &[String]) -> Vec<String> {
+    let mut cmd = vec![String::from("rustc"), String::from("+nightly")];
+    cmd.extend(bisect_local::without_sysroot(&rustc_args(check)));
+    cmd
+}
+
+/// Command line and environment for a locally built rustc
+///
+/// The sysroot of `build/<target>/stage1/bin/rustc` is `build/<target>/stage1`,
+/// and `librustc_driver` lives in its `lib` directory.
+fn local_rustc_command(rustc: &Path, check: &[String]) -> Result<(Vec<String>, (String, String))> {
+    let rustc = rustc
+        .canonicalize()
+        .with_context(|| format!("Couldn't find local rustc at {}", rustc.display()))?;
+    let sysroot = rustc
+        .parent()
+        .and_then(Path::parent)
+        .context("Local rustc should be in the bin directory of a sysroot")?;
+    let mut cmd = vec![
+        rustc.display().to_string(),
+        String::from("--sysroot"),
+        sysroot.display().to_string(),
+    ];
+    cmd.extend(rustc_args(check));
+    let lib_path_var = if cfg!(target_os = "macos") {
+        "DYLD_LIBRARY_PATH"
+    } else {
+        "LD_LIBRARY_PATH"
+    };
+    let mut lib_path = sysroot.join("lib").display().to_string();
+    if let Ok(old) = std::env::var(lib_path_var) {
+        lib_path = format!("{lib_path}:{old}");
+    }
+    Ok((cmd, (String::from(lib_path_var), lib_path)))
+}
+
+/// Command line that uses the latest nightly instead of the given toolchain
+fn latest_nightly_command(check: &[String]) -> Vec<String> {
+    let mut cmd = vec![check[0].clone(), String::from("+nightly")];
+    cmd.extend(rustc_args(check));
+    cmd
+}
+
+// NB: errors from this function are ignored as non-fatal
+fn update_nightly() -> Result<()> {
+    debug!("Updating nightly toolchain");
+    let status = Command::new("rustup")
+        .args(["update", "nightly", "--no-self-update"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("rustup update failed"));
+    }
+    Ok(())
+}
+
+/// A script for `cargo-bisect-rustc` that fails if rustc (with `args`)
+/// produces the ICE on `rs`, which is a shell word
+fn bisect_script(args: &[String], rs: &str, stderr_regex: &str) -> String {
+    format!(
+        r#"#!/usr/bin/env bash
+if rustup run "${{RUSTUP_TOOLCHAIN}}" rustc {} 2>&1 | egrep '{}'; then
+  exit 1
+fi
+exit 0
+"#,
+        check::shell_words(args, rs),
+        stderr_regex
+    )
+}
+
+/// Arguments to `cargo-bisect-rustc`, see [`bisect`]
+fn bisect_args(args: &[String], fix: bool, start: Option<&str>, script: &Path) -> Vec<String> {
+    let mut bisect_args = Vec::new();
+    if fix {
+        bisect_args.push(String::from("--regress=success"));
+    }
+    if let Some(start) = start {
+        bisect_args.extend([String::from("--start"), start.to_string()]);
+    }
+    // Install the standard library for the target with each toolchain
+    if let Some(t) = target(args) {
+        bisect_args.extend([String::from("--target"), t.to_string()]);
+    }
+    bisect_args.extend([
+        String::from("--script"),
+        script.display().to_string(),
+        String::from("--preserve"),
+    ]);
+    bisect_args
+}
+
+/// Write the reduced file and a `cargo-bisect-rustc` script to `dir`, and
+/// print the command that would run the bisection
+fn print_bisect(
+    check: &[String],
+    env: &[(String, String)],
+    file: &[u8],
+    stderr_regex: &str,
+    dir: &Path,
+) -> Result<()> {
+    let rs_path = dir.join("bisect.rs");
+    atomic::write(&rs_path, file)
+        .with_context(|| format!("Failed to write file to {}", rs_path.display()))?;
+    let script_path = dir.join("bisect.sh");
+    let script = bisect_script(
+        &rustc_args(check),
+        r#""$(dirname "$0")/bisect.rs""#,
+        stderr_regex,
+    );
+    atomic::write(&script_path, script)
+        .with_context(|| format!("Failed to write file to {}", script_path.display()))?;
+    fs::set_permissions(&script_path, fs::Permissions::from_mode(0o755))?;
+    let script_path = script_path.canonicalize().unwrap_or(script_path);
+    let start = toolchain(check).and_then(|t| t.strip_prefix("nightly-"));
+    let command = env_assignments(env)
+        .into_iter()
+        .chain([String::from("cargo-bisect-rustc")])
+        .chain(bisect_args(check, false, start, &script_path))
+        .collect::<Vec<_>>()
+        .join(" ");
+    info!(
+        "Wrote {} and {}, bisect with:",
+        rs_path.display(),
+        script_path.display()
+    );
+    println!("{command}");
+    Ok(())
+}
+
+/// Run `cargo-bisect-rustc`. If `fix` is set, look for the nightly that fixed
+/// the ICE (starting from `start`) rather than the one that introduced it.
+fn bisect(
+    args: Vec<String>,
+    env: &[(String, String)],
+    file: &[u8],
+    stderr_regex: &str,
+    fix: bool,
+    start: Option<&str>,
+) -> Result<process::Output> {
+    let rs_tmp = temp::file("icemelter-", ".rs")?;
     let rs_path = rs_tmp.path();
     fs::write(rs_path, file)?;
     debug!("Wrote source to {}", rs_path.display());
     let script_path = {
-        let script_tmp = tempfile::Builder::new()
-            .prefix("bisect-")
-            .suffix(".sh")
-            .tempfile()?;
+        let script_tmp = temp::file("bisect-", ".sh")?;
         let script_path = script_tmp.path();
         let mut perms = fs::metadata(script_path)?.permissions();
         perms.set_mode(0o700);
         fs::set_permissions(script_path, perms)?;
         fs::write(
             script_path,
-            format!(
-                r#"#!/usr/bin/env bash
-if rustup run "${{RUSTUP_TOOLCHAIN}}" rustc {} {} 2>&1 | egrep '{}'; then
-  exit 1
-fi
-exit 0
-"#,
-                args.iter()
-                    .map(|s| format!("'{s}'"))
-                    .collect::<Vec<_>>()
-                    .join(" "),
-                rs_path.display(),
-                stderr_regex
-            ),
+            bisect_script(&args, &rs_path.display().to_string(), stderr_regex),
         )?;
         script_tmp.keep()?.1
     };
     debug!("Wrote script to {}", script_path.display());
-    assert!(!Command::new(&script_path)
+    // The script succeeds if the ICE doesn't reproduce, which it should on
+    // the latest nightly when looking for the fix, and vice versa
+    let succeeds = Command::new(&script_path)
         .stdout(Stdio::null())
         .stderr(Stdio::null())
+        .envs(env.iter().map(|(k, v)| (k, v)))
         .env("RUSTUP_TOOLCHAIN", "nightly")
         .status()
-        .unwrap()
-        .success());
+        .with_context(|| format!("Failed to run the bisect script {}", script_path.display()))?
+        .success();
+    if succeeds != fix {
+        anyhow::bail!(
+            "The bisect script {} doesn't behave as expected: the ICE {} on the latest nightly",
+            script_path.display(),
+            if fix {
+                "reproduces"
+            } else {
+                "doesn't reproduce"
+            }
+        );
+    }
     let out = Command::new("cargo-bisect-rustc")
-        .arg("--script")
-        .arg(script_path)
-        .arg("--preserve")
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .args(bisect_args(&args, fix, start, &script_path))
         // TODO: blank if -q was given
         // .stdout(Stdio::null())
         // .stderr(Stdio::null())
@@ -378,11 +1758,117 @@ exit 0
     Ok(out)
 }
 
+fn run_bisect(
+    check: &[String],
+    env: &[(String, String)],
+    file: &[u8],
+    stderr_regex: &str,
+    fix: bool,
+) -> Result<String> {
+    let start = toolchain(check).and_then(|t| t.strip_prefix("nightly-"));
+    let out = bisect(rustc_args(check), env, file, stderr_regex, fix, start)?;
+    atomic::write("cargo-bisect-rustc.stdout.txt", &out.stdout)?;
+    atomic::write("cargo-bisect-rustc.stderr.txt", &out.stderr)?;
+    info!("Wrote to cargo-bisect-rustc.std{{out,err}}.txt");
+    if !out.status.success() {
+        warn!("cargo-bisect-rustc failed");
+    }
+    let mut bisect_report = Vec::with_capacity(12);
+    let mut eq_headings = 0;
+    let mut ast_headings = 0;
+    let stderr_str = String::from_utf8_lossy(out.stderr.as_slice());
+    for line in stderr_str.lines() {
+        if line.starts_with(
+            "********************************************************************************",
+        ) {
+            ast_headings += 1;
+        } else if ast_headings == 1 {
+            bisect_report.push(line);
+        }
+        if line.starts_with(
+            "==================================================================================",
+        ) {
+            eq_headings += 1;
+        } else if eq_headings >= 2 {
+            bisect_report.push(line);
+        }
+    }
+    Ok(bisect_report.join("\n"))
+}
+
+/// The final file with paths in string literals sanitized, unless that
+/// affects the ICE
+fn sanitized_code(chk: &IceCheck, file: &[u8]) -> Result<Vec<u8>> {
+    let code = String::from_utf8_lossy(file);
+    let sanitized = sanitize::code(&code);
+    if sanitized == code {
+        return Ok(file.to_vec());
+    }
+    if chk.interesting(sanitized.as_bytes())? {
+        debug!("Sanitized paths in the code");
+        return Ok(sanitized.into_bytes());
+    }
+    warn!("Sanitizing paths in the code affected the ICE, review the code before sharing it");
+    Ok(file.to_vec())
+}
+
+/// Run the final file with `RUST_BACKTRACE=full`, returning rustc's stderr
+fn capture_backtrace(chk: &IceCheck, file: &[u8]) -> Result<Option<String>> {
+    debug!("Capturing backtrace");
+    let state = chk.start(file).context("Failed to capture backtrace")?;
+    let (interesting, _status, _stdout, stderr_bytes) = chk
+        .wait_with_output(state)
+        .context("Failed to capture backtrace")?;
+    if !interesting {
+        warn!("The final file didn't produce an ICE with RUST_BACKTRACE=full");
+        return Ok(None);
+    }
+    Ok(Some(String::from(String::from_utf8_lossy(&stderr_bytes))))
+}
+
+/// Prepend ui test `aux-build` (or `proc-macro`) annotations for the
+/// auxiliary crates
+fn with_aux_annotations(file: &[u8], aux: &[Aux]) -> Vec<u8> {
+    let mut annotated = Vec::new();
+    for a in aux {
+        let directive = if a.proc_macro {
+            "proc-macro: "
+        } else {
+            "aux-build:"
+        };
+        annotated.extend(format!("//@ {}{}.rs\n", directive, a.name).into_bytes());
+    }
+    annotated.extend(file);
+    annotated
+}
+
+/// `KEY=VALUE` shell assignments for the environment of a check, except for
+/// settings that don't matter for reproducing the ICE
+fn env_assignments(env: &[(String, String)]) -> Vec<String> {
+    let plain = Regex::new(r"^[\w./:,+@%=-]*$").unwrap();
+    env.iter()
+        .filter(|(k, _)| k != "RUST_BACKTRACE")
+        .map(|(k, v)| {
+            if plain.is_match(v) {
+                format!("{k}={v}")
+            } else {
+                format!("{k}='{}'", v.replace('\'', r"'\''"))
+            }
+        })
+        .collect()
+}
+
+/// The PR that introduced the ICE, from a bisection report
+fn bisect_pr(report: &str) -> Option<String> {
+    let pr_regex = Regex::new(r"rust-lang/rust#\d+").unwrap();
+    pr_regex.find(report).map(|m| m.as_str().to_string())
+}
+
 fn rustc_version(mut argv: Vec<String>) -> String {
     let cmd = argv[0].clone();
     argv.remove(0);
     Command::new(cmd)
-        .args(argv)
+        .args(argv.iter().filter(|a| !check::is_marker(a)))
         .arg("--version")
         .arg("--verbose")
         .output()
@@ -392,15 +1878,72 @@ fn rustc_version(mut argv: Vec<String>) -> String {
         .to_string()
 }
 
+/// Whether the toolchain in the command line is a nightly (or dev) build
+fn is_nightly(argv: &[String]) -> bool {
+    let version = rustc_version(argv.to_vec());
+    version.contains("-nightly") || version.contains("-dev")
+}
+
+#[allow(clippy::too_many_arguments)]
 fn markdown(
-    to: PathBuf,
     argv: Vec<String>,
+    env: &[(String, String)],
     file: Vec<u8>,
     did_reduce: bool,
     formatted: &FormatResult,
     bisect_report: Option<String>,
-) -> Result<()> {
-    let s = String::from_utf8(file).context("When writing Markdown")?;
+    backtrace: Option<String>,
+    labels: &[&str],
+    ice_phase: Option<phase::Phase>,
+    aux: &[Aux],
+    targets: &[(String, bool)],
+    min_edition: Option<&str>,
+    no_std: bool,
+    file_name: &str,
+    release_reproduces: Option<bool>,
+    stderr: Option<&str>,
+    original: Option<&str>,
+    environment: &environment::Environment,
+    partial: Option<&str>,
+    sanitize: bool,
+) -> Result<String> {
+    // `file` is already sanitized, see `sanitized_code`
+    let paths = |t: &str| {
+        if sanitize {
+            sanitize::paths(t)
+        } else {
+            t.to_string()
+        }
+    };
+    let output = |t: &str| {
+        if sanitize {
+            sanitize::sanitize(t)
+        } else {
+            t.to_string()
+        }
+    };
+    let code = |t: &str| {
+        if sanitize {
+            sanitize::code(t)
+        } else {
+            t.to_string()
+        }
+    };
+    let command = env_assignments(env)
+        .into_iter()
+        .chain(check::with_file(&argv, file_name))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let reproduce = format!("\n\nReproduce with:\n```sh\n{}\n```", paths(&command));
+    let s = String::from_utf8(with_aux_annotations(&file, aux)).context("When writing Markdown")?;
+    let mut aux_files = String::new();
+    for a in aux {
+        aux_files.push_str(&format!(
+            "\n\n`auxiliary/{}.rs`:\n```rust\n{}\n```",
+            a.name,
+            code(String::from_utf8_lossy(&a.code).trim_end())
+        ));
+    }
     let did_format = matches!(formatted, FormatResult::Changed(_));
     let edited = if did_reduce && did_format {
         "Reduced, formatted"
@@ -409,6 +1952,10 @@ fn markdown(
     } else {
         "Formatted"
     };
+    let edited = match partial {
+        Some(_) if did_reduce => edited.replacen("Reduced", "Partially reduced", 1),
+        _ => edited.to_string(),
+    };
     let report =
         format!(
         "Triaged with [Icemelter](https://github.com/langston-barrett/icemelter). Steps performed:
@@ -420,7 +1967,7 @@ fn markdown(
 
 {}
 {}
-
+{}{}
 <details><summary>Details</summary>
 <p>
 
@@ -429,6 +1976,11 @@ rustc version:
 {}
 ```
 
+Environment:
+```
+{}
+```
+{}{}{}{}{}{}
 Icemelter version: v{}
 
 Icemelter command line:
@@ -436,7 +1988,7 @@ Icemelter command line:
 ```sh
 {}
 ```
-
+{}
 @rustbot label +S-bug-has-mcve
 
 Do you have feedback about this report? Please [file an issue](https://github.com/langston-barrett/icemelter/issues)!
@@ -444,91 +1996,1549 @@ Do you have feedback about this report? Please [file an issue](https://github.co
 </p>
 </details>",
         format_result(formatted),
-        if did_reduce { "✅" } else { "❌" },
+        match (did_reduce, partial) {
+            (true, Some(_)) => "⚠️ (partially)",
+            (true, None) => "✅",
+            (false, _) => "❌",
+        },
         if bisect_report.is_some() { "✅" } else { "❌" },
         if did_reduce || did_format {
             format!(
                 "{}:
 ```rust
 {}
-```",
-                edited, s
+```{}{}{}",
+                edited,
+                s,
+                aux_files,
+                reproduce,
+                partial.map(|p| format!("\n\n{p}")).unwrap_or_default()
             )
         } else {
             String::new()
         },
-        bisect_report.unwrap_or_default(),
+        output(&bisect_report.unwrap_or_default()),
+        match backtrace {
+            Some(bt) => format!(
+                "
+<details><summary>Backtrace</summary>
+<p>
+
+```
+{}
+```
+
+</p>
+</details>
+",
+                output(&bt)
+            ),
+            None => String::new(),
+        },
+        match original {
+            Some(original) => format!(
+                "
+<details><summary>Original code</summary>
+<p>
+
+```rust
+{}
+```
+
+</p>
+</details>
+",
+                code(original.trim_end())
+            ),
+            None => String::new(),
+        },
         rustc_version(argv),
+        paths(&environment.to_string()),
+        match stderr {
+            Some(e) => format!(
+                "\nrustc output:\n```\n{}\n```\n",
+                output(&backtrace::for_report(e, file_name))
+            ),
+            None => String::new(),
+        },
+        match ice_phase {
+            Some(p) => format!("\nICE phase: {}\n", p),
+            None => String::new(),
+        },
+        match min_edition {
+            Some(ed) if ed == EDITIONS[0] => String::from("\nReproduces on every edition\n"),
+            Some(ed) => format!("\nRequires edition {} or later\n", ed),
+            None => String::new(),
+        },
+        if no_std {
+            String::from("\nReproduces without the standard library (`#![no_std]`)\n")
+        } else {
+            String::new()
+        },
+        match release_reproduces {
+            Some(true) => String::from("\nAlso reproduces on release nightlies\n"),
+            Some(false) => String::from(
+                "\nDoesn't reproduce on release nightlies, needs a compiler with debug assertions\n",
+            ),
+            None => String::new(),
+        },
+        if targets.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "\nReproduces on targets:\n{}\n",
+                targets
+                    .iter()
+                    .map(|(t, r)| format!("- `{}`: {}", t, if *r { "✅" } else { "❌" }))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            )
+        },
         env!("CARGO_PKG_VERSION"),
-        std::env::args().map(|s| format!("'{s}'")).collect::<Vec<_>>().join(" "),
+        paths(&std::env::args().map(|s| format!("'{s}'")).collect::<Vec<_>>().join(" ")),
+        if labels.is_empty() {
+            String::new()
+        } else {
+            format!("\nSuggested labels: {}\n", labels.join(", "))
+        },
     );
-    fs::write(&to, report)
+    Ok(report)
+}
+
+fn write_markdown(to: &Path, report: &str) -> Result<()> {
+    atomic::write(to, report)
         .with_context(|| format!("When writing Markdown report to {}", to.display()))?;
     info!("Wrote Markdown report to {}", to.display());
     Ok(())
 }
 
-const STEPS: usize = 5;
+fn fixed_markdown(
+    to: PathBuf,
+    argv: Vec<String>,
+    bisect_report: Option<String>,
+    sanitize: bool,
+) -> Result<()> {
+    let report = format!(
+        "Triaged with [Icemelter](https://github.com/langston-barrett/icemelter). Steps performed:
 
-pub fn main() -> Result<()> {
-    let args = Args::parse();
-    init_tracing(&args);
-    let timeout = Duration::from_millis(args.timeout);
+- Reproduced: ✅
+- Reproduces on latest nightly: ❌
+- Bisected fix: {}
 
-    info!("Step 1/{STEPS}: Retrieving...");
-    let rs = retrieve(&args.source)?;
+{}
 
-    info!("Step 2/{STEPS}: Configuring...");
-    let initial_check = check(
-        args.debug,
-        timeout,
-        args.check.clone(),
-        Some(args.interesting_stderr.clone()),
-        args.uninteresting_stderr.clone(),
-    )?;
-    let uninteresting_stderr = if args.allow_errors {
-        args.uninteresting_stderr
-    } else {
-        let (error_codes, initial_stderr) = check_initial_ice(&initial_check, rs.as_bytes())?;
-        for error_code in &error_codes {
-            debug!("Found error code {}", error_code);
-        }
-        let fresh_error_regex = error_regex(HashSet::from_iter(error_codes));
-        let uninteresting_regex = match args.uninteresting_stderr {
-            Some(u) => format!("(?m)({}|{})", u, fresh_error_regex),
-            None => format!("(?m){}", fresh_error_regex),
-        };
-        debug!("Initial stderr: {}", initial_stderr);
-        debug!("Error regex: {}", uninteresting_regex);
-        debug_assert!(!Regex::new(&uninteresting_regex)
-            .unwrap()
-            .is_match(&initial_stderr));
-        Some(uninteresting_regex)
-    };
+<details><summary>Details</summary>
+<p>
 
-    info!("Step 3/{STEPS}: Reducing...");
-    let chk = check(
-        args.debug,
-        timeout,
-        args.check.clone(),
-        Some(args.interesting_stderr.clone()),
-        uninteresting_stderr,
-    )?;
-    let reduced =
-        reduce(&rs, args.jobs, chk.clone()).context("Failed when reducing the program")?;
-    let did_reduce = reduced != rs.as_bytes();
-    if did_reduce {
-        debug!("Reduced!");
-        if args.allow_errors {
-            info!("Unable to reduce! Sorry.");
-            info!("If you think this test case is reducible, please file an issue!");
-        } else {
-            info!("Unable to reduce, try --allow-errors.");
-        }
-    }
+Latest nightly rustc version:
+```
+{}
+```
 
-    info!("Step 4/{STEPS}: Formatting...");
-    let (fmt_result, formatted) = match fmt(&chk, &reduced) {
+Icemelter version: v{}
+
+Icemelter command line:
+
+```sh
+{}
+```
+
+Do you have feedback about this report? Please [file an issue](https://github.com/langston-barrett/icemelter/issues)!
+
+</p>
+</details>",
+        if bisect_report.is_some() { "✅" } else { "❌" },
+        match (sanitize, bisect_report) {
+            (true, Some(r)) => sanitize::sanitize(&r),
+            (_, r) => r.unwrap_or_default(),
+        },
+        rustc_version(latest_nightly_command(&argv)),
+        env!("CARGO_PKG_VERSION"),
+        {
+            let args = std::env::args()
+                .map(|s| format!("'{s}'"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            if sanitize {
+                sanitize::paths(&args)
+            } else {
+                args
+            }
+        },
+    );
+    atomic::write(&to, report)
+        .with_context(|| format!("When writing Markdown report to {}", to.display()))?;
+    info!("Wrote Markdown report to {}", to.display());
+    Ok(())
+}
+
+const STEPS: usize = 5;
+
+#[cfg(feature = "fetch")]
+fn issue_is_open(repo: &str, issue_number: usize) -> Option<bool> {
+    let gh_config = github::Config::load().unwrap_or_else(|_| github::Config::anonymous());
+    github::get_issue(&gh_config, repo, issue_number)
+        .ok()
+        .map(|i| !i.is_closed())
+}
+
+#[cfg(not(feature = "fetch"))]
+fn issue_is_open(_repo: &str, _issue_number: usize) -> Option<bool> {
+    None
+}
+
+/// Short summary of a run, in Zulip's Markdown dialect
+fn zulip_summary(record: &history::Record) -> String {
+    let mut summary = match record.issue {
+        Some(n) => {
+            let repo = record.repo();
+            format!("Melted [{repo}#{n}](https://github.com/{repo}/issues/{n})")
+        }
+        None => format!("Melted `{}`", record.source),
+    };
+    summary.push_str(&format!(
+        "\n- Reduced from {} to {} bytes",
+        record.original_size, record.reduced_size
+    ));
+    if let Some(pr) = &record.bisect_pr {
+        summary.push_str(&format!("\n- Regressed in {pr}"));
+    }
+    if let Some(report) = &record.report {
+        summary.push_str(&format!("\n- Report: `{}`", report.display()));
+    }
+    summary
+}
+
+#[cfg(feature = "fetch")]
+fn post_to_zulip(stream: &str, topic: &str, content: &str) -> Result<()> {
+    let config = zulip::Config::from_env().with_context(|| {
+        format!(
+            "Missing {} or {} environment variable",
+            zulip::Config::EMAIL_VAR,
+            zulip::Config::API_KEY_VAR
+        )
+    })?;
+    zulip::post(&config, stream, topic, content).context("Failed to post to Zulip")?;
+    Ok(())
+}
+
+#[cfg(not(feature = "fetch"))]
+fn post_to_zulip(_stream: &str, _topic: &str, _content: &str) -> Result<()> {
+    Err(anyhow::anyhow!(
+        "Icemelter was built without the `fetch` feature, can't post to Zulip"
+    ))
+}
+
+#[cfg(feature = "fetch")]
+fn post_report(
+    repo: &str,
+    issue_number: usize,
+    report: &str,
+    yes: bool,
+    dry_run: bool,
+) -> Result<()> {
+    if dry_run {
+        println!("{report}");
+        post::dry_run(repo, issue_number)
+    } else {
+        post::post(repo, issue_number, report, yes)
+    }
+}
+
+#[cfg(not(feature = "fetch"))]
+fn post_report(
+    _repo: &str,
+    _issue_number: usize,
+    _report: &str,
+    _yes: bool,
+    _dry_run: bool,
+) -> Result<()> {
+    Err(anyhow::anyhow!(
+        "Icemelter was built without the `fetch` feature, can't post to GitHub"
+    ))
+}
+
+#[cfg(feature = "fetch")]
+fn open_crashes_pr(
+    issue_number: usize,
+    test: &[u8],
+    aux: &[Aux],
+    fork: Option<&str>,
+    yes: bool,
+) -> Result<()> {
+    crashes::open_pr(issue_number, test, aux, fork, yes)
+}
+
+#[cfg(not(feature = "fetch"))]
+fn open_crashes_pr(
+    _issue_number: usize,
+    _test: &[u8],
+    _aux: &[Aux],
+    _fork: Option<&str>,
+    _yes: bool,
+) -> Result<()> {
+    Err(anyhow::anyhow!(
+        "Icemelter was built without the `fetch` feature, can't open a PR"
+    ))
+}
+
+#[cfg(feature = "keyring")]
+fn auth(command: AuthCommand) -> Result<()> {
+    match command {
+        AuthCommand::Login => {
+            let token = if io::stdin().is_terminal() {
+                rpassword::prompt_password("GitHub token: ")?
+            } else {
+                let mut line = String::new();
+                io::stdin().lock().read_line(&mut line)?;
+                line
+            };
+            let config = github::Config::new(token.trim().to_string());
+            let user = github::get_user(&config).context("Invalid GitHub token")?;
+            config
+                .save_to_keyring()
+                .context("Failed to save token to the keyring")?;
+            info!("Logged in as {}", user.login);
+        }
+        AuthCommand::Logout => {
+            github::Config::delete_from_keyring()
+                .context("Failed to delete token from the keyring")?;
+            info!("Deleted token from the keyring");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "keyring"))]
+fn auth(_command: AuthCommand) -> Result<()> {
+    Err(anyhow::anyhow!(
+        "Icemelter was built without the `keyring` feature"
+    ))
+}
+
+#[cfg(feature = "fetch")]
+fn configure_http(args: &Args) -> Result<()> {
+    if args.proxy.is_some() || args.ca_cert.is_some() {
+        http::configure(args.proxy.as_deref(), args.ca_cert.as_deref())?;
+    }
+    if args.refresh {
+        cache::refresh();
+    }
+    if let Some(url) = &args.github_api_url {
+        github::set_api_url(url);
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "fetch"))]
+fn configure_http(args: &Args) -> Result<()> {
+    if args.proxy.is_some() || args.ca_cert.is_some() || args.github_api_url.is_some() {
+        warn!("Icemelter was built without the `fetch` feature, ignoring --proxy, --ca-cert, and --github-api-url");
+    }
+    Ok(())
+}
+
+#[cfg(feature = "fetch")]
+fn self_update(check: bool) -> Result<()> {
+    update::self_update(check)
+}
+
+#[cfg(not(feature = "fetch"))]
+fn self_update(_check: bool) -> Result<()> {
+    Err(anyhow::anyhow!(
+        "Icemelter was built without the `fetch` feature, can't update itself"
+    ))
+}
+
+fn stats(history: Option<PathBuf>) -> Result<()> {
+    let path = history
+        .or_else(history::default_path)
+        .context("Couldn't find history file, try --history")?;
+    if !path.exists() {
+        println!("No runs recorded yet.");
+        return Ok(());
+    }
+    history::print_stats(&history::read(&path)?, issue_is_open);
+    Ok(())
+}
+
+/// rustc's stderr for an ICE, from a capture or by running a .rs file
+fn ice_output(chk: &IceCheck, path: &Path) -> Result<String> {
+    let content = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    if path.extension().is_none_or(|e| e != "rs") {
+        return Ok(String::from_utf8_lossy(&content).into_owned());
+    }
+    let state = chk
+        .start(&content)
+        .with_context(|| format!("Failed to check {}", path.display()))?;
+    let (interesting, _status, _stdout, stderr) = chk
+        .wait_with_output(state)
+        .with_context(|| format!("Failed to check {}", path.display()))?;
+    if !interesting {
+        anyhow::bail!("{} doesn't produce an ICE", path.display());
+    }
+    Ok(String::from_utf8_lossy(&stderr).into_owned())
+}
+
+fn diff_ice(first: &Path, second: &Path, timeout: Duration, check_cmd: Vec<String>) -> Result<()> {
+    let chk = check(
+        false,
+        timeout,
+        check_cmd,
+        vec![(String::from("RUST_BACKTRACE"), String::from("1"))],
+        Some(String::from(DEFAULT_INTERESTING_STDERR)),
+        None,
+        &[],
+    )?;
+    let a = backtrace::Backtrace::parse(&ice_output(&chk, first)?);
+    let b = backtrace::Backtrace::parse(&ice_output(&chk, second)?);
+    for (path, bt) in [(first, &a), (second, &b)] {
+        println!(
+            "{}: {}",
+            path.display(),
+            bt.message.as_deref().unwrap_or("(no ICE message)")
+        );
+    }
+    println!();
+    println!("{}", a.similarity(&b));
+    Ok(())
+}
+
+fn intake(
+    dir: &Path,
+    output: &Path,
+    timeout: Duration,
+    check_cmd: Vec<String>,
+    dashboard: &serve::Dashboard,
+) -> Result<()> {
+    let chk = check(
+        false,
+        timeout,
+        check_cmd.clone(),
+        vec![(String::from("RUST_BACKTRACE"), String::from("1"))],
+        Some(String::from(DEFAULT_INTERESTING_STDERR)),
+        None,
+        &[],
+    )?;
+    let files = intake::inputs(dir)?;
+    info!("Checking {} inputs...", files.len());
+    let groups = intake::group(&chk, &files)?;
+    info!(
+        "Found {} distinct ICEs in {} inputs",
+        groups.len(),
+        groups.iter().map(|g| g.inputs.len()).sum::<usize>()
+    );
+    let exe = std::env::current_exe().context("Couldn't find the icemelter executable")?;
+    let total = groups.len();
+    for group in &groups {
+        if !output.join(&group.id).join("melted.rs").exists() {
+            dashboard.queue(&group.inputs[0].display().to_string());
+        }
+    }
+    let mut outcomes = Vec::with_capacity(total);
+    for (i, group) in groups.into_iter().enumerate() {
+        let group_dir = output.join(&group.id);
+        intake::write_group(&group_dir, &group)?;
+        // Only reduce each ICE once, even across runs
+        let melted = group_dir.join("melted.rs");
+        if melted.exists() {
+            info!(
+                "Already melted {} ({} inputs): {}",
+                group.id,
+                group.inputs.len(),
+                group_dir.display()
+            );
+            outcomes.push((group, intake::Outcome::AlreadyMelted));
+            continue;
+        }
+        let input = &group.inputs[0];
+        dashboard.start(&input.display().to_string());
+        info!(
+            "Melting {} ({} of {}, {} inputs): {}",
+            input.display(),
+            i + 1,
+            total,
+            group.inputs.len(),
+            group.message.as_deref().unwrap_or("unknown ICE")
+        );
+        let status = Command::new(&exe)
+            .arg("--markdown")
+            .arg("--timeout")
+            .arg(timeout.as_millis().to_string())
+            .arg("--output")
+            .arg(&melted)
+            .arg(input)
+            .arg("--")
+            .args(&check_cmd)
+            .status()
+            .context("Failed to run icemelter")?;
+        let outcome = if status.success() {
+            dashboard.finish(Some(melted.clone()), Some(melted.with_extension("md")));
+            intake::Outcome::Melted
+        } else {
+            warn!("Failed to melt {}", input.display());
+            dashboard.finish(None, None);
+            intake::Outcome::Failed
+        };
+        outcomes.push((group, outcome));
+    }
+    let summary = output.join("summary.md");
+    atomic::write(&summary, intake::summary(&outcomes))
+        .with_context(|| format!("Failed to write {}", summary.display()))?;
+    info!("Wrote reports to {}", output.display());
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn replay(
+    trace_path: &Path,
+    original: &Path,
+    proc_macro: &[PathBuf],
+    aux_paths: &[PathBuf],
+    verify: &[usize],
+    env: Vec<(String, String)>,
+    timeout: Duration,
+    output: &Path,
+) -> Result<()> {
+    let entries = trace::read(trace_path)?;
+    let main = read_file(&original.to_string_lossy())?;
+    let mut aux = proc_macro
+        .iter()
+        .map(|p| (p, true))
+        .chain(aux_paths.iter().map(|p| (p, false)))
+        .map(|(p, proc_macro)| {
+            Aux::read(p, proc_macro).with_context(|| format!("Failed to read file {}", p.display()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut texts = HashMap::from([(String::from(trace::MAIN), main.into_bytes())]);
+    texts.extend(aux.iter().map(|a| (a.name.clone(), a.code.clone())));
+    for step in verify {
+        match entries.iter().find(|e| e.step == *step) {
+            None => warn!("There's no step {} in the trace", step),
+            Some(e) if e.edit.is_none() || e.command.is_none() => {
+                warn!("Step {} wasn't interesting, so the trace lacks it", step)
+            }
+            Some(_) => (),
+        }
+    }
+    trace::replay(&entries, &mut texts, |entry, candidate, texts| {
+        let command = match &entry.command {
+            Some(c) if verify.contains(&entry.step) && !c.is_empty() => c,
+            _ => return Ok(()),
+        };
+        // The other files as of this step
+        for a in &mut aux {
+            if let Some(code) = texts.get(&a.name) {
+                a.code = code.clone();
+            }
+        }
+        let mut chk = IceCheck::new(
+            command[0].clone(),
+            command[1..].to_vec(),
+            env.clone(),
+            Some(Regex::new(DEFAULT_INTERESTING_STDERR)?),
+            None,
+            false,
+            Some(timeout),
+        )
+        .with_aux(aux.clone());
+        if let Some(i) = aux.iter().position(|a| a.name == entry.file) {
+            chk = chk.reducing_aux(i, texts[trace::MAIN].clone());
+        }
+        let (interesting, _, _, stderr) = chk.wait_with_output(chk.start(candidate)?)?;
+        let stderr = String::from_utf8_lossy(&stderr);
+        match issue::ice_message(&stderr).filter(|_| interesting) {
+            Some(msg) => info!("Step {} ({}): {}", entry.step, entry.pass, msg),
+            None => warn!("Step {} ({}): no ICE", entry.step, entry.pass),
+        }
+        Ok(())
+    })?;
+    info!("Replayed {} steps", entries.len());
+    atomic::write(output, &texts[trace::MAIN])
+        .with_context(|| format!("Failed to write file to {}", output.display()))?;
+    info!("Rebuilt file written to {}", output.display());
+    let aux_dir = output.with_file_name("auxiliary");
+    if !aux.is_empty() {
+        fs::create_dir_all(&aux_dir)
+            .with_context(|| format!("Failed to create directory {}", aux_dir.display()))?;
+        for a in &aux {
+            let path = aux_dir.join(format!("{}.rs", a.name));
+            atomic::write(&path, &texts[&a.name])
+                .with_context(|| format!("Failed to write file to {}", path.display()))?;
+        }
+        info!("Auxiliary crates written to {}", aux_dir.display());
+    }
+    Ok(())
+}
+
+fn known_path(known: Option<PathBuf>) -> Result<PathBuf> {
+    known
+        .or_else(known::default_path)
+        .context("Couldn't find known ICEs file, try --known")
+}
+
+#[cfg(feature = "fetch")]
+fn update_known(known: Option<PathBuf>) -> Result<()> {
+    let path = known_path(known)?;
+    let gh_config = github::Config::load_or_anonymous();
+    let mut issues = 0;
+    let mut signatures = Vec::new();
+    for page in 1.. {
+        let batch = github::get_open_ices(&gh_config, page)
+            .context("Failed to retrieve issues from Github")?;
+        if batch.is_empty() {
+            break;
+        }
+        for i in batch.into_iter().filter(|i| i.pull_request.is_none()) {
+            issues += 1;
+            if let Some(signature) = i.body.as_deref().and_then(issue::ice_message) {
+                signatures.push(known::Known {
+                    issue: i.number,
+                    title: i.title,
+                    signature,
+                });
+            }
+        }
+    }
+    known::write(&path, &signatures)?;
+    info!(
+        "Saved {} ICE messages from {} open issues to {}",
+        signatures.len(),
+        issues,
+        path.display()
+    );
+    Ok(())
+}
+
+#[cfg(not(feature = "fetch"))]
+fn update_known(_known: Option<PathBuf>) -> Result<()> {
+    Err(anyhow::anyhow!(
+        "Icemelter was built without the `fetch` feature, can't fetch known ICEs"
+    ))
+}
+
+/// Warn if the ICE matches a known issue (other than the one being melted),
+/// and ask whether to go on
+fn check_known(known: Option<PathBuf>, issue: Option<usize>, stderr: &str) -> Result<()> {
+    let path = known_path(known)?;
+    if !path.exists() {
+        debug!("No known ICEs at {}", path.display());
+        return Ok(());
+    }
+    let Some(message) = backtrace::Backtrace::parse(stderr).message else {
+        return Ok(());
+    };
+    let corpus = known::read(&path)?;
+    let matches = known::matching(&corpus, &message)
+        .into_iter()
+        .filter(|k| Some(k.issue) != issue)
+        .collect::<Vec<_>>();
+    if matches.is_empty() {
+        return Ok(());
+    }
+    for k in &matches {
+        warn!(
+            "The ICE matches known issue rust-lang/rust#{}: {}",
+            k.issue, k.title
+        );
+    }
+    if !io::stdin().is_terminal() {
+        warn!("It may be a duplicate, continuing anyway");
+        return Ok(());
+    }
+    print!("Reduce it anyway? [y/N] ");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    if !matches!(answer.trim(), "y" | "Y" | "yes") {
+        error!("Reduction is probably unnecessary. Pass --force to proceed anyway.");
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// The dashboard, if `--serve` was given
+fn dashboard(serve: &Option<String>) -> Result<serve::Dashboard> {
+    match serve {
+        Some(addr) => serve::Dashboard::serve(addr),
+        None => Ok(serve::Dashboard::default()),
+    }
+}
+
+pub fn main() -> Result<()> {
+    if let Some(dir) = std::env::var_os(wrap::DIR_VAR) {
+        process::exit(wrap::intercept(dir, std::env::args().skip(1)));
+    }
+    let args = Args::parse();
+    init_tracing(&args);
+    configure_http(&args)?;
+    match args.command {
+        Some(SubCommand::Stats { history }) => return stats(history),
+        Some(SubCommand::UpdateKnown { known }) => return update_known(known),
+        Some(SubCommand::Auth { command }) => return auth(command),
+        Some(SubCommand::SelfUpdate { check }) => return self_update(check),
+        Some(SubCommand::Intake {
+            dir,
+            output,
+            serve,
+            timeout,
+            check,
+        }) => return intake(&dir, &output, timeout, check, &dashboard(&serve)?),
+        Some(SubCommand::DiffIce {
+            first,
+            second,
+            timeout,
+            check,
+        }) => return diff_ice(&first, &second, timeout, check),
+        Some(SubCommand::WatchDir {
+            dir,
+            interval,
+            serve,
+            timeout,
+            check,
+        }) => {
+            let dashboard = dashboard(&serve)?;
+            return watch::watch(&dir, interval, timeout, &check, &dashboard);
+        }
+        Some(SubCommand::Wrap {
+            output,
+            timeout,
+            cmd,
+        }) => return wrap::wrap(&output, timeout, &cmd),
+        Some(SubCommand::Replay {
+            trace,
+            original,
+            aux,
+            proc_macro,
+            verify,
+            env,
+            timeout,
+            output,
+        }) => {
+            return replay(
+                &trace,
+                &original,
+                &proc_macro,
+                &aux,
+                &verify,
+                env,
+                timeout,
+                &output,
+            )
+        }
+        None => (),
+    }
+    let mut source_arg = match batch::input() {
+        Some(source) => source,
+        None => {
+            let sources = match &args.query {
+                Some(query) => search(query, &args.repo)?,
+                None => batch::expand(&args.sources)?,
+            };
+            if sources.len() > 1 || args.query.is_some() {
+                if args.output.is_some() {
+                    error!("--output can't be used with several inputs");
+                    std::process::exit(1);
+                }
+                return batch::run(&sources, &dashboard(&args.serve)?);
+            }
+            sources.first().cloned().context("Missing ICE")?
+        }
+    };
+    let batched = std::env::var_os(batch::INPUT_VAR).is_some();
+    let timeout = args.timeout;
+    if args.keep_temps {
+        let dir = std::env::temp_dir().join(format!("icemelter-{}", process::id()));
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+        info!("Keeping temporary files in {}", dir.display());
+        temp::keep_in(dir);
+    }
+    if let Some(name) = &args.candidate_name {
+        temp::name_candidates(name.clone());
+    }
+    let dashboard = dashboard(&args.serve)?;
+
+    info!("Step 1/{STEPS}: Retrieving...");
+    dashboard.start(&source_arg);
+    dashboard.step(1, STEPS, "Retrieving");
+    let mut crater_check = None;
+    if args.crater || source_arg.starts_with(CRATER_REPORTS) {
+        let (dir, cmd) = crater_project(&source_arg)?;
+        info!("Reducing {} with `{}`", dir.display(), cmd.join(" "));
+        source_arg = dir.display().to_string();
+        crater_check = Some(cmd);
+    }
+    let mut project = cargo_project(&source_arg, args.project_file.as_deref())?;
+    let workspace = project.as_ref().map(|(root, _)| workspace_root(root));
+    // Every build uses the same dependencies
+    let lock = match &workspace {
+        Some(w) => Some(cargo::lockfile(w, &args.env).context("Failed to snapshot Cargo.lock")?),
+        None => None,
+    };
+    // Code generated by a build script, which the reduced file includes
+    let mut generated = Vec::new();
+    if let (Some((root, file)), Some(workspace), Some(lock), None) =
+        (&mut project, &workspace, &lock, &args.project_file)
+    {
+        let interesting =
+            Regex::new(&args.interesting_stderr).context("Invalid interesting stderr regex")?;
+        info!("Building the project to find where the ICE is...");
+        if let Some(site) = cargo::locate_ice(root, workspace, &args.env, lock, &interesting)? {
+            if site.file != *file {
+                info!("The ICE is in {}", site.file.display());
+            }
+            *file = site.file;
+            generated = site.generated;
+        }
+    }
+    let source_path = match &project {
+        Some((root, file)) => {
+            info!(
+                "Reducing {} in the Cargo project {}",
+                file.display(),
+                root.display()
+            );
+            root.join(file).display().to_string()
+        }
+        None => source_arg.clone(),
+    };
+    let mut source = retrieve(&source_path, &args.repo, args.force)?;
+    for (name, _) in &generated {
+        info!("Inlining {} generated by the build script", name);
+    }
+    if !generated.is_empty() {
+        source.code = cargo::inline_generated(&source.code, &generated);
+    }
+    if (args.post || args.post_dry_run) && source.issue.is_none() {
+        error!("--post requires an issue number as the input");
+        std::process::exit(1);
+    }
+    if args.crashes_pr && source.issue.is_none() {
+        error!("--crashes-pr requires an issue number as the input");
+        std::process::exit(1);
+    }
+    if args.crashes_pr && source.repo != issue::RUST_REPO {
+        error!("--crashes-pr requires a rust-lang/rust issue");
+        std::process::exit(1);
+    }
+    let mut original_size = source.code.len();
+    // Proc macros first, they can't depend on other crates here
+    let mut aux = args
+        .proc_macro
+        .iter()
+        .map(|p| (p, true))
+        .chain(args.aux.iter().map(|p| (p, false)))
+        .map(|(p, proc_macro)| {
+            Aux::read(p, proc_macro).with_context(|| format!("Failed to read file {}", p.display()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let fixture = if args.glacier {
+        Some(glacier::Fixture::read(&source_arg)?)
+    } else {
+        None
+    };
+    // Directory for this run on an issue, and the previous run
+    let run_dirs = match (&args.output, source.issue) {
+        (None, Some(issue)) => Some(runs::next_run_dir(issue)?),
+        _ => None,
+    };
+    let output = match (&args.output, &run_dirs, &fixture) {
+        (Some(o), _, _) => o.clone(),
+        (None, _, Some(f)) => f.path.clone(),
+        (None, Some((dir, _)), None) => {
+            info!("Writing results to {}", dir.display());
+            dir.join("melted.rs")
+        }
+        (None, None, None) if batched => batch::output(&source_arg),
+        (None, None, None) => PathBuf::from("melted.rs"),
+    };
+    let in_place = fixture.is_some() && args.output.is_none();
+    // Issue bodies get edited and files get modified, keep the exact input
+    let original_path = match &fixture {
+        Some(f) if in_place => f.original_path(),
+        _ => output.with_extension("orig.rs"),
+    };
+    let aux_dir = output.with_file_name("auxiliary");
+    let lock_path = output.with_extension("Cargo.lock");
+    if !args.force {
+        let mut outputs = vec![original_path.clone()];
+        if !in_place {
+            outputs.push(output.clone());
+        }
+        if lock.is_some() {
+            outputs.push(lock_path.clone());
+        }
+        if args.markdown {
+            outputs.push(output.with_extension("md"));
+        }
+        outputs.extend(aux.iter().map(|a| aux_dir.join(format!("{}.rs", a.name))));
+        // The exact input from a previous run on the same input is fine
+        let same_original = fs::read(&original_path).is_ok_and(|o| o == source.code.as_bytes());
+        let existing = outputs
+            .iter()
+            .filter(|p| p.exists() && !(same_original && **p == original_path))
+            .collect::<Vec<_>>();
+        if !existing.is_empty() {
+            for path in existing {
+                error!("{} already exists", path.display());
+            }
+            error!("Refusing to overwrite previous results. Pass --force to overwrite them.");
+            std::process::exit(1);
+        }
+    }
+    atomic::write(&original_path, &source.code)
+        .with_context(|| format!("Failed to write file to {}", original_path.display()))?;
+    debug!("Original input written to {}", original_path.display());
+    let mut original_code = args.report_include_original.then(|| source.code.clone());
+    let mut traced_code = args.trace.is_some().then(|| source.code.clone());
+    let mut rs = source.code;
+    let mut inlined = 0;
+    // Files from stdin or the web have nothing next to them to inline
+    if source.issue.is_none() && source_path != "-" && !is_url(&source_path) {
+        let dir = Path::new(&source_path).parent().unwrap_or(Path::new("."));
+        (rs, inlined) = flatten::flatten(&rs, dir)?;
+        if inlined > 0 {
+            info!("Inlined {} included files and modules", inlined);
+        }
+    }
+    let stubs = flatten::missing_includes(&rs)?;
+    if !stubs.is_empty() {
+        info!(
+            "Using empty files for {} missing included files",
+            stubs.len()
+        );
+        temp::stub_files(stubs);
+    }
+
+    info!("Step 2/{STEPS}: Configuring...");
+    dashboard.step(2, STEPS, "Configuring");
+    let mut check_cmd = args.check.clone();
+    // Target directories of checks in a Cargo project, deleted at the end
+    let mut _cargo_targets = None;
+    if let (Some((root, file)), Some(lock)) = (project.clone(), lock) {
+        if check_cmd == ["rustc"] {
+            check_cmd =
+                crater_check.unwrap_or_else(|| vec![String::from("cargo"), String::from("build")]);
+            check_cmd.push(String::from("--locked"));
+        }
+        atomic::write(&lock_path, &lock)
+            .with_context(|| format!("Failed to write file to {}", lock_path.display()))?;
+        info!("Dependencies are pinned to {}", lock_path.display());
+        let workspace = workspace.clone().unwrap_or_else(|| root.clone());
+        _cargo_targets = Some(temp::in_project(root, file, workspace, lock));
+    } else if !check_cmd.iter().any(|a| check::is_marker(a)) {
+        check_cmd.push(format!("@@{}", args.candidate_suffix));
+    }
+    if let Some(commit) = &args.alt {
+        check_cmd = with_toolchain(&check_cmd, &install_alt(commit)?);
+    }
+    let rustup_proxy = matches!(check_cmd[0].as_str(), "rustc" | "rustdoc");
+    if let (true, None, None, None, Some(body)) = (
+        rustup_proxy && toolchain(&check_cmd).is_none(),
+        &args.local_rustc,
+        &args.docker,
+        &project,
+        &source.issue_body,
+    ) {
+        let issue_toolchain = issue::rustc_version(body)
+            .and_then(|v| project::pinned(None, &v))
+            .filter(|tc| !tc.contains("-dev"));
+        if let Some(tc) = issue_toolchain {
+            match install_toolchain(&tc) {
+                Ok(()) => {
+                    info!(
+                        "Using {} from the issue, pass +TOOLCHAIN to override it",
+                        tc
+                    );
+                    check_cmd = with_toolchain(&check_cmd, &tc);
+                }
+                Err(e) => warn!("Failed to install {} from the issue: {:#}", tc, e),
+            }
+        }
+    }
+    // Every candidate is compiled with the harness, so the reducer keeps the
+    // `#[cfg(test)]` items that the ICE needs
+    if args.test && !check_cmd.iter().any(|a| a == "--test") {
+        check_cmd.push(String::from("--test"));
+    }
+    // Flags from the issue, which are dropped if the ICE doesn't reproduce
+    // with them
+    let mut auto_flags = Vec::new();
+    if let (false, None, Some(body)) = (args.no_auto_flags, &project, &source.issue_body) {
+        let mut names = issue::relevant_flags(&check_cmd)
+            .iter()
+            .map(|f| issue::flag_name(f))
+            .collect::<Vec<_>>();
+        let mut added = Vec::new();
+        for flag in issue::flags(body) {
+            let name = issue::flag_name(&flag);
+            if !names.contains(&name) {
+                names.push(name);
+                added.extend(flag);
+            }
+        }
+        if !added.is_empty() {
+            info!(
+                "Using flags from the issue: {} (pass --no-auto-flags to ignore them)",
+                added.join(" ")
+            );
+            check_cmd.extend(added.iter().cloned());
+            auto_flags = added;
+        }
+    }
+    if let Some(triple) = &args.target {
+        check_cmd = with_target(&check_cmd, triple);
+    }
+    if let (Some(triple), None, None) = (target(&check_cmd), &args.local_rustc, &args.docker) {
+        if install_target(toolchain(&check_cmd), triple).is_err() {
+            warn!("Failed to install target {}", triple);
+        }
+    }
+    let mut interesting_stderr = args.interesting_stderr.clone();
+    if let (true, Some(f)) = (
+        args.interesting_stderr == DEFAULT_INTERESTING_STDERR,
+        &fixture,
+    ) {
+        match f.signature() {
+            Some(rx) => {
+                debug!("ICE message regex from the fixture: {}", rx);
+                interesting_stderr = rx;
+            }
+            None => warn!("No ICE message in the fixture's expected stderr"),
+        }
+    }
+    if let Some(backend) = args.backend {
+        if let Some(flag) = backend.flag() {
+            if !check_cmd.iter().any(|a| a.starts_with("-Zcodegen-backend")) {
+                check_cmd.push(String::from(flag));
+            }
+        }
+        if let (Some(component), None, None) =
+            (backend.component(), &args.local_rustc, &args.docker)
+        {
+            if install_component(toolchain(&check_cmd), component).is_err() {
+                warn!("Failed to install {}", component);
+            }
+        }
+        if interesting_stderr == DEFAULT_INTERESTING_STDERR {
+            interesting_stderr = format!("({}|{})", interesting_stderr, backend.banner());
+        }
+    }
+    let mut env = args.env.clone();
+    if let Some(local_rustc) = &args.local_rustc {
+        let (local_cmd, lib_path) = local_rustc_command(local_rustc, &check_cmd)?;
+        debug!("Local rustc command line: {:?}", local_cmd);
+        check_cmd = local_cmd;
+        env.push(lib_path);
+    }
+    // Backtraces just slow down candidate checks, see `capture_backtrace`
+    if !env.iter().any(|(k, _)| k == "RUST_BACKTRACE") {
+        env.push((String::from("RUST_BACKTRACE"), String::from("0")));
+    }
+    if let Some(image) = &args.docker {
+        check_cmd = docker_command(image, &check_cmd, &env);
+        debug!("Docker command line: {:?}", check_cmd);
+    }
+    if check_cmd.iter().any(|a| a.starts_with("-Z")) && !is_nightly(&check_cmd) {
+        if args.bootstrap {
+            debug!("Setting RUSTC_BOOTSTRAP=1 to allow -Z flags");
+            env.push((String::from("RUSTC_BOOTSTRAP"), String::from("1")));
+        } else {
+            warn!("Using -Z flags on a stable or beta toolchain, try --bootstrap.");
+        }
+    }
+    let make_check = |check_cmd: Vec<String>| {
+        check(
+            args.debug,
+            timeout,
+            check_cmd,
+            env.clone(),
+            Some(interesting_stderr.clone()),
+            args.uninteresting_stderr.clone(),
+            &aux,
+        )
+    };
+    let mut initial = None;
+    if let Some((code, cmd, i)) = reproduce(&rs, &check_cmd, make_check)? {
+        (rs, check_cmd, initial) = (code, cmd, Some(i));
+    }
+    if initial.is_none() && !auto_flags.is_empty() {
+        info!("No ICE, trying without the flags from the issue...");
+        let without = without_flags(&check_cmd, &auto_flags);
+        if let Some((code, cmd, i)) = reproduce(&rs, &without, make_check)? {
+            (rs, check_cmd, initial) = (code, cmd, Some(i));
+        }
+    }
+    for alternative in source.alternatives {
+        if initial.is_some() {
+            break;
+        }
+        info!("No ICE, trying code from {} instead...", alternative.origin);
+        if let Some((code, cmd, i)) = reproduce(&alternative.code, &check_cmd, make_check)? {
+            info!("Using code from {}", alternative.origin);
+            atomic::write(&original_path, &alternative.code)
+                .with_context(|| format!("Failed to write file to {}", original_path.display()))?;
+            original_size = alternative.code.len();
+            if let Some(c) = &mut original_code {
+                c.clone_from(&alternative.code);
+            }
+            if let Some(c) = &mut traced_code {
+                c.clone_from(&alternative.code);
+            }
+            (rs, check_cmd, initial) = (code, cmd, Some(i));
+        }
+    }
+    let (error_codes, initial_stderr) = match initial {
+        Some(i) => i,
+        None => {
+            error!("The file doesn't seem to produce an ICE.");
+            if inlined > 0 {
+                error!("Maybe it doesn't after inlining included files and modules?");
+            }
+            std::process::exit(1);
+        }
+    };
+    if args.interesting_stderr == DEFAULT_INTERESTING_STDERR {
+        if let Some(msg) = source.issue_body.as_deref().and_then(issue::ice_message) {
+            let msg_regex = issue::message_regex(&msg);
+            debug!("ICE message regex: {}", msg_regex);
+            if Regex::new(&msg_regex)
+                .map(|r| r.is_match(&initial_stderr))
+                .unwrap_or(false)
+            {
+                info!(
+                    "Only accepting ICEs with the message from the issue: {}",
+                    msg
+                );
+                interesting_stderr = msg_regex;
+            } else {
+                warn!("The ICE message from the issue doesn't match the local ICE");
+            }
+        }
+    }
+    if !args.force {
+        let known_issue = source.issue.filter(|_| source.repo == issue::RUST_REPO);
+        check_known(args.known.clone(), known_issue, &initial_stderr)?;
+    }
+    // Only melt each ICE once per batch
+    if batched {
+        let mut backtrace_env = env.clone();
+        backtrace_env.retain(|(k, _)| k != "RUST_BACKTRACE");
+        backtrace_env.push((String::from("RUST_BACKTRACE"), String::from("full")));
+        let chk = check(
+            args.debug,
+            timeout,
+            check_cmd.clone(),
+            backtrace_env,
+            Some(interesting_stderr.clone()),
+            None,
+            &aux,
+        )?;
+        let stderr = capture_backtrace(&chk, rs.as_bytes())?;
+        if let Some(first) = stderr.as_deref().and_then(batch::melted_duplicate) {
+            info!("Same ICE as {}, which was already melted", first.source);
+            let ice = stderr.as_deref().map(backtrace::Backtrace::parse);
+            let mut record = history::Record::now(
+                source_arg,
+                source.issue,
+                rs.len(),
+                rs.len(),
+                ice.as_ref(),
+                None,
+                None,
+            );
+            record.output = first.output;
+            record.duplicate_of = Some(first.source);
+            batch::record(&record);
+            return Ok(());
+        }
+    }
+    if args.preflight {
+        info!("Checking whether the ICE reproduces on the latest nightly...");
+        if !args.update_nightly {
+            debug!("Using the installed nightly, pass --update-nightly to update it");
+        } else if update_nightly().is_err() {
+            warn!("Failed to update the nightly toolchain");
+        }
+        if let Some(triple) = target(&check_cmd) {
+            if install_target(Some("nightly"), triple).is_err() {
+                warn!("Failed to install target {} for nightly", triple);
+            }
+        }
+        let nightly_chk = check(
+            args.debug,
+            timeout,
+            latest_nightly_command(&check_cmd),
+            env.clone(),
+            Some(interesting_stderr.clone()),
+            None,
+            &aux,
+        )?;
+        if !nightly_chk.interesting(rs.as_bytes())? {
+            warn!("The ICE doesn't reproduce on the latest nightly, it may have been fixed!");
+            let bisect_report = if args.bisect {
+                info!("Bisecting the fix (this can take a very long time)...");
+                match run_bisect(&check_cmd, &env, rs.as_bytes(), &interesting_stderr, true) {
+                    Ok(report) => Some(report),
+                    Err(e) => {
+                        warn!("Not bisecting: {:#}", e);
+                        None
+                    }
+                }
+            } else {
+                info!("Try adding --bisect to find the fix.");
+                None
+            };
+            if args.markdown {
+                fixed_markdown(
+                    output.with_extension("md"),
+                    check_cmd,
+                    bisect_report,
+                    !args.no_sanitize,
+                )?;
+            }
+            if let Some(n) = &args.notify {
+                notify::send(
+                    n,
+                    &format!("The ICE in {source_arg} doesn't reproduce on the latest nightly"),
+                    &output,
+                    None,
+                );
+            }
+            return Ok(());
+        }
+        info!("The ICE reproduces on the latest nightly");
+    }
+    let preserved_codes = args.preserve_diagnostics.then(|| error_codes.clone());
+    let fingerprint = (!args.any_ice && args.interesting_stderr == DEFAULT_INTERESTING_STDERR)
+        .then(|| backtrace::Fingerprint::parse(&initial_stderr))
+        .filter(|f| !f.is_empty());
+    if let Some(f) = &fingerprint {
+        info!(
+            "Only accepting ICEs like the input's: {} (pass --any-ice to accept any)",
+            f
+        );
+    }
+    let baseline_check = |cmd: &[String], aux: &[Aux]| -> Result<Option<IceCheck>> {
+        args.baseline
+            .as_ref()
+            .map(|tc| {
+                check(
+                    args.debug,
+                    timeout,
+                    with_toolchain(cmd, tc),
+                    env.clone(),
+                    Some(args.interesting_stderr.clone()),
+                    None,
+                    aux,
+                )
+            })
+            .transpose()
+    };
+    // Checks for candidates, as opposed to checks for the report
+    let for_candidates = |chk: IceCheck, cmd: &[String], aux: &[Aux]| -> Result<IceCheck> {
+        let mut chk = match &preserved_codes {
+            Some(codes) => chk.preserve_diagnostics(codes.clone()),
+            None => chk,
+        };
+        if let Some(f) = &fingerprint {
+            chk = chk.require_fingerprint(f.clone());
+        }
+        if let Some(baseline_chk) = baseline_check(cmd, aux)? {
+            chk = chk.unless_reproduces(baseline_chk);
+        }
+        Ok(chk)
+    };
+    let uninteresting_stderr = if args.allow_errors {
+        args.uninteresting_stderr
+    } else {
+        for error_code in &error_codes {
+            debug!("Found error code {}", error_code);
+        }
+        let fresh_error_regex = error_regex(HashSet::from_iter(error_codes), project.is_some());
+        let uninteresting_regex = match args.uninteresting_stderr {
+            Some(u) => format!("(?m)({}|{})", u, fresh_error_regex),
+            None => format!("(?m){}", fresh_error_regex),
+        };
+        debug!("Initial stderr: {}", initial_stderr);
+        debug!("Error regex: {}", uninteresting_regex);
+        debug_assert!(!Regex::new(&uninteresting_regex)
+            .unwrap()
+            .is_match(&initial_stderr));
+        Some(uninteresting_regex)
+    };
+
+    if let (Some(tc), Some(baseline_chk)) = (&args.baseline, baseline_check(&check_cmd, &aux)?) {
+        let (interesting, _, _, stderr) =
+            baseline_chk.wait_with_output(baseline_chk.start(rs.as_bytes())?)?;
+        if interesting {
+            error!("The input also ICEs on the baseline toolchain {}", tc);
+            std::process::exit(1);
+        }
+        if let Some(e) = rejected_command(&String::from_utf8_lossy(&stderr)) {
+            error!("The baseline toolchain {} can't check the input: {}", tc, e);
+            error!("Pass a --baseline that accepts the same flags (e.g., a nightly)");
+            std::process::exit(1);
+        }
+        info!("The input doesn't ICE on the baseline toolchain {}", tc);
+    }
+
+    info!("Step 3/{STEPS}: Reducing...");
+    dashboard.step(3, STEPS, "Reducing");
+    let tui = if args.tui {
+        Some(tui::Tui::start(&source_arg)?)
+    } else {
+        None
+    };
+    if let (Some(path), Some(code)) = (&args.trace, &traced_code) {
+        trace::create(path)?;
+        trace::start(trace::MAIN, code.as_bytes(), rs.as_bytes());
+        for a in &aux {
+            trace::start(&a.name, &a.code, &a.code);
+        }
+    }
+    let mut chk = for_candidates(
+        check(
+            args.debug,
+            timeout,
+            check_cmd.clone(),
+            env.clone(),
+            Some(interesting_stderr.clone()),
+            uninteresting_stderr.clone(),
+            &aux,
+        )?,
+        &check_cmd,
+        &aux,
+    )?;
+    if args.scale_timeout || args.optimize_check_time {
+        let check_time = check_time(&chk, rs.as_bytes(), args.jobs)?;
+        info!(
+            "Checking the input takes {}ms with {} jobs",
+            check_time.as_millis(),
+            args.jobs
+        );
+        if args.scale_timeout {
+            chk = chk.scale_timeout(check_time, rs.len());
+        }
+        if args.optimize_check_time {
+            chk = chk.prefer_faster(check_time);
+        }
+    }
+    let mut pass_stats = Vec::new();
+    let mut reduced = run_pass(
+        &mut pass_stats,
+        "reduce",
+        &chk,
+        rs.as_bytes().to_vec(),
+        |_| reduce(&rs, args.jobs, chk.clone()).context("Failed when reducing the program"),
+    )?;
+    reduced = run_pass(&mut pass_stats, "lints", &chk, reduced, |r| {
+        passes::lints(&chk, &check_cmd, &env, r).context("Failed when applying rustc's suggestions")
+    })?;
+    let rustdoc = is_rustdoc(&check_cmd);
+    for pass in passes::PASSES.iter().filter(|p| p.rustdoc || !rustdoc) {
+        reduced = run_pass(&mut pass_stats, pass.name, &chk, reduced, |r| {
+            passes::run(pass, &chk, r)
+                .with_context(|| format!("Failed when running the {} pass", pass.name))
+        })?;
+    }
+    trace::pass(Some("lib"));
+    if let Some((lib, lib_cmd)) = without_trivial_main(&reduced, &check_cmd) {
+        debug!(
+            "Trying to drop trivial main with command line {:?}",
+            lib_cmd
+        );
+        let lib_chk = for_candidates(
+            check(
+                args.debug,
+                timeout,
+                lib_cmd.clone(),
+                env.clone(),
+                Some(interesting_stderr.clone()),
+                uninteresting_stderr.clone(),
+                &aux,
+            )?,
+            &lib_cmd,
+            &aux,
+        )?;
+        if lib_chk.interesting(&lib)? {
+            info!("Dropped trivial `fn main`, compiling as a library");
+            reduced = lib;
+            check_cmd = lib_cmd;
+            chk = lib_chk;
+        }
+    }
+    // Libraries are simplest, then binaries
+    let crate_type = flag_value(&check_cmd, "--crate-type")
+        .unwrap_or("bin")
+        .to_string();
+    trace::pass(Some("crate-type"));
+    for ty in ["lib", "bin"] {
+        if ty == crate_type {
+            break;
+        }
+        let ty_cmd = with_flag(&check_cmd, "--crate-type", ty);
+        debug!("Trying crate type {} with command line {:?}", ty, ty_cmd);
+        let ty_chk = for_candidates(
+            check(
+                args.debug,
+                timeout,
+                ty_cmd.clone(),
+                env.clone(),
+                Some(interesting_stderr.clone()),
+                uninteresting_stderr.clone(),
+                &aux,
+            )?,
+            &ty_cmd,
+            &aux,
+        )?;
+        if ty_chk.interesting(&reduced)? {
+            info!("Compiling as crate type {} instead of {}", ty, crate_type);
+            check_cmd = ty_cmd;
+            chk = ty_chk;
+            break;
+        }
+    }
+    for i in 0..aux.len() {
+        info!("Reducing auxiliary crate {}...", aux[i].name);
+        let aux_chk = chk.clone().reducing_aux(i, reduced.clone());
+        let aux_rs = String::from_utf8_lossy(&aux[i].code).into_owned();
+        let name = format!("aux:{}", aux[i].name);
+        aux[i].code = run_pass(&mut pass_stats, &name, &chk, aux[i].code.clone(), |_| {
+            reduce(&aux_rs, args.jobs, aux_chk)
+                .with_context(|| format!("Failed when reducing {}", aux[i].name))
+        })?;
+        chk = chk.with_aux(aux.clone());
+    }
+    trace::pass(Some("extern"));
+    let stub_dir = output.with_file_name("stubs");
+    for e in externs::find(&check_cmd).into_iter().rev() {
+        let without_cmd = externs::without(&check_cmd, &e);
+        let without_chk = for_candidates(
+            check(
+                args.debug,
+                timeout,
+                without_cmd.clone(),
+                env.clone(),
+                Some(interesting_stderr.clone()),
+                uninteresting_stderr.clone(),
+                &aux,
+            )?,
+            &without_cmd,
+            &aux,
+        )?;
+        if without_chk.interesting(&reduced)? {
+            info!("Dropped --extern {}", e.name);
+            check_cmd = without_cmd;
+            chk = without_chk;
+            continue;
+        }
+        // Stubs would need to be built in the container
+        if e.path.is_none() || args.docker.is_some() {
+            continue;
+        }
+        let stub = match externs::build_stub(&stub_command(&check_cmd), &e.name, &stub_dir) {
+            Ok(s) => s,
+            Err(err) => {
+                debug!("{:#}", err);
+                continue;
+            }
+        };
+        let stub_cmd = externs::with_path(&check_cmd, &e, &stub);
+        let stub_chk = for_candidates(
+            check(
+                args.debug,
+                timeout,
+                stub_cmd.clone(),
+                env.clone(),
+                Some(interesting_stderr.clone()),
+                uninteresting_stderr.clone(),
+                &aux,
+            )?,
+            &stub_cmd,
+            &aux,
+        )?;
+        if stub_chk.interesting(&reduced)? {
+            info!("Replaced --extern {} with an empty crate", e.name);
+            check_cmd = stub_cmd;
+            chk = stub_chk;
+        } else {
+            let _ = fs::remove_file(&stub);
+        }
+    }
+    // Only there if a stub was used
+    let _ = fs::remove_dir(&stub_dir);
+    trace::pass(Some("no-std"));
+    let mut no_std = false;
+    if args.try_no_std && !String::from_utf8_lossy(&reduced).contains("#![no_std]") {
+        for (variant, no_std_cmd) in no_std_variants(&reduced, &check_cmd) {
+            let no_std_chk = for_candidates(
+                check(
+                    args.debug,
+                    timeout,
+                    no_std_cmd.clone(),
+                    env.clone(),
+                    Some(interesting_stderr.clone()),
+                    uninteresting_stderr.clone(),
+                    &aux,
+                )?,
+                &no_std_cmd,
+                &aux,
+            )?;
+            if no_std_chk.interesting(&variant)? {
+                info!("The ICE reproduces with `#![no_std]`");
+                reduced = variant;
+                check_cmd = no_std_cmd;
+                chk = no_std_chk;
+                no_std = true;
+                break;
+            }
+        }
+        if !no_std {
+            info!("The ICE doesn't reproduce with `#![no_std]`");
+        }
+    }
+    if args.suppress_warnings {
+        trace::pass(Some("allow"));
+        let state = chk.start(&reduced)?;
+        let (_, _, _, stderr) = chk.wait_with_output(state)?;
+        let lints = warned_lints(&String::from_utf8_lossy(&stderr));
+        if !lints.is_empty() {
+            let allow = format!("#![allow({})]\n", lints.join(", "));
+            let candidate = [allow.as_bytes(), &reduced].concat();
+            if chk.interesting(&candidate)? {
+                info!("Suppressed warnings with {}", allow.trim_end());
+                reduced = candidate;
+            } else {
+                debug!("Suppressing warnings affected the ICE");
+            }
+        }
+    }
+    if let Some(t) = tui {
+        t.stop();
+    }
+    if tui::aborted() {
+        atomic::write(&output, with_aux_annotations(&reduced, &aux))
+            .with_context(|| format!("Failed to write file to {}", output.display()))?;
+        info!("Aborted, best candidate written to {}", output.display());
+        std::process::exit(1);
+    }
+    let did_reduce = reduced != rs.as_bytes();
+    if did_reduce {
+        debug!("Reduced!");
+        if args.allow_errors {
+            info!("Unable to reduce! Sorry.");
+            info!("If you think this test case is reducible, please file an issue!");
+        } else {
+            info!("Unable to reduce, try --allow-errors.");
+        }
+    }
+
+    info!("Step 4/{STEPS}: Formatting...");
+    dashboard.step(4, STEPS, "Formatting");
+    trace::pass(Some("format"));
+    let (fmt_result, formatted) = match fmt(&chk, &reduced) {
         Err(_) => {
             warn!("Failed to format with rustfmt");
             (FormatResult::CouldntFormat, reduced)
@@ -538,43 +3548,169 @@ pub fn main() -> Result<()> {
             (r, reduced)
         }
     };
+    trace::output(trace::MAIN, &formatted);
+    for a in &aux {
+        trace::output(&a.name, &a.code);
+    }
+    if let Some(path) = &args.trace {
+        info!("Trace written to {}", path.display());
+    }
 
-    let bisect_report = if args.bisect {
+    let mut backtrace_env = env.clone();
+    backtrace_env.retain(|(k, _)| k != "RUST_BACKTRACE");
+    backtrace_env.push((String::from("RUST_BACKTRACE"), String::from("full")));
+    let backtrace_stderr = capture_backtrace(
+        &check(
+            args.debug,
+            timeout,
+            check_cmd.clone(),
+            backtrace_env,
+            Some(interesting_stderr.clone()),
+            None,
+            &aux,
+        )?,
+        &formatted,
+    )?;
+    let backtrace = backtrace_stderr.as_deref().and_then(backtrace::extract);
+    let ice = backtrace_stderr.as_deref().map(backtrace::Backtrace::parse);
+    let suggested_labels = ice.as_ref().map(labels::suggest).unwrap_or_default();
+    if !suggested_labels.is_empty() {
+        info!("Suggested labels: {}", suggested_labels.join(", "));
+    }
+    let ice_phase = ice.as_ref().and_then(|bt| phase::classify(bt, &check_cmd));
+    if let Some(p) = ice_phase {
+        info!("ICE phase: {}", p);
+    }
+    let mut target_results = Vec::new();
+    for triple in &args.verify_targets {
+        let target_cmd = with_target(&check_cmd, triple);
+        if args.local_rustc.is_none()
+            && args.docker.is_none()
+            && install_target(toolchain(&target_cmd), triple).is_err()
+        {
+            warn!("Failed to install target {}", triple);
+        }
+        let reproduces = check(
+            args.debug,
+            timeout,
+            target_cmd,
+            env.clone(),
+            Some(interesting_stderr.clone()),
+            None,
+            &aux,
+        )?
+        .interesting(&formatted)?;
+        info!(
+            "The ICE {} on {}",
+            if reproduces {
+                "reproduces"
+            } else {
+                "doesn't reproduce"
+            },
+            triple
+        );
+        target_results.push((triple.clone(), reproduces));
+    }
+    let current_edition = edition(&check_cmd).to_string();
+    let min_edition = if EDITIONS.contains(&current_edition.as_str()) {
+        let mut min_edition = current_edition.clone();
+        for ed in EDITIONS.iter().take_while(|e| **e != current_edition) {
+            debug!("Trying edition {}", ed);
+            let edition_chk = check(
+                args.debug,
+                timeout,
+                with_flag(&check_cmd, "--edition", ed),
+                env.clone(),
+                Some(interesting_stderr.clone()),
+                None,
+                &aux,
+            )?;
+            if edition_chk.interesting(&formatted)? {
+                min_edition = ed.to_string();
+                break;
+            }
+        }
+        if min_edition == EDITIONS[0] {
+            info!("The ICE reproduces on every edition");
+        } else {
+            info!("The ICE requires edition {} or later", min_edition);
+        }
+        Some(min_edition)
+    } else {
+        None
+    };
+    // Some ICEs only happen with debug assertions, as in alt builds
+    let release_reproduces = if args.alt.is_some() || args.local_rustc.is_some() {
+        info!("Checking whether the ICE reproduces on the latest release nightly...");
+        if !args.update_nightly {
+            debug!("Using the installed nightly, pass --update-nightly to update it");
+        } else if update_nightly().is_err() {
+            warn!("Failed to update the nightly toolchain");
+        }
+        let mut release_env = env.clone();
+        release_env.retain(|(k, _)| k != "LD_LIBRARY_PATH" && k != "DYLD_LIBRARY_PATH");
+        let reproduces = check(
+            args.debug,
+            timeout,
+            release_nightly_command(&check_cmd),
+            release_env,
+            Some(interesting_stderr.clone()),
+            None,
+            &aux,
+        )?
+        .interesting(&formatted)?;
+        if reproduces {
+            info!("The ICE also reproduces on the latest release nightly");
+        } else {
+            warn!("The ICE doesn't reproduce on release nightlies, it may need debug assertions");
+        }
+        Some(reproduces)
+    } else {
+        None
+    };
+
+    if args.bisect_print {
+        print_bisect(
+            &check_cmd,
+            &env,
+            &formatted,
+            &interesting_stderr,
+            output.parent().unwrap_or(Path::new(".")),
+        )?;
+    }
+    let bisect_report = if args.bisect || args.bisect_local.is_some() {
         info!("Step 5/{STEPS}: Bisecting (this can take a very long time)...");
-        let mut rustc_args = args.check.clone();
-        rustc_args.remove(0);
-        if !rustc_args.is_empty() && rustc_args[0].starts_with('+') {
-            rustc_args.remove(0);
-        }
-        let out = bisect(rustc_args, formatted.as_slice(), &args.interesting_stderr)?;
-        std::fs::write("cargo-bisect-rustc.stdout.txt", &out.stdout)?;
-        std::fs::write("cargo-bisect-rustc.stderr.txt", &out.stderr)?;
-        info!("Wrote to cargo-bisect-rustc.std{{out,err}}.txt");
-        if !out.status.success() {
-            warn!("cargo-bisect-rustc failed");
-        }
-        let mut bisect_report = Vec::with_capacity(12);
-        let mut eq_headings = 0;
-        let mut ast_headings = 0;
-        let stderr_str = String::from_utf8_lossy(out.stderr.as_slice());
-        for line in stderr_str.lines() {
-            if line.starts_with(
-                "********************************************************************************",
+        dashboard.step(5, STEPS, "Bisecting");
+        let mut reports = Vec::new();
+        if args.bisect {
+            match run_bisect(
+                &check_cmd,
+                &env,
+                formatted.as_slice(),
+                &interesting_stderr,
+                false,
             ) {
-                ast_headings += 1;
-            } else if ast_headings == 1 {
-                bisect_report.push(line);
-            }
-            if line.starts_with("==================================================================================") {
-                eq_headings +=1;
-            } else if eq_headings >= 2 {
-                bisect_report.push(line);
+                Ok(report) => reports.push(report),
+                Err(e) => warn!("Not bisecting: {:#}", e),
             }
         }
-        Some(bisect_report.join("\n"))
+        if let (Some(rust), Some(good)) = (&args.bisect_local, &args.bisect_local_good) {
+            reports.push(bisect_local::bisect_local(
+                rust,
+                good,
+                &args.bisect_local_bad,
+                &rustc_args(&check_cmd),
+                &env,
+                formatted.as_slice(),
+                &interesting_stderr,
+            )?);
+        }
+        Some(reports.join("\n\n"))
     } else {
-        warn!("Skipping bisection! Try adding --bisect.");
-        info!("Bisecting takes a long time, but it's very helpful.");
+        if !args.bisect_print {
+            warn!("Skipping bisection! Try adding --bisect.");
+            info!("Bisecting takes a long time, but it's very helpful.");
+        }
         None
     };
 
@@ -588,21 +3724,292 @@ pub fn main() -> Result<()> {
             debug_assert!(did_format);
             "Formatted"
         };
-        fs::write(&args.output, &formatted)
-            .with_context(|| format!("Failed to write file to {}", args.output.display()))?;
-        info!("{} file written to {}", edited, args.output.display());
+        atomic::write(&output, with_aux_annotations(&formatted, &aux))
+            .with_context(|| format!("Failed to write file to {}", output.display()))?;
+        info!("{} file written to {}", edited, output.display());
+    }
+    if fixture.is_some() {
+        let state = chk.start(&formatted)?;
+        let (interesting, _, _, stderr) = chk.wait_with_output(state)?;
+        if interesting {
+            let path = glacier::write_stderr(&output, &String::from_utf8_lossy(&stderr))?;
+            info!("Expected stderr written to {}", path.display());
+        } else {
+            warn!("The final file didn't produce an ICE, not updating the expected stderr");
+        }
+    }
+    if !aux.is_empty() {
+        fs::create_dir_all(&aux_dir)
+            .with_context(|| format!("Failed to create directory {}", aux_dir.display()))?;
+        for a in &aux {
+            let path = aux_dir.join(format!("{}.rs", a.name));
+            atomic::write(&path, &a.code)
+                .with_context(|| format!("Failed to write file to {}", path.display()))?;
+        }
+        info!("Auxiliary crates written to {}", aux_dir.display());
+    }
+    let out_dir = output.parent().unwrap_or(Path::new("."));
+    for stub in flatten::missing_includes(&String::from_utf8_lossy(&formatted))? {
+        let path = out_dir.join(stub);
+        if path.exists() {
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+        }
+        atomic::write(&path, "")
+            .with_context(|| format!("Failed to write file to {}", path.display()))?;
+        info!("Empty included file written to {}", path.display());
+    }
+    if let Some(dir) = &args.emit_project {
+        let command = project::emit(
+            dir,
+            &rustc_args(&check_cmd),
+            toolchain(&check_cmd),
+            &rustc_version(check_cmd.clone()),
+            is_rustdoc(&check_cmd),
+            &formatted,
+            &aux,
+            args.force,
+        )?;
+        info!(
+            "Cargo project written to {}, reproduce with `{}`",
+            dir.display(),
+            command
+        );
+    }
+
+    let removed = 1.0 - formatted.len() as f64 / std::cmp::max(1, original_size) as f64;
+    let partial = match args.min_reduction_ratio {
+        Some(ratio) if removed < ratio => {
+            let mut tips = Vec::new();
+            if !args.allow_errors {
+                tips.push("`--allow-errors`");
+            }
+            tips.push("a longer `--timeout`");
+            let guidance = format!(
+                "The output is only {:.0}% smaller than the input (wanted {:.0}%), try {}.",
+                removed * 100.0,
+                ratio * 100.0,
+                tips.join(" or ")
+            );
+            warn!("{}", guidance.replace('`', ""));
+            Some(guidance)
+        }
+        _ => None,
+    };
+
+    let mut record = history::Record::now(
+        source_arg,
+        source.issue,
+        original_size,
+        formatted.len(),
+        ice.as_ref(),
+        bisect_report.as_deref().and_then(bisect_pr),
+        if args.markdown {
+            Some(output.with_extension("md"))
+        } else {
+            None
+        },
+    );
+    record.passes = pass_stats;
+    record.output = Some(output.clone());
+    record.repo = source
+        .issue
+        .map(|_| source.repo.clone())
+        .filter(|r| r != issue::RUST_REPO);
+    let tested = match (&args.local_rustc, &args.docker) {
+        (Some(rustc), _) => Some(rustc.display().to_string()),
+        (_, Some(image)) => Some(format!("{image} (docker)")),
+        _ => toolchain(&check_cmd)
+            .map(String::from)
+            .or_else(environment::active_toolchain),
+    };
+    let mut toolchains = vec![tested.unwrap_or_else(|| String::from("unknown"))];
+    if args.preflight {
+        toolchains.push(String::from("nightly (preflight)"));
+    }
+    toolchains.extend(args.baseline.iter().map(|tc| format!("{tc} (baseline)")));
+    let environment = environment::capture(&rustc_version(check_cmd.clone()), toolchains);
+    record.environment = Some(environment.clone());
+    let history_path = if args.no_history {
+        None
+    } else {
+        args.history.clone().or_else(history::default_path)
+    };
+    if let Some(path) = history_path {
+        if let Err(e) = history::append(&path, &record) {
+            warn!("Failed to record run in history: {:#}", e);
+        }
+    }
+    batch::record(&record);
+    if let Some(path) = &args.export_csv {
+        history::append_csv(path, &record)?;
+        info!("Wrote summary to {}", path.display());
     }
 
-    if args.markdown {
-        markdown(
-            args.output.with_extension("md"),
-            args.check,
-            formatted,
+    let shared = if args.no_sanitize
+        || !(args.markdown || args.post || args.post_dry_run || args.crashes_pr)
+    {
+        formatted.clone()
+    } else {
+        sanitized_code(&chk, &formatted)?
+    };
+    let rustc_cmd = match &args.docker {
+        Some(_) => in_container(&check_cmd),
+        None => &check_cmd,
+    };
+    let crash_test = source.issue.filter(|_| args.crashes_pr).map(|n| {
+        crashes::test_file(
+            n,
+            rustc_cmd.get(1..).unwrap_or_default(),
+            &with_aux_annotations(&shared, &aux),
+        )
+    });
+    if args.markdown || args.post || args.post_dry_run {
+        let report = markdown(
+            check_cmd,
+            &env,
+            shared,
             did_reduce,
             &fmt_result,
             bisect_report,
+            backtrace,
+            &suggested_labels,
+            ice_phase,
+            &aux,
+            &target_results,
+            min_edition.as_deref(),
+            no_std,
+            &output
+                .file_name()
+                .map(|f| f.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            release_reproduces,
+            backtrace_stderr.as_deref(),
+            original_code.as_deref(),
+            &environment,
+            partial.as_deref(),
+            !args.no_sanitize,
         )?;
+        if args.markdown {
+            write_markdown(&output.with_extension("md"), &report)?;
+        }
+        if args.post || args.post_dry_run {
+            let issue_number = source
+                .issue
+                .context("--post requires an issue number as the input")?;
+            post_report(
+                &source.repo,
+                issue_number,
+                &report,
+                args.yes,
+                args.post_dry_run,
+            )?;
+        }
+    }
+    if let (Some(issue_number), Some(test)) = (source.issue, crash_test) {
+        open_crashes_pr(issue_number, &test, &aux, args.fork.as_deref(), args.yes)?;
+    }
+    if let Some(n) = &args.notify {
+        notify::send(
+            n,
+            &format!(
+                "Melted {}: {} bytes reduced to {}, written to {}",
+                record.source,
+                record.original_size,
+                record.reduced_size,
+                output.display()
+            ),
+            &output,
+            Some(&record),
+        );
+    }
+    if let Some(stream) = &args.zulip_stream {
+        let topic = args.zulip_topic.clone().unwrap_or_else(|| {
+            record
+                .issue_reference()
+                .unwrap_or_else(|| record.source.clone())
+        });
+        let mut summary = zulip_summary(&record);
+        if !args.no_sanitize {
+            summary = sanitize::paths(&summary);
+        }
+        match post_to_zulip(stream, &topic, &summary) {
+            Ok(()) => info!("Posted summary to Zulip stream {} > {}", stream, topic),
+            Err(e) => warn!("{:#}", e),
+        }
+    }
+    if let Some((dir, previous)) = &run_dirs {
+        runs::write_summary(dir, &record)?;
+        if let Some(prev) = previous {
+            let changes = runs::changes(prev, &record).unwrap_or_else(|e| {
+                warn!("Couldn't compare to the previous run: {:#}", e);
+                Vec::new()
+            });
+            if changes.is_empty() {
+                info!("No changes since {}", prev.display());
+            } else {
+                info!("Changes since {}:", prev.display());
+                for change in &changes {
+                    info!("  {}", change);
+                }
+            }
+            atomic::write(dir.join("changes.txt"), changes.join("\n") + "\n")
+                .context("Failed to write changes.txt")?;
+        }
+    }
+    info!("Reduction passes:");
+    for pass in &record.passes {
+        info!(
+            "  {}: removed {} bytes in {} checks",
+            pass.name, pass.removed, pass.checks
+        );
+    }
+    dashboard.finish(Some(output.clone()), record.report);
+    if let Some(dir) = temp::kept() {
+        info!("Temporary files kept in {}", dir.display());
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duration_units() {
+        let ms = Duration::from_millis;
+        assert_eq!(parse_duration("2s"), Ok(ms(2000)));
+        assert_eq!(parse_duration("2sec"), Ok(ms(2000)));
+        assert_eq!(parse_duration("500ms"), Ok(ms(500)));
+        assert_eq!(parse_duration("1.5s"), Ok(ms(1500)));
+        assert_eq!(parse_duration("5m"), Ok(ms(300_000)));
+        assert_eq!(parse_duration("5min"), Ok(ms(300_000)));
+        assert_eq!(parse_duration("1h"), Ok(ms(3_600_000)));
+        assert_eq!(parse_duration("1h 30min"), Ok(ms(5_400_000)));
+        assert_eq!(parse_duration("1m30s"), Ok(ms(90_000)));
+    }
+
+    #[test]
+    fn duration_bare_number_is_milliseconds() {
+        assert_eq!(parse_duration("250"), Ok(Duration::from_millis(250)));
+    }
+
+    #[test]
+    fn duration_errors() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("0s").is_err());
+        assert!(parse_duration("2 days").is_err());
+        assert!(parse_duration("s").is_err());
+    }
+
+    #[test]
+    fn interval_bare_number_is_seconds() {
+        assert_eq!(parse_interval("2"), Ok(Duration::from_secs(2)));
+        assert_eq!(parse_interval("500ms"), Ok(Duration::from_millis(500)));
+        assert!(parse_interval("0").is_err());
+    }
+}
@@ -0,0 +1,89 @@
+use std::path::Path;
+use std::process::Command;
+
+use serde::Serialize;
+use tracing::{debug, warn};
+
+use crate::history::Record;
+
+/// Where to send a notification when a run finishes, see `--notify`
+#[derive(Clone, Debug)]
+pub(crate) enum Notify {
+    Desktop,
+    Webhook(String),
+}
+
+pub(crate) fn parse(s: &str) -> Result<Notify, String> {
+    if s == "desktop" {
+        Ok(Notify::Desktop)
+    } else if let Some(url) = s.strip_prefix("url:") {
+        Ok(Notify::Webhook(url.to_string()))
+    } else {
+        Err(format!(
+            "expected 'desktop' or 'url:<webhook>', found '{s}'"
+        ))
+    }
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    /// For Slack-compatible webhooks
+    text: &'a str,
+    output: &'a Path,
+    record: Option<&'a Record>,
+}
+
+fn desktop(summary: &str) -> std::io::Result<std::process::ExitStatus> {
+    if cfg!(target_os = "macos") {
+        Command::new("osascript")
+            .arg("-e")
+            .arg(format!(
+                "display notification \"{}\" with title \"icemelter\"",
+                summary.replace('\\', "\\\\").replace('"', "\\\"")
+            ))
+            .status()
+    } else {
+        Command::new("notify-send")
+            .arg("icemelter")
+            .arg(summary)
+            .status()
+    }
+}
+
+#[cfg(feature = "fetch")]
+fn webhook(url: &str, payload: &Payload) -> anyhow::Result<()> {
+    crate::http::CLIENT
+        .post(url)
+        .json(payload)
+        .send()?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(not(feature = "fetch"))]
+fn webhook(_url: &str, _payload: &Payload) -> anyhow::Result<()> {
+    Err(anyhow::anyhow!(
+        "Icemelter was built without the `fetch` feature"
+    ))
+}
+
+/// Send a notification, warning (but not failing) if it can't be sent
+pub(crate) fn send(notify: &Notify, summary: &str, output: &Path, record: Option<&Record>) {
+    debug!("Sending notification: {}", summary);
+    match notify {
+        Notify::Desktop => match desktop(summary) {
+            Ok(s) if s.success() => (),
+            _ => warn!("Failed to send desktop notification"),
+        },
+        Notify::Webhook(url) => {
+            let payload = Payload {
+                text: summary,
+                output,
+                record,
+            };
+            if let Err(e) = webhook(url, &payload) {
+                warn!("Failed to send notification to {}: {:#}", url, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,558 @@
+//! Rust-specific simplifications that deleting subtrees can't make, e.g.,
+//! dropping a turbofish without leaving a dangling `::`
+//!
+//! Each pass suggests [`Edit`]s, which are kept if the result is smaller and
+//! still interesting.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::ops::Range;
+use std::process::{Command, Stdio};
+
+use serde_json::Value;
+use tracing::debug;
+use tree_sitter::{Node, Tree};
+use treereduce::Check;
+
+use crate::check;
+use crate::temp;
+
+/// Lints with fixes that [`lints`] applies
+const LINTS: &[&str] = &["unused_imports", "unused_variables"];
+
+/// Replacements of byte ranges, made together
+pub(crate) type Edit = Vec<(Range<usize>, String)>;
+
+pub(crate) struct Pass {
+    pub(crate) name: &'static str,
+    /// Whether to run when checking with rustdoc
+    pub(crate) rustdoc: bool,
+    edits: fn(Node<'_>, &[u8]) -> Vec<Edit>,
+}
+
+pub(crate) const PASSES: &[Pass] = &[
+    Pass {
+        name: "turbofish",
+        rustdoc: true,
+        edits: turbofish,
+    },
+    Pass {
+        name: "const-generics",
+        rustdoc: true,
+        edits: const_generics,
+    },
+    Pass {
+        name: "flatten",
+        rustdoc: true,
+        edits: flatten,
+    },
+    Pass {
+        name: "paths",
+        rustdoc: true,
+        edits: paths,
+    },
+    Pass {
+        name: "literals",
+        rustdoc: true,
+        edits: literals,
+    },
+    // Docs are essential to rustdoc ICEs
+    Pass {
+        name: "docs",
+        rustdoc: false,
+        edits: docs,
+    },
+];
+
+fn parse(text: &[u8]) -> Option<Tree> {
+    let mut parser = tree_sitter::Parser::new();
+    parser.set_language(tree_sitter_rust::language()).ok()?;
+    parser.parse(text, None)
+}
+
+/// Every node in the tree, parents before children
+fn nodes(root: Node<'_>) -> Vec<Node<'_>> {
+    let mut nodes = Vec::new();
+    let mut cursor = root.walk();
+    'outer: loop {
+        nodes.push(cursor.node());
+        if cursor.goto_first_child() {
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                break 'outer;
+            }
+        }
+    }
+    nodes
+}
+
+fn delete(range: Range<usize>) -> Edit {
+    vec![(range, String::new())]
+}
+
+fn replace(node: Node<'_>, with: &str) -> Edit {
+    vec![(node.byte_range(), String::from(with))]
+}
+
+fn source<'a>(node: Node<'_>, text: &'a [u8]) -> &'a str {
+    std::str::from_utf8(&text[node.byte_range()]).unwrap_or_default()
+}
+
+/// Named children, other than comments
+fn children(node: Node<'_>) -> Vec<Node<'_>> {
+    let mut cursor = node.walk();
+    node.named_children(&mut cursor)
+        .filter(|c| !c.kind().ends_with("comment"))
+        .collect()
+}
+
+fn apply(text: &[u8], edit: &Edit) -> Vec<u8> {
+    let mut ranges = edit.iter().collect::<Vec<_>>();
+    ranges.sort_by_key(|(r, _)| std::cmp::Reverse((r.start, r.end)));
+    let mut new = text.to_vec();
+    for (range, with) in ranges {
+        new.splice(range.clone(), with.bytes());
+    }
+    new
+}
+
+/// Run a pass until none of its edits are kept
+pub(crate) fn run<C: Check>(pass: &Pass, chk: &C, mut text: Vec<u8>) -> io::Result<Vec<u8>> {
+    loop {
+        let Some(tree) = parse(&text) else {
+            return Ok(text);
+        };
+        let mut edits = (pass.edits)(tree.root_node(), &text);
+        edits.retain(|e| !e.is_empty());
+        // Back to front, so that kept edits don't move the rest
+        edits.sort_by_key(|e| std::cmp::Reverse(e.iter().map(|(r, _)| r.start).min()));
+        let mut kept = false;
+        let mut lowest = usize::MAX;
+        for edit in edits {
+            if edit.iter().any(|(r, _)| r.end > lowest) {
+                continue;
+            }
+            let candidate = apply(&text, &edit);
+            if candidate.len() >= text.len() || !chk.interesting(&candidate)? {
+                continue;
+            }
+            debug!("Pass {} made an edit", pass.name);
+            lowest = edit.iter().map(|(r, _)| r.start).min().unwrap_or(0);
+            text = candidate;
+            kept = true;
+        }
+        if !kept {
+            return Ok(text);
+        }
+    }
+}
+
+/// Machine-applicable suggestions for [`LINTS`] in rustc's JSON diagnostics
+fn lint_suggestions(diagnostics: &str, file: &str) -> Edit {
+    let mut edit: Edit = Vec::new();
+    let diagnostics = diagnostics
+        .lines()
+        .filter_map(|l| serde_json::from_str::<Value>(l).ok());
+    for diag in diagnostics {
+        let code = diag["code"]["code"].as_str().unwrap_or_default();
+        if !LINTS.contains(&code) {
+            continue;
+        }
+        let children = diag["children"].as_array().cloned().unwrap_or_default();
+        let spans = children
+            .iter()
+            .flat_map(|c| c["spans"].as_array().cloned().unwrap_or_default());
+        for span in spans {
+            let (Some(start), Some(end), Some(with)) = (
+                span["byte_start"].as_u64(),
+                span["byte_end"].as_u64(),
+                span["suggested_replacement"].as_str(),
+            ) else {
+                continue;
+            };
+            let range = start as usize..end as usize;
+            if span["suggestion_applicability"] == "MachineApplicable"
+                && span["file_name"] == file
+                && !edit
+                    .iter()
+                    .any(|(r, _)| r.start < range.end && range.start < r.end)
+            {
+                edit.push((range, String::from(with)));
+            }
+        }
+    }
+    edit
+}
+
+/// Apply rustc's fixes for unused imports and variables all at once, if the
+/// result is still interesting
+pub(crate) fn lints<C: Check>(
+    chk: &C,
+    command: &[String],
+    env: &[(String, String)],
+    text: Vec<u8>,
+) -> io::Result<Vec<u8>> {
+    let Some((cmd, args)) = command.split_first() else {
+        return Ok(text);
+    };
+    let file = temp::candidate(".rs", &text)?;
+    let out_dir = temp::dir("icemelter-lints-")?;
+    let path = file.path().to_string_lossy().into_owned();
+    let output = Command::new(cmd)
+        .args(check::with_file(args, &path))
+        .arg("--error-format=json")
+        .args(LINTS.iter().map(|l| format!("-W{l}")))
+        .arg("--out-dir")
+        .arg(out_dir.path())
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .output()?;
+    let edit = lint_suggestions(&String::from_utf8_lossy(&output.stderr), &path);
+    debug!("rustc suggested {} fixes", edit.len());
+    if edit.is_empty() || edit.iter().any(|(r, _)| r.end > text.len()) {
+        return Ok(text);
+    }
+    let candidate = apply(&text, &edit);
+    if candidate.len() < text.len() && chk.interesting(&candidate)? {
+        return Ok(candidate);
+    }
+    Ok(text)
+}
+
+/// Drop turbofish and other explicit generic arguments, or replace them with
+/// `_`
+fn turbofish(root: Node<'_>, _text: &[u8]) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    for node in nodes(root) {
+        match node.kind() {
+            // `f::<T>`, `Vec::<T>::new`, `Vec<T>`
+            "generic_function" | "generic_type_with_turbofish" | "generic_type" => {
+                let path = node
+                    .child_by_field_name("function")
+                    .or_else(|| node.child_by_field_name("type"));
+                if let Some(path) = path {
+                    edits.push(delete(path.end_byte()..node.end_byte()));
+                }
+            }
+            "type_arguments" => {
+                let mut cursor = node.walk();
+                for arg in node.named_children(&mut cursor) {
+                    let kind = arg.kind();
+                    if kind != "lifetime" && kind != "type_binding" && arg.byte_range().len() > 1 {
+                        edits.push(replace(arg, "_"));
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    edits
+}
+
+/// Replace const generic arguments, array lengths, and const values with small
+/// literals, and drop generic parameter defaults
+fn const_generics(root: Node<'_>, text: &[u8]) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    let mut shrink = |node: Node<'_>| {
+        for lit in ["0", "1"] {
+            if node.byte_range().len() > lit.len() {
+                edits.push(replace(node, lit));
+            }
+        }
+    };
+    for node in nodes(root) {
+        match node.kind() {
+            "type_arguments" => {
+                let mut cursor = node.walk();
+                for arg in node.named_children(&mut cursor) {
+                    if arg.kind() == "block" || arg.kind() == "integer_literal" {
+                        shrink(arg);
+                    }
+                }
+            }
+            "array_type" | "array_expression" => {
+                if let Some(len) = node.child_by_field_name("length") {
+                    shrink(len);
+                }
+            }
+            "const_item" => {
+                if let Some(value) = node.child_by_field_name("value") {
+                    shrink(value);
+                }
+            }
+            _ => (),
+        }
+    }
+    for node in nodes(root) {
+        let (name, default) = match node.kind() {
+            "optional_type_parameter" => (
+                node.child_by_field_name("name"),
+                node.child_by_field_name("default_type"),
+            ),
+            // `const N: usize = 1`
+            "const_parameter" => (node.child_by_field_name("type"), None),
+            _ => continue,
+        };
+        if let Some(name) = name {
+            let rest = &text[name.end_byte()..node.end_byte()];
+            if default.is_some() || rest.contains(&b'=') {
+                edits.push(delete(name.end_byte()..node.end_byte()));
+            }
+        }
+    }
+    edits
+}
+
+/// Flatten nested blocks, and inline trivial closures
+fn flatten(root: Node<'_>, text: &[u8]) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    for node in nodes(root) {
+        match node.kind() {
+            "block" => {
+                let [child] = children(node)[..] else {
+                    continue;
+                };
+                // `{ { ... } }` to `{ ... }`
+                let inner = match child.kind() {
+                    "block" => Some(child),
+                    "expression_statement" => children(child)
+                        .into_iter()
+                        .next()
+                        .filter(|c| c.kind() == "block"),
+                    _ => None,
+                };
+                if let Some(inner) = inner {
+                    edits.push(replace(node, source(inner, text)));
+                    continue;
+                }
+                // `{ expr }` to `expr`, where blocks aren't required
+                let required = node.parent().is_none_or(|p| {
+                    matches!(
+                        p.kind(),
+                        "function_item"
+                            | "if_expression"
+                            | "else_clause"
+                            | "while_expression"
+                            | "loop_expression"
+                            | "for_expression"
+                            | "unsafe_block"
+                            | "async_block"
+                            | "const_block"
+                            | "try_block"
+                            | "gen_block"
+                    )
+                });
+                if !required && child.kind() != "expression_statement" {
+                    edits.push(replace(node, source(child, text)));
+                }
+            }
+            "call_expression" => {
+                // `(|| expr)()` to `expr`
+                let (Some(function), Some(args)) = (
+                    node.child_by_field_name("function"),
+                    node.child_by_field_name("arguments"),
+                ) else {
+                    continue;
+                };
+                let closure = match function.kind() {
+                    "parenthesized_expression" => children(function).into_iter().next(),
+                    _ => None,
+                }
+                .filter(|c| c.kind() == "closure_expression");
+                let Some(closure) = closure else {
+                    continue;
+                };
+                let no_params = closure
+                    .child_by_field_name("parameters")
+                    .is_some_and(|p| children(p).is_empty());
+                if let (true, true, Some(body)) = (
+                    no_params,
+                    children(args).is_empty(),
+                    closure.child_by_field_name("body"),
+                ) {
+                    edits.push(replace(node, source(body, text)));
+                }
+            }
+            "closure_expression" => {
+                // `|x| f(x)` to `f`
+                let (Some(params), Some(body)) = (
+                    node.child_by_field_name("parameters"),
+                    node.child_by_field_name("body"),
+                ) else {
+                    continue;
+                };
+                let (Some(function), Some(args)) = (
+                    body.child_by_field_name("function"),
+                    body.child_by_field_name("arguments"),
+                ) else {
+                    continue;
+                };
+                let params = children(params)
+                    .into_iter()
+                    .map(|p| source(p, text))
+                    .collect::<Vec<_>>();
+                let args = children(args)
+                    .into_iter()
+                    .map(|a| source(a, text))
+                    .collect::<Vec<_>>();
+                if body.kind() == "call_expression"
+                    && params == args
+                    && matches!(function.kind(), "identifier" | "scoped_identifier")
+                {
+                    edits.push(replace(node, source(function, text)));
+                }
+            }
+            _ => (),
+        }
+    }
+    edits
+}
+
+/// Where to add a `use`: after inner attributes
+fn use_position(root: Node<'_>) -> usize {
+    children(root)
+        .into_iter()
+        .take_while(|c| c.kind() == "inner_attribute_item")
+        .last()
+        .map_or(0, |a| a.end_byte())
+}
+
+/// Shorten qualified paths, either by removing the qualification or by adding
+/// a `use`
+fn paths(root: Node<'_>, text: &[u8]) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    // Occurrences of each path
+    let mut paths = BTreeMap::<&str, Vec<Node<'_>>>::new();
+    for node in nodes(root) {
+        match node.kind() {
+            "scoped_identifier" | "scoped_type_identifier" => {
+                if node.child_by_field_name("path").is_none() {
+                    continue;
+                }
+                let mut ancestor = node.parent();
+                while let Some(a) = ancestor {
+                    if matches!(a.kind(), "use_declaration" | "attribute_item") {
+                        break;
+                    }
+                    ancestor = a.parent();
+                }
+                if ancestor.is_none() {
+                    paths.entry(source(node, text)).or_default().push(node);
+                }
+            }
+            // `<T as Trait>::f` to `T::f`
+            "bracketed_type" => {
+                let ty = children(node)
+                    .into_iter()
+                    .next()
+                    .filter(|c| c.kind() == "qualified_type")
+                    .and_then(|q| q.child_by_field_name("type"));
+                if let Some(ty) = ty {
+                    edits.push(replace(node, source(ty, text)));
+                }
+            }
+            _ => (),
+        }
+    }
+    let at = use_position(root);
+    for (path, occurrences) in paths {
+        let Some(name) = occurrences[0].child_by_field_name("name") else {
+            continue;
+        };
+        let name = source(name, text);
+        if name == "super" {
+            continue;
+        }
+        let unqualified = occurrences
+            .iter()
+            .map(|n| (n.byte_range(), String::from(name)))
+            .collect::<Edit>();
+        let mut with_use = unqualified.clone();
+        with_use.push((at..at, format!("use {path};\n")));
+        edits.push(unqualified);
+        edits.push(with_use);
+    }
+    edits
+}
+
+fn is_doc_comment(comment: &str) -> bool {
+    (comment.starts_with("///") && !comment.starts_with("////"))
+        || comment.starts_with("//!")
+        || (comment.starts_with("/**") && !comment.starts_with("/***") && comment != "/**/")
+        || comment.starts_with("/*!")
+}
+
+/// Remove doc comments and `#[doc = ...]` attributes
+fn docs(root: Node<'_>, text: &[u8]) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    for node in nodes(root) {
+        let is_doc = match node.kind() {
+            "line_comment" | "block_comment" => is_doc_comment(source(node, text)),
+            "attribute_item" | "inner_attribute_item" => children(node)
+                .into_iter()
+                .next()
+                .and_then(|a| children(a).into_iter().next())
+                .is_some_and(|path| source(path, text) == "doc"),
+            _ => false,
+        };
+        if is_doc {
+            edits.push(delete(node.byte_range()));
+        }
+    }
+    edits
+}
+
+/// The last position at most `at` in the contents of a string literal that
+/// isn't in the middle of an escape
+fn escape_boundary(content: &str, at: usize, raw: bool) -> usize {
+    let mut boundary = 0;
+    let mut chars = content.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if i > at {
+            break;
+        }
+        boundary = i;
+        if raw || c != '\\' {
+            continue;
+        }
+        match chars.next() {
+            Some((_, 'x')) => {
+                chars.next();
+                chars.next();
+            }
+            Some((_, 'u')) => while chars.next().is_some_and(|(_, c)| c != '}') {},
+            _ => (),
+        }
+    }
+    boundary
+}
+
+/// Shrink the contents of string literals, e.g., inlined `include_str!`s
+fn literals(root: Node<'_>, text: &[u8]) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    for node in nodes(root) {
+        let raw = match node.kind() {
+            "string_literal" => false,
+            "raw_string_literal" => true,
+            _ => continue,
+        };
+        let lit = source(node, text);
+        let (Some(open), Some(close)) = (lit.find('"'), lit.rfind('"')) else {
+            continue;
+        };
+        if close <= open + 1 {
+            continue;
+        }
+        let content = &lit[open + 1..close];
+        let start = node.start_byte() + open + 1;
+        edits.push(delete(start..start + content.len()));
+        let half = escape_boundary(content, content.len() / 2, raw);
+        if half > 0 {
+            edits.push(delete(start + half..start + content.len()));
+        }
+    }
+    edits
+}
@@ -0,0 +1,102 @@
+use std::fmt;
+
+use crate::backtrace::{frame_crate, Backtrace};
+
+/// The compiler phase in which an ICE occurred
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Phase {
+    Parse,
+    Resolve,
+    Typeck,
+    Borrowck,
+    Mir,
+    Codegen,
+    Llvm,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Phase::Parse => "parse",
+            Phase::Resolve => "resolve",
+            Phase::Typeck => "typeck",
+            Phase::Borrowck => "borrowck",
+            Phase::Mir => "MIR",
+            Phase::Codegen => "codegen",
+            Phase::Llvm => "LLVM",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+fn crate_phase(krate: &str) -> Option<Phase> {
+    match krate {
+        "rustc_parse" | "rustc_lexer" => Some(Phase::Parse),
+        "rustc_resolve" | "rustc_expand" | "rustc_ast_lowering" => Some(Phase::Resolve),
+        "rustc_hir_typeck" | "rustc_hir_analysis" | "rustc_trait_selection" | "rustc_infer" => {
+            Some(Phase::Typeck)
+        }
+        "rustc_borrowck" => Some(Phase::Borrowck),
+        "rustc_mir_build" | "rustc_mir_transform" | "rustc_mir_dataflow" | "rustc_const_eval" => {
+            Some(Phase::Mir)
+        }
+        "rustc_codegen_ssa" | "rustc_monomorphize" | "rustc_symbol_mangling" => {
+            Some(Phase::Codegen)
+        }
+        "rustc_codegen_llvm" | "rustc_llvm" => Some(Phase::Llvm),
+        _ => None,
+    }
+}
+
+fn query_phase(query: &str) -> Option<Phase> {
+    match query {
+        "resolver_for_lowering" | "expn_that_defined" => Some(Phase::Resolve),
+        "typeck" | "type_of" | "check_well_formed" | "evaluate_obligation" => Some(Phase::Typeck),
+        "mir_borrowck" => Some(Phase::Borrowck),
+        "mir_built"
+        | "optimized_mir"
+        | "mir_promoted"
+        | "mir_drops_elaborated_and_const_checked"
+        | "eval_to_const_value_raw"
+        | "eval_to_allocation_raw" => Some(Phase::Mir),
+        "collect_and_partition_mono_items" | "codegen_select_candidate" | "symbol_name" => {
+            Some(Phase::Codegen)
+        }
+        _ => None,
+    }
+}
+
+/// Phase suggested by rustc flags, used when stderr is inconclusive
+fn flag_phase(args: &[String]) -> Option<Phase> {
+    if args
+        .iter()
+        .any(|a| a.starts_with("-Zunpretty") || a == "-Zparse-crate-root-only")
+    {
+        Some(Phase::Parse)
+    } else if args.iter().any(|a| a.starts_with("-Cllvm-args")) {
+        Some(Phase::Llvm)
+    } else if args.iter().any(|a| a.starts_with("-Zmir-opt-level")) {
+        Some(Phase::Mir)
+    } else {
+        None
+    }
+}
+
+/// Classify an ICE by compiler phase, based on its backtrace and rustc's
+/// flags
+pub(crate) fn classify(bt: &Backtrace, args: &[String]) -> Option<Phase> {
+    if bt.is_llvm_error() {
+        return Some(Phase::Llvm);
+    }
+    bt.location_crates
+        .iter()
+        .find_map(|c| crate_phase(c))
+        .or_else(|| bt.queries.first().and_then(|q| query_phase(q)))
+        .or_else(|| {
+            bt.frames
+                .iter()
+                .filter_map(|f| frame_crate(f))
+                .find_map(crate_phase)
+        })
+        .or_else(|| flag_phase(args))
+}
@@ -0,0 +1,42 @@
+//! Getting code from Rust Playground share links
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use reqwest::Url;
+
+use crate::github;
+
+/// Playground links in Markdown, in order
+pub(crate) fn links(markdown: &str) -> Vec<String> {
+    let rx = Regex::new(r#"https?://play\.rust-lang\.org/?\?[^\s)\]>"'`]+"#).unwrap();
+    let mut links = Vec::new();
+    for m in rx.find_iter(markdown) {
+        let link = m.as_str().to_string();
+        if !links.contains(&link) {
+            links.push(link);
+        }
+    }
+    links
+}
+
+/// The code behind a share link, either in the link itself (`code=`) or in a
+/// gist (`gist=`)
+pub(crate) fn code(config: &github::Config, link: &str) -> Result<String> {
+    let url = Url::parse(link).with_context(|| format!("Invalid playground link {link}"))?;
+    let query = url.query_pairs().collect::<Vec<_>>();
+    if let Some((_, code)) = query.iter().find(|(k, _)| k == "code") {
+        return Ok(code.to_string());
+    }
+    let Some((_, id)) = query.iter().find(|(k, _)| k == "gist") else {
+        anyhow::bail!("No code or gist in playground link {link}");
+    };
+    let gist =
+        github::get_gist(config, id).with_context(|| format!("Failed to retrieve gist {id}"))?;
+    // The playground saves the code as `playground.rs`
+    gist.files
+        .iter()
+        .find(|(name, _)| *name == "playground.rs")
+        .or_else(|| gist.files.iter().find(|(name, _)| name.ends_with(".rs")))
+        .and_then(|(_, f)| f.content.clone())
+        .with_context(|| format!("No Rust code in gist {id}"))
+}
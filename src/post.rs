@@ -0,0 +1,60 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use anyhow::{Context, Result};
+use tracing::{info, warn};
+
+use crate::github;
+use crate::issue;
+
+/// Check that a comment could be posted to the issue, without posting it
+pub(crate) fn dry_run(repo: &str, issue_number: usize) -> Result<()> {
+    let reference = issue::reference(repo, issue_number);
+    let config = github::Config::load()?;
+    let user = github::get_user(&config).context("Invalid GitHub token")?;
+    info!("Authenticated as {}", user.login);
+    let issue = github::get_issue(&config, repo, issue_number)
+        .with_context(|| format!("Failed to retrieve issue {reference}"))?;
+    if issue.locked {
+        anyhow::bail!("Issue {reference} is locked, can't comment on it");
+    }
+    let rate = github::get_rate_limit(&config)
+        .context("Failed to retrieve rate limit")?
+        .rate;
+    info!(
+        "{}/{} GitHub API requests remaining",
+        rate.remaining, rate.limit
+    );
+    if rate.remaining == 0 {
+        anyhow::bail!("GitHub API rate limit exceeded");
+    }
+    info!("Dry run: would post the comment to {}", reference);
+    Ok(())
+}
+
+/// Show `body` and ask `question`
+pub(crate) fn confirm(body: &str, question: &str) -> Result<bool> {
+    println!("{body}");
+    println!();
+    if !io::stdin().is_terminal() {
+        warn!("Not proceeding without confirmation, pass --yes");
+        return Ok(false);
+    }
+    print!("{question} [y/N] ");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Post a comment to the issue, after confirmation (unless `yes`)
+pub(crate) fn post(repo: &str, issue_number: usize, body: &str, yes: bool) -> Result<()> {
+    let question = format!("Post this comment to {repo}#{issue_number}?");
+    if !yes && !confirm(body, &question)? {
+        info!("Not posting the comment");
+        return Ok(());
+    }
+    let url = github::post_comment(&github::Config::load()?, repo, issue_number, body)
+        .with_context(|| format!("Failed to comment on issue {repo}#{issue_number}"))?;
+    info!("Posted comment: {}", url);
+    Ok(())
+}
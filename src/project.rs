@@ -0,0 +1,342 @@
+//! A Cargo project that reproduces the ICE, see `--emit-project`
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use tracing::warn;
+
+use crate::atomic;
+use crate::bisect_local::without_sysroot;
+use crate::check::{self, Aux};
+use crate::flatten;
+
+const LIB_TYPES: &[&str] = &["lib", "rlib", "dylib", "cdylib", "staticlib", "proc-macro"];
+
+/// What the rustc command line means for Cargo
+#[derive(Default)]
+struct Settings {
+    name: Option<String>,
+    edition: Option<String>,
+    crate_type: Option<String>,
+    target: Option<String>,
+    test: bool,
+    metadata_only: bool,
+    rustflags: Vec<String>,
+}
+
+fn settings(args: &[String]) -> Settings {
+    let mut settings = Settings::default();
+    let args = without_sysroot(args);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((f, v)) if f.starts_with("--") => (f, Some(v.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = || inline.clone().or_else(|| args.next().cloned());
+        match flag {
+            "--crate-name" => settings.name = value(),
+            "--edition" => settings.edition = value(),
+            "--crate-type" => settings.crate_type = value(),
+            "--target" => settings.target = value(),
+            "--emit" => {
+                settings.metadata_only =
+                    value().is_some_and(|v| v.contains("metadata") && !v.contains("link"));
+            }
+            "--out-dir" | "-o" => {
+                value();
+            }
+            "--test" => settings.test = true,
+            _ if arg.starts_with('+') || check::is_marker(arg) => {}
+            _ => settings.rustflags.push(arg.clone()),
+        }
+    }
+    settings
+}
+
+/// The day after a date, for nightly dates
+fn next_day(y: u32, m: u32, d: u32) -> (u32, u32, u32) {
+    let leap = (y % 4 == 0 && y % 100 != 0) || y % 400 == 0;
+    let days = match m {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    if d < days {
+        (y, m, d + 1)
+    } else if m < 12 {
+        (y, m + 1, 1)
+    } else {
+        (y + 1, 1, 1)
+    }
+}
+
+/// The value of a `key: value` line in `rustc -vV` output
+fn version_field<'a>(version: &'a str, key: &str) -> Option<&'a str> {
+    version
+        .lines()
+        .find_map(|l| l.strip_prefix(key)?.strip_prefix(':'))
+        .map(str::trim)
+}
+
+/// A toolchain that stays the same over time, e.g., `nightly-2024-01-02`
+/// instead of `nightly`, based on `rustc -vV`
+pub(crate) fn pinned(toolchain: Option<&str>, rustc_version: &str) -> Option<String> {
+    let release = version_field(rustc_version, "release")?;
+    if !release.contains("nightly") && !release.contains("beta") {
+        return Some(release.to_string());
+    }
+    if let Some(tc) = toolchain {
+        // Already dated
+        if tc.split('-').nth(1).is_some_and(|y| y.len() == 4) {
+            return Some(tc.to_string());
+        }
+    }
+    let date = version_field(rustc_version, "commit-date")?;
+    let mut parts = date.splitn(3, '-').map(|p| p.parse::<u32>().ok());
+    let (Some(Some(y)), Some(Some(m)), Some(Some(d))) = (parts.next(), parts.next(), parts.next())
+    else {
+        return toolchain.map(String::from);
+    };
+    // Nightlies are built from the previous day's commits
+    let (y, m, d) = next_day(y, m, d);
+    let channel = if release.contains("beta") {
+        "beta"
+    } else {
+        "nightly"
+    };
+    Some(format!("{channel}-{y:04}-{m:02}-{d:02}"))
+}
+
+fn toml_list(items: &[String]) -> String {
+    let items = items
+        .iter()
+        .map(|i| format!("{i:?}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("[{items}]")
+}
+
+fn write(path: &Path, content: impl AsRef<[u8]>) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    atomic::write(path, content).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Write a Cargo project to `dir` that builds `code` like `args` (rustc's
+/// arguments, without the program), returning the command that reproduces
+/// the ICE
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn emit(
+    dir: &Path,
+    args: &[String],
+    toolchain: Option<&str>,
+    rustc_version: &str,
+    rustdoc: bool,
+    code: &[u8],
+    aux: &[Aux],
+    force: bool,
+) -> Result<String> {
+    if dir.join("Cargo.toml").exists() && !force {
+        bail!(
+            "{} already has a Cargo.toml, pass --force to overwrite it",
+            dir.display()
+        );
+    }
+    let settings = settings(args);
+    let name = settings
+        .name
+        .clone()
+        .unwrap_or_else(|| String::from("icemelter_repro"));
+    let edition = settings.edition.as_deref().unwrap_or("2015");
+    let crate_type = settings.crate_type.as_deref().unwrap_or("bin");
+    let is_lib = LIB_TYPES.contains(&crate_type);
+
+    let mut manifest = format!(
+        "[package]\nname = {name:?}\nversion = \"0.1.0\"\nedition = {edition:?}\npublish = false\n"
+    );
+    let src = if is_lib {
+        manifest.push_str("\n[lib]\npath = \"src/lib.rs\"\n");
+        if crate_type == "proc-macro" {
+            manifest.push_str("proc-macro = true\n");
+        } else if crate_type != "lib" {
+            manifest.push_str(&format!("crate-type = [{crate_type:?}]\n"));
+        }
+        "src/lib.rs"
+    } else {
+        "src/main.rs"
+    };
+    if !aux.is_empty() {
+        manifest.push_str("\n[dependencies]\n");
+    }
+    for a in aux {
+        manifest.push_str(&format!("{} = {{ path = \"aux/{}\" }}\n", a.name, a.name));
+        let mut aux_manifest = format!(
+            "[package]\nname = {:?}\nversion = \"0.1.0\"\nedition = {edition:?}\npublish = false\n\n[lib]\npath = \"lib.rs\"\n",
+            a.name
+        );
+        if a.proc_macro {
+            aux_manifest.push_str("proc-macro = true\n");
+        }
+        let aux_dir = dir.join("aux").join(&a.name);
+        write(&aux_dir.join("Cargo.toml"), aux_manifest)?;
+        write(&aux_dir.join("lib.rs"), &a.code)?;
+    }
+    // Keep it out of any enclosing workspace
+    manifest.push_str("\n[workspace]\n");
+    write(&dir.join("Cargo.toml"), manifest)?;
+
+    let main = dir.join(src);
+    write(&main, code)?;
+    let src_dir = main.parent().unwrap_or(dir);
+    for stub in flatten::missing_includes(&String::from_utf8_lossy(code))? {
+        write(&src_dir.join(stub), "")?;
+    }
+
+    for flag in settings
+        .rustflags
+        .iter()
+        .filter(|f| f.starts_with("--extern") || f.starts_with("-L"))
+    {
+        warn!(
+            "The project uses {}, it may not work on other machines",
+            flag
+        );
+    }
+    let mut config = String::new();
+    if let Some(target) = &settings.target {
+        config.push_str(&format!("[build]\ntarget = {target:?}\n"));
+    }
+    if !settings.rustflags.is_empty() {
+        if config.is_empty() {
+            config.push_str("[build]\n");
+        }
+        let key = if rustdoc { "rustdocflags" } else { "rustflags" };
+        config.push_str(&format!("{key} = {}\n", toml_list(&settings.rustflags)));
+    }
+    if !config.is_empty() {
+        write(&dir.join(".cargo").join("config.toml"), config)?;
+    }
+
+    if let Some(channel) = pinned(toolchain, rustc_version) {
+        let mut toolchain_file = format!("[toolchain]\nchannel = {channel:?}\n");
+        if let Some(target) = &settings.target {
+            toolchain_file.push_str(&format!("targets = [{target:?}]\n"));
+        }
+        write(&dir.join("rust-toolchain.toml"), toolchain_file)?;
+    }
+
+    let command = if rustdoc {
+        "cargo doc"
+    } else if settings.test {
+        "cargo test --no-run"
+    } else if settings.metadata_only {
+        "cargo check"
+    } else {
+        "cargo build"
+    };
+    write(
+        &dir.join("README.md"),
+        format!(
+            "Reproduces an internal compiler error, found with [Icemelter](https://github.com/langston-barrett/icemelter).\n\n```sh\n{command}\n```\n"
+        ),
+    )?;
+    Ok(command.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    fn version(release: &str, date: &str) -> String {
+        format!(
+            "rustc {release} (0123456789 {date})\nbinary: rustc\ncommit-hash: 0123456789\ncommit-date: {date}\nhost: x86_64-unknown-linux-gnu\nrelease: {release}\nLLVM version: 18.1.4\n"
+        )
+    }
+
+    #[test]
+    fn next_day_leap_years() {
+        assert_eq!(next_day(2024, 2, 28), (2024, 2, 29));
+        assert_eq!(next_day(2024, 2, 29), (2024, 3, 1));
+        assert_eq!(next_day(2023, 2, 28), (2023, 3, 1));
+        assert_eq!(next_day(1900, 2, 28), (1900, 3, 1));
+        assert_eq!(next_day(2000, 2, 28), (2000, 2, 29));
+    }
+
+    #[test]
+    fn next_day_month_and_year() {
+        assert_eq!(next_day(2023, 4, 30), (2023, 5, 1));
+        assert_eq!(next_day(2023, 5, 30), (2023, 5, 31));
+        assert_eq!(next_day(2023, 12, 31), (2024, 1, 1));
+    }
+
+    #[test]
+    fn pinned_nightly() {
+        let v = version("1.80.0-nightly", "2024-02-28");
+        assert_eq!(
+            pinned(Some("nightly"), &v).as_deref(),
+            Some("nightly-2024-02-29")
+        );
+        assert_eq!(pinned(None, &v).as_deref(), Some("nightly-2024-02-29"));
+        assert_eq!(
+            pinned(Some("nightly-2024-01-02"), &v).as_deref(),
+            Some("nightly-2024-01-02")
+        );
+    }
+
+    #[test]
+    fn pinned_beta_and_stable() {
+        let v = version("1.80.0-beta.3", "2024-12-31");
+        assert_eq!(pinned(Some("beta"), &v).as_deref(), Some("beta-2025-01-01"));
+        let v = version("1.79.0", "2024-06-10");
+        assert_eq!(pinned(Some("stable"), &v).as_deref(), Some("1.79.0"));
+    }
+
+    #[test]
+    fn settings_from_rustc_args() {
+        let s = settings(&strings(&[
+            "--crate-name",
+            "foo",
+            "--edition=2021",
+            "--crate-type",
+            "lib",
+            "--target=x86_64-unknown-linux-gnu",
+            "--sysroot",
+            "/sysroot",
+            "-Copt-level=3",
+            "@@",
+            "--emit=metadata",
+            "-o",
+            "out",
+            "+nightly",
+            "-Zmir-opt-level=4",
+        ]));
+        assert_eq!(s.name.as_deref(), Some("foo"));
+        assert_eq!(s.edition.as_deref(), Some("2021"));
+        assert_eq!(s.crate_type.as_deref(), Some("lib"));
+        assert_eq!(s.target.as_deref(), Some("x86_64-unknown-linux-gnu"));
+        assert!(s.metadata_only);
+        assert!(!s.test);
+        assert_eq!(
+            s.rustflags,
+            strings(&["-Copt-level=3", "-Zmir-opt-level=4"])
+        );
+    }
+
+    #[test]
+    fn settings_test_and_link() {
+        let s = settings(&strings(&["--test", "--emit", "metadata,link", "x.rs"]));
+        assert!(s.test);
+        assert!(!s.metadata_only);
+        assert_eq!(s.name, None);
+        assert_eq!(s.rustflags, strings(&["x.rs"]));
+    }
+}
@@ -0,0 +1,82 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::atomic;
+use crate::history::Record;
+
+const SUMMARY: &str = "summary.json";
+
+/// Directory for the next run on an issue, `issue-N/run-K`, and the directory
+/// of the previous finished run (if any)
+pub(crate) fn next_run_dir(issue: usize) -> Result<(PathBuf, Option<PathBuf>)> {
+    let issue_dir = PathBuf::from(format!("issue-{issue}"));
+    let mut last = 0;
+    let mut previous = None;
+    if issue_dir.is_dir() {
+        for entry in fs::read_dir(&issue_dir)
+            .with_context(|| format!("Failed to read directory {}", issue_dir.display()))?
+        {
+            let name = entry?.file_name();
+            if let Some(n) = name
+                .to_str()
+                .and_then(|n| n.strip_prefix("run-"))
+                .and_then(|n| n.parse::<usize>().ok())
+            {
+                last = last.max(n);
+                let dir = issue_dir.join(name);
+                if dir.join(SUMMARY).exists() && previous.as_ref().is_none_or(|(p, _)| *p < n) {
+                    previous = Some((n, dir));
+                }
+            }
+        }
+    }
+    let dir = issue_dir.join(format!("run-{}", last + 1));
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    Ok((dir, previous.map(|(_, p)| p)))
+}
+
+pub(crate) fn write_summary(dir: &Path, record: &Record) -> Result<()> {
+    let path = dir.join(SUMMARY);
+    let json = serde_json::to_string_pretty(record).context("Failed to serialize summary")?;
+    atomic::write(&path, json).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Describe what changed since the previous run
+pub(crate) fn changes(previous: &Path, record: &Record) -> Result<Vec<String>> {
+    let path = previous.join(SUMMARY);
+    let text =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let prev: Record =
+        serde_json::from_str(&text).with_context(|| format!("Malformed {}", path.display()))?;
+    let mut changes = Vec::new();
+    if prev.reduced_size != record.reduced_size {
+        changes.push(format!(
+            "Reduced size: {} -> {} bytes",
+            prev.reduced_size, record.reduced_size
+        ));
+    }
+    if prev.original_size != record.original_size {
+        changes.push(format!(
+            "Original size: {} -> {} bytes",
+            prev.original_size, record.original_size
+        ));
+    }
+    if prev.signature != record.signature {
+        changes.push(format!(
+            "Signature: {} -> {}",
+            prev.signature.as_deref().unwrap_or("(none)"),
+            record.signature.as_deref().unwrap_or("(none)")
+        ));
+    }
+    if prev.bisect_pr != record.bisect_pr && record.bisect_pr.is_some() {
+        changes.push(format!(
+            "Bisected to: {} -> {}",
+            prev.bisect_pr.as_deref().unwrap_or("(none)"),
+            record.bisect_pr.as_deref().unwrap_or("(none)")
+        ));
+    }
+    Ok(changes)
+}
@@ -0,0 +1,115 @@
+//! Removing private information (home directories, usernames, and machine
+//! names) from reports before they're written or posted
+//!
+//! Only compiler output gets names replaced, elsewhere just paths are.
+
+use std::borrow::Cow;
+use std::process::Command;
+
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+
+/// Not identifying, and replacing them would mangle unrelated text
+const COMMON_NAMES: &[&str] = &["root", "user", "runner", "ubuntu", "localhost"];
+
+struct Sanitizer {
+    home: Option<Regex>,
+    /// Other people's home directories, e.g., in paths from another machine
+    homes: Regex,
+    /// Usernames and machine names, with their replacements
+    names: Vec<(Regex, &'static str)>,
+}
+
+fn machine_name() -> Option<String> {
+    let out = Command::new("uname").arg("-n").output().ok()?;
+    let name = String::from_utf8_lossy(&out.stdout).trim().to_string();
+    // Just the host, not the domain
+    name.split('.').next().map(String::from)
+}
+
+fn word(name: &str) -> Option<Regex> {
+    if name.len() < 3 || COMMON_NAMES.contains(&name) {
+        return None;
+    }
+    Regex::new(&format!(r"\b{}\b", regex::escape(name))).ok()
+}
+
+static SANITIZER: Lazy<Sanitizer> = Lazy::new(|| {
+    let user = ["USER", "LOGNAME", "USERNAME"]
+        .iter()
+        .filter_map(|v| std::env::var(v).ok())
+        .find(|v| !v.is_empty());
+    let mut names = Vec::new();
+    if let Some(re) = user.as_deref().and_then(word) {
+        names.push((re, "user"));
+    }
+    if let Some(re) = machine_name().as_deref().and_then(word) {
+        names.push((re, "hostname"));
+    }
+    Sanitizer {
+        home: std::env::var("HOME")
+            .ok()
+            .map(|h| h.trim_end_matches('/').to_string())
+            .filter(|h| h.len() > 1)
+            .and_then(|h| Regex::new(&format!(r"{}\b", regex::escape(&h))).ok()),
+        homes: Regex::new(r"(/home/|/Users/|[A-Za-z]:\\Users\\)[A-Za-z0-9._-]+").unwrap(),
+        names,
+    }
+});
+
+/// Replace the home directory with `~`, and other home directories with
+/// `/home/user` (or similar)
+///
+/// Unlike [`sanitize`], names are kept, they could be ordinary words (e.g., a
+/// machine named `rust`).
+pub(crate) fn paths(text: &str) -> String {
+    let s = &*SANITIZER;
+    let text = match &s.home {
+        Some(home) => home.replace_all(text, "~"),
+        None => Cow::Borrowed(text),
+    };
+    s.homes.replace_all(&text, "${1}user").into_owned()
+}
+
+/// Sanitize [`paths`] in string literals (e.g., in `include!`s), leaving the
+/// rest of the code alone
+pub(crate) fn code(code: &str) -> String {
+    let literal = Regex::new(r#""(?:[^"\\]|\\.)*""#).unwrap();
+    literal
+        .replace_all(code, |c: &Captures<'_>| paths(&c[0]))
+        .into_owned()
+}
+
+/// [`paths`], plus the username and machine name, for compiler output
+pub(crate) fn sanitize(text: &str) -> String {
+    let s = &*SANITIZER;
+    let mut text = paths(text);
+    for (re, replacement) in &s.names {
+        text = re.replace_all(&text, *replacement).into_owned();
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_homes() {
+        assert_eq!(paths(r#""/home/e.x-ample""#), r#""/home/user""#);
+        assert_eq!(paths("(/home/e.x-ample)"), "(/home/user)");
+        assert_eq!(
+            paths("`/Users/e.x-ample/src/lib.rs`"),
+            "`/Users/user/src/lib.rs`"
+        );
+        assert_eq!(paths(r"'C:\Users\e.x-ample\src'"), r"'C:\Users\user\src'");
+    }
+
+    #[test]
+    fn code_literals() {
+        assert_eq!(
+            code(r#"const P: &str = "/home/e.x-ample"; include!("/home/e.x-ample/x.rs");"#),
+            r#"const P: &str = "/home/user"; include!("/home/user/x.rs");"#
+        );
+    }
+}
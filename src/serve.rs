@@ -0,0 +1,220 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use tracing::{debug, info};
+
+/// A finished reduction
+struct Finished {
+    input: String,
+    /// `None` if it failed
+    output: Option<PathBuf>,
+    report: Option<PathBuf>,
+}
+
+/// An in-progress reduction
+struct Current {
+    input: String,
+    step: usize,
+    steps: usize,
+    name: String,
+    started: Instant,
+}
+
+#[derive(Default)]
+struct State {
+    current: Option<Current>,
+    queue: Vec<String>,
+    finished: Vec<Finished>,
+}
+
+/// Progress shown on the web dashboard, see `--serve`
+///
+/// Updates are no-ops if the dashboard is disabled.
+#[derive(Clone, Default)]
+pub(crate) struct Dashboard {
+    state: Option<Arc<Mutex<State>>>,
+}
+
+impl Dashboard {
+    /// Serve the dashboard on `addr` (e.g., `127.0.0.1:8080`) in the background
+    pub(crate) fn serve(addr: &str) -> Result<Self> {
+        let listener =
+            TcpListener::bind(addr).with_context(|| format!("Failed to listen on {}", addr))?;
+        info!("Serving dashboard at http://{}", listener.local_addr()?);
+        let state = Arc::new(Mutex::new(State::default()));
+        let server_state = Arc::clone(&state);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Err(e) = handle(stream, &server_state) {
+                    debug!("Dashboard request failed: {}", e);
+                }
+            }
+        });
+        Ok(Dashboard { state: Some(state) })
+    }
+
+    fn update(&self, f: impl FnOnce(&mut State)) {
+        if let Some(state) = &self.state {
+            if let Ok(mut s) = state.lock() {
+                f(&mut s);
+            }
+        }
+    }
+
+    /// Inputs that will be melted, e.g., the rest of a batch
+    pub(crate) fn queue(&self, input: &str) {
+        let input = input.to_string();
+        self.update(|s| s.queue.push(input));
+    }
+
+    pub(crate) fn start(&self, input: &str) {
+        let input = input.to_string();
+        self.update(|s| {
+            s.queue.retain(|i| *i != input);
+            s.current = Some(Current {
+                input,
+                step: 0,
+                steps: 0,
+                name: String::new(),
+                started: Instant::now(),
+            });
+        });
+    }
+
+    pub(crate) fn step(&self, step: usize, steps: usize, name: &str) {
+        self.update(|s| {
+            if let Some(c) = &mut s.current {
+                c.step = step;
+                c.steps = steps;
+                c.name = name.to_string();
+            }
+        });
+    }
+
+    pub(crate) fn finish(&self, output: Option<PathBuf>, report: Option<PathBuf>) {
+        self.update(|s| {
+            if let Some(c) = s.current.take() {
+                s.finished.push(Finished {
+                    input: c.input,
+                    output,
+                    report,
+                });
+            }
+        });
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn page(state: &State) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\
+         <meta http-equiv=\"refresh\" content=\"5\"><title>icemelter</title></head><body>\n",
+    );
+    html.push_str("<h1>icemelter</h1>\n<h2>In progress</h2>\n");
+    match &state.current {
+        // Steps of runs in other processes (e.g., within a batch) aren't known
+        Some(c) if c.steps == 0 => html.push_str(&format!(
+            "<p>{}: running for {}s</p>\n",
+            escape(&c.input),
+            c.started.elapsed().as_secs()
+        )),
+        Some(c) => html.push_str(&format!(
+            "<p>{}: step {}/{} ({}), running for {}s</p>\n",
+            escape(&c.input),
+            c.step,
+            c.steps,
+            escape(&c.name),
+            c.started.elapsed().as_secs()
+        )),
+        None => html.push_str("<p>Nothing</p>\n"),
+    }
+    html.push_str(&format!("<h2>Queue ({})</h2>\n<ul>\n", state.queue.len()));
+    for input in &state.queue {
+        html.push_str(&format!("<li>{}</li>\n", escape(input)));
+    }
+    html.push_str("</ul>\n");
+    html.push_str(&format!(
+        "<h2>Finished ({})</h2>\n<ul>\n",
+        state.finished.len()
+    ));
+    for (i, f) in state.finished.iter().enumerate() {
+        let Some(output) = &f.output else {
+            html.push_str(&format!("<li>{}: failed</li>\n", escape(&f.input)));
+            continue;
+        };
+        html.push_str(&format!(
+            "<li>{}: <a href=\"/artifacts/{i}/output\">{}</a>",
+            escape(&f.input),
+            escape(&output.display().to_string())
+        ));
+        if let Some(report) = &f.report {
+            html.push_str(&format!(
+                ", <a href=\"/artifacts/{i}/report\">{}</a>",
+                escape(&report.display().to_string())
+            ));
+        }
+        html.push_str("</li>\n");
+    }
+    html.push_str("</ul>\n</body></html>\n");
+    html
+}
+
+/// Only files produced by a finished reduction are served
+fn artifact(state: &State, path: &str) -> Option<PathBuf> {
+    let rest = path.strip_prefix("/artifacts/")?;
+    let (index, kind) = rest.split_once('/')?;
+    let finished = state.finished.get(index.parse::<usize>().ok()?)?;
+    match kind {
+        "output" => finished.output.clone(),
+        "report" => finished.report.clone(),
+        _ => None,
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    Ok(())
+}
+
+fn handle(mut stream: TcpStream, state: &Mutex<State>) -> Result<()> {
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+    debug!("Dashboard request for {}", path);
+    let state = state
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Dashboard state poisoned"))?;
+    if path == "/" {
+        let body = page(&state);
+        drop(state);
+        return respond(&mut stream, "200 OK", "text/html", body.as_bytes());
+    }
+    let file = artifact(&state, path);
+    drop(state);
+    match file.and_then(|f| fs::read(f).ok()) {
+        Some(contents) => respond(
+            &mut stream,
+            "200 OK",
+            "text/plain; charset=utf-8",
+            &contents,
+        ),
+        None => respond(&mut stream, "404 Not Found", "text/plain", b"Not found"),
+    }
+}
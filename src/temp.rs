@@ -0,0 +1,256 @@
+//! Temporary files, which are kept with `--keep-temps`
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use once_cell::sync::{Lazy, OnceCell};
+use tempfile::{Builder, NamedTempFile, TempDir};
+
+/// Where to keep temporary files, see [`keep_in`]
+static KEEP: OnceCell<PathBuf> = OnceCell::new();
+
+/// Name for candidate files, see [`name_candidates`]
+static NAME: OnceCell<String> = OnceCell::new();
+
+/// Empty files to put next to candidates, see [`stub_files`]
+static STUBS: OnceCell<Vec<PathBuf>> = OnceCell::new();
+
+/// Cargo project to put candidates in, see [`in_project`]
+static PROJECT: OnceCell<Project> = OnceCell::new();
+
+/// Cargo target directories not in use by any check, see [`Target`]
+static TARGETS: Lazy<Mutex<Vec<TempDir>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+struct Project {
+    /// Root of the Cargo project
+    root: PathBuf,
+    /// The file in the project that candidates replace, relative to `root`
+    file: PathBuf,
+    /// Root of the workspace containing the project, which gets copied
+    workspace: PathBuf,
+    /// The workspace's `Cargo.lock` when Icemelter started
+    lock: Vec<u8>,
+}
+
+/// Create temporary files in `dir`, and don't delete them
+pub(crate) fn keep_in(dir: PathBuf) {
+    let _ = KEEP.set(dir);
+}
+
+/// The directory temporary files are kept in, if any
+pub(crate) fn kept() -> Option<&'static Path> {
+    KEEP.get().map(PathBuf::as_path)
+}
+
+/// Name candidate files `name` (plus their suffix) instead of giving them
+/// random names, e.g., so that the crate name is stable
+pub(crate) fn name_candidates(name: String) {
+    let _ = NAME.set(name);
+}
+
+/// Create empty files at these paths (relative to the candidate) next to each
+/// candidate, for `include_str!`s and the like of files that are missing
+pub(crate) fn stub_files(paths: Vec<PathBuf>) {
+    let _ = STUBS.set(paths);
+}
+
+/// Put each candidate at `file` (relative to `root`) in a copy of the Cargo
+/// project at `root`, or rather of its `workspace`. Copies go next to the
+/// workspace, so that relative paths to other crates still work, and get the
+/// lockfile `lock`, so that dependencies don't change during the run.
+///
+/// Each check builds in a Cargo target directory of its own, which later
+/// checks reuse so that dependencies are only built once per concurrent check.
+/// They're deleted when the returned [`Targets`] is dropped.
+pub(crate) fn in_project(
+    root: PathBuf,
+    file: PathBuf,
+    workspace: PathBuf,
+    lock: Vec<u8>,
+) -> Targets {
+    let _ = PROJECT.set(Project {
+        root,
+        file,
+        workspace,
+        lock,
+    });
+    Targets(())
+}
+
+/// The Cargo project candidates go into, if any
+pub(crate) fn project() -> Option<&'static Path> {
+    PROJECT.get().map(|p| p.root.as_path())
+}
+
+/// Deletes the Cargo target directories when dropped, see [`in_project`]
+pub(crate) struct Targets(());
+
+impl Drop for Targets {
+    fn drop(&mut self) {
+        if let Ok(mut free) = TARGETS.lock() {
+            free.clear();
+        }
+    }
+}
+
+/// A Cargo target directory used by one check at a time, which is returned for
+/// reuse when dropped
+pub(crate) struct Target(Option<TempDir>);
+
+impl Target {
+    fn take() -> io::Result<Self> {
+        let free = TARGETS.lock().ok().and_then(|mut free| free.pop());
+        match free {
+            Some(dir) => Ok(Target(Some(dir))),
+            None => Ok(Target(Some(dir("icemelter-target-")?))),
+        }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        self.0.as_ref().map(TempDir::path).unwrap_or(Path::new(""))
+    }
+}
+
+impl Drop for Target {
+    fn drop(&mut self) {
+        if let (Some(dir), Ok(mut free)) = (self.0.take(), TARGETS.lock()) {
+            free.push(dir);
+        }
+    }
+}
+
+/// Copy a Cargo workspace next to itself, so that relative paths to other
+/// crates still work
+pub(crate) fn copy_workspace(workspace: &Path) -> io::Result<TempDir> {
+    let parent = workspace.parent().unwrap_or(workspace);
+    let dir = builder(".icemelter-", "").tempdir_in(parent)?;
+    copy_project(workspace, dir.path())?;
+    Ok(dir)
+}
+
+/// Copy a Cargo project, without build outputs or version control
+fn copy_project(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name == "target" || name == ".git" {
+            continue;
+        }
+        let dest = to.join(&name);
+        if entry.file_type()?.is_dir() {
+            copy_project(&entry.path(), &dest)?;
+        } else {
+            fs::copy(entry.path(), dest)?;
+        }
+    }
+    Ok(())
+}
+
+/// A temporary file containing a candidate, deleted when dropped (unless kept)
+pub(crate) enum Candidate {
+    Random(NamedTempFile),
+    /// With [`name_candidates`] or [`stub_files`], a file in its own
+    /// directory
+    InDir {
+        _dir: TempDir,
+        path: PathBuf,
+    },
+    /// With [`in_project`], a file in a copy of the workspace
+    InProject {
+        _dir: TempDir,
+        project: PathBuf,
+        path: PathBuf,
+        target: Target,
+    },
+}
+
+impl Candidate {
+    pub(crate) fn path(&self) -> &Path {
+        match self {
+            Candidate::Random(f) => f.path(),
+            Candidate::InDir { path, .. } | Candidate::InProject { path, .. } => path,
+        }
+    }
+
+    /// The copy of the Cargo project, with [`in_project`]
+    pub(crate) fn project(&self) -> Option<&Path> {
+        match self {
+            Candidate::InProject { project, .. } => Some(project),
+            _ => None,
+        }
+    }
+
+    /// The Cargo target directory to build the project in, with
+    /// [`in_project`]
+    pub(crate) fn target(&self) -> Option<&Path> {
+        match self {
+            Candidate::InProject { target, .. } => Some(target.path()),
+            _ => None,
+        }
+    }
+}
+
+/// Write a candidate to a new temporary file with this suffix (ignored with
+/// [`in_project`])
+pub(crate) fn candidate(suffix: &str, content: &[u8]) -> io::Result<Candidate> {
+    if let Some(p) = PROJECT.get() {
+        let dir = copy_workspace(&p.workspace)?;
+        fs::write(dir.path().join("Cargo.lock"), &p.lock)?;
+        let project = dir
+            .path()
+            .join(p.root.strip_prefix(&p.workspace).unwrap_or(Path::new("")));
+        let path = project.join(&p.file);
+        fs::write(&path, content)?;
+        return Ok(Candidate::InProject {
+            _dir: dir,
+            project,
+            path,
+            target: Target::take()?,
+        });
+    }
+    let stubs = STUBS.get().map(Vec::as_slice).unwrap_or_default();
+    if NAME.get().is_none() && stubs.is_empty() {
+        let mut f = file("icemelter-", suffix)?;
+        f.write_all(content)?;
+        f.flush()?;
+        return Ok(Candidate::Random(f));
+    }
+    let name = NAME.get().map(String::as_str).unwrap_or("icemelter");
+    let dir = dir("icemelter-")?;
+    let path = dir.path().join(format!("{name}{suffix}"));
+    fs::write(&path, content)?;
+    for stub in stubs {
+        let stub = dir.path().join(stub);
+        if let Some(parent) = stub.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(stub, "")?;
+    }
+    Ok(Candidate::InDir { _dir: dir, path })
+}
+
+fn builder<'a>(prefix: &'a str, suffix: &'a str) -> Builder<'a, 'a> {
+    let mut builder = Builder::new();
+    builder
+        .prefix(prefix)
+        .suffix(suffix)
+        .keep(KEEP.get().is_some());
+    builder
+}
+
+pub(crate) fn file(prefix: &str, suffix: &str) -> io::Result<NamedTempFile> {
+    match KEEP.get() {
+        Some(dir) => builder(prefix, suffix).tempfile_in(dir),
+        None => builder(prefix, suffix).tempfile(),
+    }
+}
+
+pub(crate) fn dir(prefix: &str) -> io::Result<TempDir> {
+    match KEEP.get() {
+        Some(dir) => builder(prefix, "").tempdir_in(dir),
+        None => builder(prefix, "").tempdir(),
+    }
+}
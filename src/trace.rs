@@ -0,0 +1,265 @@
+//! Traces of every candidate checked during reduction, written with
+//! `--trace` and read by `icemelter replay`
+//!
+//! A trace is a JSON lines file of [`Entry`]s. Entries for interesting
+//! candidates record an [`Edit`] to the previous interesting candidate for the
+//! same file, starting from the original input, so that every interesting
+//! candidate can be rebuilt without running rustc.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::backtrace;
+use crate::tui;
+
+/// File name for the main file, auxiliary crates use their crate name
+pub(crate) const MAIN: &str = "main";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Verdict {
+    /// The input to reduction
+    Start,
+    Interesting,
+    Uninteresting,
+    Timeout,
+    /// The final output, after formatting
+    Output,
+}
+
+/// Replace `start..end` of the previous text with `text`
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct Edit {
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    pub(crate) text: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Entry {
+    pub(crate) step: usize,
+    pub(crate) file: String,
+    pub(crate) pass: String,
+    /// FNV-1a hash of the candidate
+    pub(crate) hash: String,
+    pub(crate) size: usize,
+    pub(crate) verdict: Verdict,
+    pub(crate) millis: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) command: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) edit: Option<Edit>,
+}
+
+#[derive(Debug)]
+struct Trace {
+    out: BufWriter<File>,
+    step: usize,
+    /// Current pass, nothing is recorded outside of a pass
+    pass: Option<String>,
+    /// Last recorded text of each file
+    texts: HashMap<String, Vec<u8>>,
+}
+
+static TRACE: OnceCell<Mutex<Trace>> = OnceCell::new();
+
+/// The smallest edit that turns `old` into `new`
+pub(crate) fn diff(old: &[u8], new: &[u8]) -> Edit {
+    let new_str = String::from_utf8_lossy(new);
+    let mut prefix = old.iter().zip(new).take_while(|(o, n)| o == n).count();
+    let max_suffix = std::cmp::min(old.len(), new.len()) - prefix;
+    let mut suffix = old
+        .iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take(max_suffix)
+        .take_while(|(o, n)| o == n)
+        .count();
+    // Don't split characters
+    while !new_str.is_char_boundary(prefix) {
+        prefix -= 1;
+    }
+    while !new_str.is_char_boundary(new.len() - suffix) {
+        suffix -= 1;
+    }
+    Edit {
+        start: prefix,
+        end: old.len() - suffix,
+        text: new_str[prefix..new.len() - suffix].to_string(),
+    }
+}
+
+pub(crate) fn apply(old: &[u8], edit: &Edit) -> Option<Vec<u8>> {
+    if edit.start > edit.end || edit.end > old.len() {
+        return None;
+    }
+    let mut new = Vec::with_capacity(old.len() - (edit.end - edit.start) + edit.text.len());
+    new.extend_from_slice(&old[..edit.start]);
+    new.extend_from_slice(edit.text.as_bytes());
+    new.extend_from_slice(&old[edit.end..]);
+    Some(new)
+}
+
+/// Start writing a trace to `path`
+pub(crate) fn create(path: &Path) -> Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let _ = TRACE.set(Mutex::new(Trace {
+        out: BufWriter::new(file),
+        step: 0,
+        pass: None,
+        texts: HashMap::new(),
+    }));
+    Ok(())
+}
+
+pub(crate) fn enabled() -> bool {
+    TRACE.get().is_some()
+}
+
+/// Record candidates as part of this pass, or stop recording them
+pub(crate) fn pass(name: Option<&str>) {
+    tui::pass(name);
+    if let Some(t) = TRACE.get() {
+        if let Ok(mut t) = t.lock() {
+            t.pass = name.map(String::from);
+        }
+    }
+}
+
+impl Trace {
+    fn write(
+        &mut self,
+        file: &str,
+        command: Option<Vec<String>>,
+        text: &[u8],
+        verdict: Verdict,
+        duration: Duration,
+    ) -> io::Result<()> {
+        let edit = match verdict {
+            Verdict::Uninteresting | Verdict::Timeout => None,
+            _ => {
+                let old = self.texts.get(file).map(Vec::as_slice).unwrap_or_default();
+                let edit = diff(old, text);
+                self.texts.insert(file.to_string(), text.to_vec());
+                Some(edit)
+            }
+        };
+        self.step += 1;
+        let entry = Entry {
+            step: self.step,
+            file: file.to_string(),
+            pass: self.pass.clone().unwrap_or_default(),
+            hash: backtrace::fnv1a(text),
+            size: text.len(),
+            verdict,
+            millis: duration.as_millis() as u64,
+            command: edit.as_ref().and(command),
+            edit,
+        };
+        serde_json::to_writer(&mut self.out, &entry)?;
+        writeln!(self.out)?;
+        self.out.flush()
+    }
+}
+
+fn with_trace(f: impl FnOnce(&mut Trace) -> io::Result<()>) {
+    let Some(t) = TRACE.get() else {
+        return;
+    };
+    let Ok(mut t) = t.lock() else {
+        return;
+    };
+    if let Err(e) = f(&mut t) {
+        warn!("Failed to write trace: {}", e);
+    }
+}
+
+/// Record the input to reduction, which may differ from the `original` input
+/// (e.g., after inlining modules)
+pub(crate) fn start(file: &str, original: &[u8], text: &[u8]) {
+    with_trace(|t| {
+        t.texts.insert(file.to_string(), original.to_vec());
+        t.pass = Some(String::from("start"));
+        t.write(file, None, text, Verdict::Start, Duration::ZERO)?;
+        t.pass = None;
+        Ok(())
+    })
+}
+
+/// Record a checked candidate, if in a pass
+pub(crate) fn record(
+    file: &str,
+    command: Vec<String>,
+    candidate: &[u8],
+    verdict: Verdict,
+    duration: Duration,
+) {
+    with_trace(|t| {
+        if t.pass.is_none() {
+            return Ok(());
+        }
+        t.write(file, Some(command), candidate, verdict, duration)
+    })
+}
+
+/// Record the final output and stop recording candidates
+pub(crate) fn output(file: &str, text: &[u8]) {
+    with_trace(|t| {
+        t.pass = Some(String::from("output"));
+        t.write(file, None, text, Verdict::Output, Duration::ZERO)?;
+        t.pass = None;
+        Ok(())
+    })
+}
+
+pub(crate) fn read(path: &Path) -> Result<Vec<Entry>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(i, l)| {
+            serde_json::from_str(l)
+                .with_context(|| format!("Bad trace entry on line {} of {}", i + 1, path.display()))
+        })
+        .collect()
+}
+
+/// Apply the edits in `entries` to `texts` (which start as the original
+/// inputs), calling `step` with each rebuilt candidate and the other files
+pub(crate) fn replay(
+    entries: &[Entry],
+    texts: &mut HashMap<String, Vec<u8>>,
+    mut step: impl FnMut(&Entry, &[u8], &HashMap<String, Vec<u8>>) -> Result<()>,
+) -> Result<()> {
+    for entry in entries {
+        let Some(edit) = &entry.edit else {
+            continue;
+        };
+        let Some(old) = texts.get(&entry.file) else {
+            bail!("Missing the original input for {}", entry.file);
+        };
+        let new = match apply(old, edit) {
+            Some(n) if backtrace::fnv1a(&n) == entry.hash => n,
+            _ => bail!(
+                "Step {} doesn't apply to {}, is the original input right?",
+                entry.step,
+                entry.file
+            ),
+        };
+        step(entry, &new, texts)?;
+        texts.insert(entry.file.clone(), new);
+    }
+    Ok(())
+}
@@ -0,0 +1,443 @@
+//! Live reduction progress in the terminal, see `--tui`
+//!
+//! The TUI runs during reduction. Checks report to it with [`record`] and
+//! ask [`before_check`] whether to go ahead, which is how pausing and
+//! skipping work. Without the `tui` feature, these are no-ops.
+
+#[cfg(feature = "tui")]
+use std::collections::VecDeque;
+#[cfg(feature = "tui")]
+use std::io::{self, Write};
+#[cfg(feature = "tui")]
+use std::sync::{Condvar, Mutex};
+#[cfg(feature = "tui")]
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+#[cfg(feature = "tui")]
+use std::time::Instant;
+
+use anyhow::Result;
+#[cfg(feature = "tui")]
+use once_cell::sync::{Lazy, OnceCell};
+#[cfg(feature = "tui")]
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+#[cfg(feature = "tui")]
+use ratatui::layout::{Constraint, Layout};
+#[cfg(feature = "tui")]
+use ratatui::style::{Color, Style};
+#[cfg(feature = "tui")]
+use ratatui::text::{Line, Span};
+#[cfg(feature = "tui")]
+use ratatui::widgets::{Block, List, ListItem, Paragraph};
+#[cfg(feature = "tui")]
+use ratatui::Frame;
+#[cfg(feature = "tui")]
+use regex::Regex;
+
+use crate::trace::Verdict;
+
+/// Number of recent edits and log lines to keep
+#[cfg(feature = "tui")]
+const HISTORY: usize = 200;
+
+/// Checks per second are measured over this window
+#[cfg(feature = "tui")]
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+
+/// How often to redraw (and check for key presses)
+#[cfg(feature = "tui")]
+const TICK: Duration = Duration::from_millis(100);
+
+#[cfg(feature = "tui")]
+struct Edit {
+    file: String,
+    pass: String,
+    size: usize,
+    verdict: Verdict,
+    millis: u128,
+}
+
+#[cfg(feature = "tui")]
+#[derive(Default)]
+struct State {
+    /// Whether the TUI is showing, checks are never held up otherwise
+    active: bool,
+    paused: bool,
+    /// Reject the remaining candidates, so reduction finishes quickly
+    skipping: bool,
+    /// Like skipping, but save the best candidate and exit after reduction
+    aborted: bool,
+    input: String,
+    started: Option<Instant>,
+    pass: Option<String>,
+    checks: usize,
+    accepted: usize,
+    /// When recent checks finished, for checks per second
+    finished: VecDeque<Instant>,
+    edits: VecDeque<Edit>,
+    /// Last interesting candidate, and the file it replaces
+    best: Option<(String, Vec<u8>)>,
+    logs: VecDeque<String>,
+}
+
+#[cfg(feature = "tui")]
+static TUI: OnceCell<(Mutex<State>, Condvar)> = OnceCell::new();
+
+#[cfg(feature = "tui")]
+fn with_state<T>(f: impl FnOnce(&mut State) -> T) -> Option<T> {
+    let (state, _) = TUI.get()?;
+    let mut state = state.lock().ok()?;
+    Some(f(&mut state))
+}
+
+#[cfg(feature = "tui")]
+fn push<T>(deque: &mut VecDeque<T>, item: T) {
+    if deque.len() == HISTORY {
+        deque.pop_front();
+    }
+    deque.push_back(item);
+}
+
+/// Whether candidates should be kept for [`record`]
+#[cfg(feature = "tui")]
+pub(crate) fn enabled() -> bool {
+    with_state(|s| s.active).unwrap_or(false)
+}
+
+#[cfg(not(feature = "tui"))]
+pub(crate) fn enabled() -> bool {
+    false
+}
+
+/// Wait while paused, returns `false` if the check should be skipped
+#[cfg(feature = "tui")]
+pub(crate) fn before_check() -> bool {
+    let Some((state, resumed)) = TUI.get() else {
+        return true;
+    };
+    let Ok(mut s) = state.lock() else {
+        return true;
+    };
+    while s.active && s.paused {
+        s = match resumed.wait(s) {
+            Ok(s) => s,
+            Err(_) => return true,
+        };
+    }
+    !(s.active && s.skipping)
+}
+
+#[cfg(not(feature = "tui"))]
+pub(crate) fn before_check() -> bool {
+    true
+}
+
+/// Record a checked candidate
+#[cfg(feature = "tui")]
+pub(crate) fn record(file: &str, candidate: &[u8], verdict: Verdict, duration: Duration) {
+    with_state(|s| {
+        if !s.active {
+            return;
+        }
+        s.checks += 1;
+        let now = Instant::now();
+        push(&mut s.finished, now);
+        while s
+            .finished
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > RATE_WINDOW)
+        {
+            s.finished.pop_front();
+        }
+        if verdict == Verdict::Interesting {
+            s.accepted += 1;
+            s.best = Some((file.to_string(), candidate.to_vec()));
+        }
+        let edit = Edit {
+            file: file.to_string(),
+            pass: s.pass.clone().unwrap_or_default(),
+            size: candidate.len(),
+            verdict,
+            millis: duration.as_millis(),
+        };
+        push(&mut s.edits, edit);
+    });
+}
+
+#[cfg(not(feature = "tui"))]
+pub(crate) fn record(_file: &str, _candidate: &[u8], _verdict: Verdict, _duration: Duration) {}
+
+/// Show the current pass
+#[cfg(feature = "tui")]
+pub(crate) fn pass(name: Option<&str>) {
+    with_state(|s| s.pass = name.map(String::from));
+}
+
+#[cfg(not(feature = "tui"))]
+pub(crate) fn pass(_name: Option<&str>) {}
+
+/// Whether the user asked to abort and save the best candidate
+#[cfg(feature = "tui")]
+pub(crate) fn aborted() -> bool {
+    with_state(|s| s.aborted).unwrap_or(false)
+}
+
+#[cfg(not(feature = "tui"))]
+pub(crate) fn aborted() -> bool {
+    false
+}
+
+/// Log lines go to the TUI while it's showing, see [`Tui::start`]
+#[cfg(feature = "tui")]
+#[derive(Default)]
+pub(crate) struct LogWriter {
+    buf: Vec<u8>,
+}
+
+#[cfg(feature = "tui")]
+static ANSI: Lazy<Regex> = Lazy::new(|| Regex::new(r"\x1b\[[0-9;]*m").unwrap());
+
+#[cfg(feature = "tui")]
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tui")]
+impl Drop for LogWriter {
+    fn drop(&mut self) {
+        let text = String::from_utf8_lossy(&self.buf);
+        let shown = with_state(|s| {
+            if !s.active {
+                return false;
+            }
+            for line in text.lines() {
+                push(&mut s.logs, ANSI.replace_all(line, "").into_owned());
+            }
+            true
+        });
+        if shown != Some(true) {
+            let _ = io::stderr().write_all(&self.buf);
+        }
+    }
+}
+
+/// Shows progress until dropped
+#[cfg(feature = "tui")]
+pub(crate) struct Tui {
+    thread: Option<JoinHandle<()>>,
+}
+
+#[cfg(not(feature = "tui"))]
+pub(crate) struct Tui;
+
+#[cfg(feature = "tui")]
+impl Tui {
+    pub(crate) fn start(input: &str) -> Result<Self> {
+        let mut terminal = ratatui::try_init()?;
+        let (state, _) = TUI.get_or_init(|| (Mutex::new(State::default()), Condvar::new()));
+        if let Ok(mut s) = state.lock() {
+            *s = State {
+                active: true,
+                input: input.to_string(),
+                started: Some(Instant::now()),
+                ..State::default()
+            };
+        }
+        let thread = thread::spawn(move || {
+            while enabled() {
+                if let Some(Some(Err(e))) =
+                    with_state(|s| s.active.then(|| terminal.draw(|f| draw(f, s)).map(|_| ())))
+                {
+                    with_state(|s| push(&mut s.logs, format!("Failed to draw: {e}")));
+                }
+                if event::poll(TICK).unwrap_or(false) {
+                    if let Ok(Event::Key(key)) = event::read() {
+                        if key.kind == KeyEventKind::Press {
+                            let ctrl_c = key.modifiers.contains(KeyModifiers::CONTROL)
+                                && key.code == KeyCode::Char('c');
+                            handle(if ctrl_c { KeyCode::Char('q') } else { key.code });
+                        }
+                    }
+                }
+            }
+            ratatui::restore();
+        });
+        Ok(Tui {
+            thread: Some(thread),
+        })
+    }
+
+    /// Restore the terminal, same as dropping
+    pub(crate) fn stop(self) {}
+}
+
+#[cfg(not(feature = "tui"))]
+impl Tui {
+    pub(crate) fn start(_input: &str) -> Result<Self> {
+        Err(anyhow::anyhow!(
+            "Icemelter was built without the `tui` feature, can't show --tui"
+        ))
+    }
+
+    pub(crate) fn stop(self) {}
+}
+
+#[cfg(feature = "tui")]
+impl Drop for Tui {
+    fn drop(&mut self) {
+        let Some((state, resumed)) = TUI.get() else {
+            return;
+        };
+        let logs = match state.lock() {
+            Ok(mut s) => {
+                s.active = false;
+                s.paused = false;
+                std::mem::take(&mut s.logs)
+            }
+            Err(_) => VecDeque::new(),
+        };
+        resumed.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        // So they end up in the scrollback
+        for line in logs {
+            eprintln!("{line}");
+        }
+    }
+}
+
+#[cfg(feature = "tui")]
+fn handle(key: KeyCode) {
+    let Some((state, resumed)) = TUI.get() else {
+        return;
+    };
+    let Ok(mut s) = state.lock() else {
+        return;
+    };
+    match key {
+        KeyCode::Char('p') | KeyCode::Char(' ') => s.paused = !s.paused,
+        KeyCode::Char('s') => {
+            s.skipping = true;
+            s.paused = false;
+        }
+        KeyCode::Char('q') | KeyCode::Esc => {
+            s.skipping = true;
+            s.aborted = true;
+            s.paused = false;
+        }
+        _ => return,
+    }
+    let status = if s.aborted {
+        "Aborting, the best candidate will be saved"
+    } else if s.skipping {
+        "Skipping to formatting"
+    } else if s.paused {
+        "Paused"
+    } else {
+        "Resumed"
+    };
+    push(&mut s.logs, status.to_string());
+    resumed.notify_all();
+}
+
+#[cfg(feature = "tui")]
+fn draw(frame: &mut Frame, s: &State) {
+    let [header, body, footer] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(0),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [best, side] =
+        Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(body);
+    let [edits, logs] =
+        Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(side);
+
+    let elapsed = s.started.map(|t| t.elapsed()).unwrap_or_default();
+    let rate = s.finished.len() as f64 / RATE_WINDOW.min(elapsed).as_secs_f64().max(1.0);
+    let status = if s.aborted {
+        "aborting"
+    } else if s.skipping {
+        "skipping to formatting"
+    } else if s.paused {
+        "paused"
+    } else {
+        "reducing"
+    };
+    let summary = format!(
+        "Step 3: Reducing, pass {} ({}) | {}s | {} checks, {} accepted | {:.1} checks/s",
+        s.pass.as_deref().unwrap_or("-"),
+        status,
+        elapsed.as_secs(),
+        s.checks,
+        s.accepted,
+        rate
+    );
+    frame.render_widget(
+        Paragraph::new(summary).block(Block::bordered().title(format!(" icemelter: {} ", s.input))),
+        header,
+    );
+
+    let (title, text) = match &s.best {
+        Some((file, code)) => (
+            format!(" Best candidate: {} ({} bytes) ", file, code.len()),
+            String::from_utf8_lossy(code).into_owned(),
+        ),
+        None => (String::from(" Best candidate "), String::new()),
+    };
+    frame.render_widget(
+        Paragraph::new(text).block(Block::bordered().title(title)),
+        best,
+    );
+
+    let height = usize::from(edits.height.saturating_sub(2));
+    let items = s
+        .edits
+        .iter()
+        .rev()
+        .take(height)
+        .map(|e| {
+            let (mark, color) = match e.verdict {
+                Verdict::Interesting => ("✓", Color::Green),
+                Verdict::Timeout => ("⧗", Color::Yellow),
+                _ => ("✗", Color::Red),
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(mark, Style::default().fg(color)),
+                Span::raw(format!(
+                    " {} {}: {} bytes, {}ms",
+                    e.pass, e.file, e.size, e.millis
+                )),
+            ]))
+        })
+        .collect::<Vec<_>>();
+    frame.render_widget(
+        List::new(items).block(Block::bordered().title(" Recent edits ")),
+        edits,
+    );
+
+    let height = usize::from(logs.height.saturating_sub(2));
+    let lines = s
+        .logs
+        .iter()
+        .skip(s.logs.len().saturating_sub(height))
+        .map(|l| Line::from(l.as_str()))
+        .collect::<Vec<_>>();
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(" Log ")),
+        logs,
+    );
+
+    frame.render_widget(
+        Paragraph::new("p: pause/resume  s: skip to formatting  q: abort and save"),
+        footer,
+    );
+}
@@ -0,0 +1,118 @@
+//! Replacing the running binary with the latest release, see
+//! `icemelter self-update`
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::os::unix::prelude::PermissionsExt;
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use tracing::{debug, info};
+
+use crate::github;
+use crate::http::CLIENT;
+
+const REPO: &str = "langston-barrett/icemelter";
+
+/// The target of the prebuilt binaries this one can be replaced with, see
+/// `.github/workflows/release.yml`
+const TARGET: Option<&str> = if cfg!(all(
+    target_arch = "x86_64",
+    target_os = "linux",
+    target_env = "gnu"
+)) {
+    Some("x86_64-unknown-linux-gnu")
+} else if cfg!(all(
+    target_arch = "x86_64",
+    target_os = "linux",
+    target_env = "musl"
+)) {
+    Some("x86_64-unknown-linux-musl")
+} else if cfg!(all(target_arch = "x86_64", target_os = "macos")) {
+    Some("x86_64-apple-darwin")
+} else {
+    None
+};
+
+/// `v1.2.3` or `1.2.3` to `[1, 2, 3]`
+fn parse_version(v: &str) -> Option<Vec<u64>> {
+    v.trim_start_matches('v')
+        .split('.')
+        .map(|n| n.parse().ok())
+        .collect()
+}
+
+fn download(url: &str) -> Result<Vec<u8>> {
+    debug!("Downloading {}", url);
+    let bytes = CLIENT
+        .get(url)
+        .send()
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.bytes())
+        .with_context(|| format!("Failed to download {url}"))?;
+    Ok(bytes.to_vec())
+}
+
+/// Check for a newer release and (unless `check_only`) replace the running
+/// binary with it, after verifying its SHA-256 checksum
+pub(crate) fn self_update(check_only: bool) -> Result<()> {
+    let config = github::Config::load().ok();
+    let release = github::get_latest_release(config.as_ref(), REPO)
+        .context("Failed to find the latest release")?;
+    let current = env!("CARGO_PKG_VERSION");
+    let latest = parse_version(&release.tag_name)
+        .with_context(|| format!("Unexpected release tag {}", release.tag_name))?;
+    if parse_version(current) >= Some(latest) {
+        info!("Already up to date (v{})", current);
+        return Ok(());
+    }
+    info!(
+        "Icemelter {} is available (this is v{})",
+        release.tag_name, current
+    );
+    if check_only {
+        return Ok(());
+    }
+    let target = TARGET.context("There are no prebuilt binaries for this platform")?;
+    let name = format!("icemelter_{target}");
+    let url = |name: &str| {
+        release
+            .assets
+            .iter()
+            .find(|a| a.name == name)
+            .map(|a| a.browser_download_url.clone())
+            .with_context(|| format!("Release {} has no {}", release.tag_name, name))
+    };
+    let binary = download(&url(&name)?)?;
+    let checksum = String::from_utf8(download(&url(&format!("{name}.sha256"))?)?)
+        .context("Malformed checksum file")?;
+    let expected = checksum
+        .split_whitespace()
+        .next()
+        .context("Empty checksum file")?
+        .to_lowercase();
+    let actual = Sha256::digest(&binary)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>();
+    if actual != expected {
+        anyhow::bail!("Checksum mismatch for {name}: expected {expected}, got {actual}");
+    }
+    let exe = env::current_exe().context("Couldn't find the icemelter executable")?;
+    let exe = exe.canonicalize().unwrap_or(exe);
+    let dir = exe.parent().context("Executable has no parent directory")?;
+    // Write next to the executable and rename, so it's never left truncated
+    let mut tmp = tempfile::Builder::new()
+        .prefix(".icemelter-")
+        .tempfile_in(dir)
+        .with_context(|| format!("Failed to write to {}", dir.display()))?;
+    tmp.write_all(&binary)?;
+    tmp.as_file()
+        .set_permissions(fs::Permissions::from_mode(0o755))?;
+    tmp.as_file().sync_all()?;
+    tmp.persist(&exe)
+        .with_context(|| format!("Failed to replace {}", exe.display()))?;
+    info!("Updated {} to {}", exe.display(), release.tag_name);
+    Ok(())
+}
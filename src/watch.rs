@@ -0,0 +1,132 @@
+//! Melting crashing inputs as they appear in a directory, see
+//! `icemelter watch-dir`
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use tracing::{info, warn};
+
+use crate::intake;
+use crate::serve::Dashboard;
+
+/// Subdirectories for inputs that were melted, and those that weren't
+const DONE: &str = "done";
+const FAILED: &str = "failed";
+
+/// Hidden, so it isn't mistaken for an input
+const WORK: &str = ".icemelter";
+
+/// `dir/name`, or `dir/name.N` for the first `N` that doesn't exist yet
+fn unused(dir: &Path, name: &str) -> PathBuf {
+    let mut path = dir.join(name);
+    let mut n = 1;
+    while path.exists() {
+        path = dir.join(format!("{name}.{n}"));
+        n += 1;
+    }
+    path
+}
+
+/// Melt `input`, moving it and the results into `done/NAME` or
+/// `failed/NAME`, returning whether it succeeded
+fn melt(
+    exe: &Path,
+    dir: &Path,
+    input: &Path,
+    timeout: Duration,
+    check: &[String],
+    dashboard: &Dashboard,
+) -> Result<bool> {
+    let name = input
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let work = dir.join(WORK).join(&name);
+    // Leftovers from an interrupted run
+    let _ = fs::remove_dir_all(&work);
+    fs::create_dir_all(&work)
+        .with_context(|| format!("Failed to create directory {}", work.display()))?;
+    info!("Melting {}...", input.display());
+    dashboard.start(&name);
+    let status = Command::new(exe)
+        .arg("--markdown")
+        .arg("--timeout")
+        .arg(timeout.as_millis().to_string())
+        .arg("--output")
+        .arg(work.join("melted.rs"))
+        .arg(input)
+        .arg("--")
+        .args(check)
+        .status()
+        .context("Failed to run icemelter")?;
+    let dest = unused(
+        &dir.join(if status.success() { DONE } else { FAILED }),
+        &name,
+    );
+    fs::rename(&work, &dest)
+        .with_context(|| format!("Failed to move results to {}", dest.display()))?;
+    let moved = unused(&dest, &name);
+    fs::rename(input, &moved)
+        .with_context(|| format!("Failed to move {} to {}", input.display(), moved.display()))?;
+    if status.success() {
+        info!("Melted {}: {}", name, dest.display());
+        let melted = dest.join("melted.rs");
+        dashboard.finish(Some(melted.clone()), Some(melted.with_extension("md")));
+    } else {
+        warn!("Failed to melt {}: {}", name, dest.display());
+        dashboard.finish(None, None);
+    }
+    Ok(status.success())
+}
+
+/// Melt each new file in `dir`, checking every `interval`, forever
+pub(crate) fn watch(
+    dir: &Path,
+    interval: Duration,
+    timeout: Duration,
+    check: &[String],
+    dashboard: &Dashboard,
+) -> Result<()> {
+    for sub in [DONE, FAILED] {
+        let sub = dir.join(sub);
+        fs::create_dir_all(&sub)
+            .with_context(|| format!("Failed to create directory {}", sub.display()))?;
+    }
+    let exe = std::env::current_exe().context("Couldn't find the icemelter executable")?;
+    // Sizes at the last check, inputs are only melted once they stop growing
+    let mut sizes: HashMap<PathBuf, u64> = HashMap::new();
+    let (mut melted, mut failed) = (0, 0);
+    info!("Watching {} for new inputs...", dir.display());
+    loop {
+        let inputs = intake::inputs(dir)?;
+        sizes.retain(|p, _| inputs.contains(p));
+        let mut ready = Vec::new();
+        for input in inputs {
+            let Ok(meta) = fs::metadata(&input) else {
+                continue;
+            };
+            if sizes.insert(input.clone(), meta.len()) != Some(meta.len()) {
+                continue;
+            }
+            sizes.remove(&input);
+            if let Some(name) = input.file_name() {
+                dashboard.queue(&name.to_string_lossy());
+            }
+            ready.push(input);
+        }
+        for input in ready {
+            if melt(&exe, dir, &input, timeout, check, dashboard)? {
+                melted += 1;
+            } else {
+                failed += 1;
+            }
+            info!("{} melted, {} failed so far", melted, failed);
+        }
+        thread::sleep(interval);
+    }
+}
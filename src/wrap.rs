@@ -0,0 +1,208 @@
+//! Capturing rustc invocations that ICE during a real build, see
+//! `icemelter wrap`
+//!
+//! The build is run with `RUSTC_WRAPPER` set to icemelter itself and
+//! [`DIR_VAR`] set to a directory. Cargo then runs `icemelter RUSTC ARGS...`
+//! for each crate, which runs rustc as usual and records invocations that
+//! ICE. Once the build is done, each recorded invocation is melted.
+
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+/// Where invocations are recorded, only set for the wrapped build
+pub(crate) const DIR_VAR: &str = "ICEMELTER_WRAP_DIR";
+
+const INVOCATION: &str = "invocation.json";
+
+/// rustc exits with this code on an ICE
+const ICE_EXIT_CODE: i32 = 101;
+
+/// Environment variables passed on to melting, e.g., for `env!`
+const ENV_PREFIXES: &[&str] = &["CARGO_", "RUSTC_BOOTSTRAP", "OUT_DIR"];
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Invocation {
+    rustc: String,
+    /// Arguments, with the input replaced by `@@.rs`
+    args: Vec<String>,
+    cwd: PathBuf,
+    input: PathBuf,
+    env: Vec<(String, String)>,
+}
+
+/// The crate root, as passed by Cargo
+fn input_index(args: &[String], cwd: &Path) -> Option<usize> {
+    args.iter()
+        .position(|a| !a.starts_with('-') && a.ends_with(".rs") && cwd.join(a).is_file())
+}
+
+/// Arguments that only make sense within Cargo
+fn melt_args(args: &[String]) -> Vec<String> {
+    let mut out = Vec::with_capacity(args.len());
+    let mut iter = args.iter().peekable();
+    while let Some(arg) = iter.next() {
+        if arg.starts_with("--error-format")
+            || arg.starts_with("--json")
+            || arg.starts_with("--diagnostic-width")
+            || arg.starts_with("-Cincremental=")
+        {
+            continue;
+        }
+        if arg == "-C" && iter.peek().is_some_and(|a| a.starts_with("incremental=")) {
+            iter.next();
+            continue;
+        }
+        out.push(arg.clone());
+    }
+    out
+}
+
+fn record(dir: &Path, rustc: &str, args: &[String]) -> Result<()> {
+    let cwd = std::env::current_dir().context("Couldn't get the working directory")?;
+    let Some(idx) = input_index(args, &cwd) else {
+        bail!("Couldn't find the input in the rustc invocation");
+    };
+    let mut melted = melt_args(args);
+    let input = cwd.join(&args[idx]);
+    if let Some(i) = melted.iter().position(|a| *a == args[idx]) {
+        melted[i] = String::from("@@.rs");
+    }
+    let name = args
+        .iter()
+        .position(|a| a == "--crate-name")
+        .and_then(|i| args.get(i + 1))
+        .cloned()
+        .unwrap_or_else(|| String::from("crate"));
+    let mut out = dir.join(&name);
+    let mut n = 1;
+    while fs::create_dir(&out).is_err() {
+        if n > 1000 {
+            bail!("Failed to create a directory in {}", dir.display());
+        }
+        out = dir.join(format!("{name}.{n}"));
+        n += 1;
+    }
+    let invocation = Invocation {
+        rustc: rustc.to_string(),
+        args: melted,
+        cwd,
+        input,
+        env: std::env::vars()
+            .filter(|(k, _)| ENV_PREFIXES.iter().any(|p| k.starts_with(p)))
+            .collect(),
+    };
+    let path = out.join(INVOCATION);
+    fs::write(&path, serde_json::to_vec_pretty(&invocation)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Run rustc as `RUSTC_WRAPPER`, recording the invocation in `dir` if it
+/// ICEs, returning its exit code
+pub(crate) fn intercept(dir: OsString, mut args: impl Iterator<Item = String>) -> i32 {
+    let Some(rustc) = args.next() else {
+        eprintln!("icemelter: expected a rustc command line from Cargo");
+        return 1;
+    };
+    let args: Vec<_> = args.collect();
+    let out = match Command::new(&rustc)
+        .args(&args)
+        .stderr(Stdio::piped())
+        .output()
+    {
+        Ok(out) => out,
+        Err(e) => {
+            eprintln!("icemelter: failed to run {rustc}: {e}");
+            return 1;
+        }
+    };
+    let _ = io::stdout().write_all(&out.stdout);
+    let _ = io::stderr().write_all(&out.stderr);
+    let code = out.status.code().unwrap_or(1);
+    // Cargo probes rustc (e.g., `rustc -vV`) through the wrapper, too
+    if code == ICE_EXIT_CODE {
+        if let Err(e) = record(Path::new(&dir), &rustc, &args) {
+            eprintln!("icemelter: failed to record the invocation: {e:#}");
+        }
+    }
+    code
+}
+
+/// Melt a recorded invocation, returning whether it succeeded
+fn melt(exe: &Path, dir: &Path, timeout: Duration) -> Result<bool> {
+    let path = dir.join(INVOCATION);
+    let invocation: Invocation = serde_json::from_slice(
+        &fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?,
+    )
+    .with_context(|| format!("Bad invocation in {}", path.display()))?;
+    info!("Melting {}...", invocation.input.display());
+    let mut cmd = Command::new(exe);
+    cmd.current_dir(&invocation.cwd)
+        .arg("--markdown")
+        .arg("--timeout")
+        .arg(timeout.as_millis().to_string())
+        .arg("--output")
+        .arg(dir.join("melted.rs"));
+    for (k, v) in &invocation.env {
+        cmd.arg("--env").arg(format!("{k}={v}"));
+    }
+    let status = cmd
+        .arg(&invocation.input)
+        .arg("--")
+        .arg(&invocation.rustc)
+        .args(&invocation.args)
+        .status()
+        .context("Failed to run icemelter")?;
+    Ok(status.success())
+}
+
+/// Run the build `cmd`, then melt each rustc invocation that ICEd
+pub(crate) fn wrap(output: &Path, timeout: Duration, cmd: &[String]) -> Result<()> {
+    fs::create_dir_all(output)
+        .with_context(|| format!("Failed to create directory {}", output.display()))?;
+    let output = output
+        .canonicalize()
+        .with_context(|| format!("Failed to canonicalize {}", output.display()))?;
+    let exe = std::env::current_exe().context("Couldn't find the icemelter executable")?;
+    info!("Running {}...", cmd.join(" "));
+    let status = Command::new(&cmd[0])
+        .args(&cmd[1..])
+        .env("RUSTC_WRAPPER", &exe)
+        .env(DIR_VAR, &output)
+        .status()
+        .with_context(|| format!("Failed to run {}", cmd[0]))?;
+    let mut dirs = fs::read_dir(&output)
+        .with_context(|| format!("Failed to read directory {}", output.display()))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.join(INVOCATION).is_file())
+        .collect::<Vec<_>>();
+    dirs.sort();
+    if dirs.is_empty() {
+        if status.success() {
+            info!("The build succeeded, nothing to melt");
+        } else {
+            warn!("The build failed, but rustc didn't ICE");
+        }
+        return Ok(());
+    }
+    info!("Captured {} ICE(s)", dirs.len());
+    let (mut melted, mut failed) = (0, 0);
+    for dir in dirs {
+        if melt(&exe, &dir, timeout)? {
+            info!("Melted: {}", dir.display());
+            melted += 1;
+        } else {
+            warn!("Failed to melt: {}", dir.display());
+            failed += 1;
+        }
+    }
+    info!("{} melted, {} failed", melted, failed);
+    Ok(())
+}
@@ -0,0 +1,46 @@
+use std::env::{var, VarError};
+
+use crate::http::CLIENT;
+
+/// Zulip credentials, see <https://zulip.com/api/api-keys>
+pub(crate) struct Config {
+    site: String,
+    email: String,
+    api_key: String,
+}
+
+impl Config {
+    pub(crate) const SITE_VAR: &str = "ZULIP_SITE";
+    pub(crate) const EMAIL_VAR: &str = "ZULIP_EMAIL";
+    pub(crate) const API_KEY_VAR: &str = "ZULIP_API_KEY";
+
+    pub(crate) fn from_env() -> Result<Self, VarError> {
+        Ok(Self {
+            site: var(Self::SITE_VAR)
+                .unwrap_or_else(|_| String::from("https://rust-lang.zulipchat.com")),
+            email: var(Self::EMAIL_VAR)?,
+            api_key: var(Self::API_KEY_VAR)?,
+        })
+    }
+}
+
+pub(crate) fn post(
+    config: &Config,
+    stream: &str,
+    topic: &str,
+    content: &str,
+) -> Result<(), reqwest::Error> {
+    let url = format!("{}/api/v1/messages", config.site.trim_end_matches('/'));
+    CLIENT
+        .post(url)
+        .basic_auth(&config.email, Some(&config.api_key))
+        .form(&[
+            ("type", "stream"),
+            ("to", stream),
+            ("topic", topic),
+            ("content", content),
+        ])
+        .send()?
+        .error_for_status()?;
+    Ok(())
+}